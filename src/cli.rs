@@ -0,0 +1,158 @@
+//! # Cli
+//!
+//! Command line arguments and the maintenance subcommands
+
+use crate::irina::{Automatizer, ChatsFormat, Maintenance};
+
+use argh::FromArgs;
+use std::path::PathBuf;
+use teloxide::types::ChatId;
+
+#[derive(FromArgs)]
+/// Spazio grigio telegram bot
+pub struct Args {
+    /// path of the TOML configuration file; overrides CONFIG_FILE
+    #[argh(option, short = 'c')]
+    pub config: Option<PathBuf>,
+    /// validate the configuration and exit
+    #[argh(switch)]
+    pub check_config: bool,
+    #[argh(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum Command {
+    Run(RunArgs),
+    Migrate(MigrateArgs),
+    ListChats(ListChatsArgs),
+    ExportChats(ExportChatsArgs),
+    ImportChats(ImportChatsArgs),
+    RunJob(RunJobArgs),
+    SendTest(SendTestArgs),
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "run")]
+/// Run the bot (default)
+pub struct RunArgs {}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "migrate")]
+/// Create the missing database tables and migrate the chats
+pub struct MigrateArgs {}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "list-chats")]
+/// List the subscribed chats
+pub struct ListChatsArgs {}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "export-chats")]
+/// Export the subscribed chats, with their follows and opt-outs
+pub struct ExportChatsArgs {
+    /// export format: json or csv (default json)
+    #[argh(option, short = 'f', default = "ChatsFormat::Json")]
+    format: ChatsFormat,
+    /// file to write; stdout if unset
+    #[argh(option, short = 'o')]
+    output: Option<PathBuf>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "import-chats")]
+/// Import the chats exported with export-chats; already subscribed chats are skipped
+pub struct ImportChatsArgs {
+    /// import format: json or csv (default json)
+    #[argh(option, short = 'f', default = "ChatsFormat::Json")]
+    format: ChatsFormat,
+    /// file to read
+    #[argh(positional)]
+    input: PathBuf,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "run-job")]
+/// Run an automatizer job once
+pub struct RunJobArgs {
+    /// job name (good_morning, newsletter, instagram, video, feed, email_digest)
+    #[argh(positional)]
+    job: String,
+    /// print the announcements without sending them or advancing the watermarks
+    #[argh(switch)]
+    dry_run: bool,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "send-test")]
+/// Send a sample announcement to a chat
+pub struct SendTestArgs {
+    /// id of the chat to send the announcement to
+    #[argh(positional)]
+    chat_id: i64,
+}
+
+impl Command {
+    /// Execute maintenance command. `Run` is handled by main, since it starts the bot
+    pub async fn execute(self) -> anyhow::Result<()> {
+        match self {
+            Self::Run(_) => Ok(()),
+            Self::Migrate(_) => {
+                Maintenance::migrate().await?;
+                println!("database is up to date");
+                Ok(())
+            }
+            Self::ListChats(_) => {
+                for chat in Maintenance::list_chats().await? {
+                    println!(
+                        "{}\tsubscribed at {}\tfollows: {}\topt-outs: {}",
+                        chat.chat_id,
+                        chat.created_at,
+                        chat.creators.join(", "),
+                        chat.opt_outs.join(", ")
+                    );
+                }
+                Ok(())
+            }
+            Self::ExportChats(args) => {
+                let data = Maintenance::export_chats(args.format).await?;
+                match args.output {
+                    Some(output) => std::fs::write(&output, data).map_err(|e| {
+                        anyhow::anyhow!("could not write {}: {}", output.display(), e)
+                    }),
+                    None => {
+                        print!("{}", data);
+                        Ok(())
+                    }
+                }
+            }
+            Self::ImportChats(args) => {
+                let data = std::fs::read_to_string(&args.input).map_err(|e| {
+                    anyhow::anyhow!("could not read {}: {}", args.input.display(), e)
+                })?;
+                let (imported, skipped) = Maintenance::import_chats(args.format, &data).await?;
+                println!(
+                    "imported {} chats; skipped {} already subscribed",
+                    imported, skipped
+                );
+                Ok(())
+            }
+            Self::RunJob(args) => {
+                Automatizer::set_dry_run(args.dry_run);
+                let delivered = Maintenance::run_job(&args.job).await?;
+                if args.dry_run {
+                    println!("{}_job would deliver {} messages", args.job, delivered);
+                } else {
+                    println!("{}_job delivered {} messages", args.job, delivered);
+                }
+                Ok(())
+            }
+            Self::SendTest(args) => {
+                Maintenance::send_test(ChatId(args.chat_id)).await?;
+                println!("test message sent to {}", args.chat_id);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! # Client
+//!
+//! A client for RSS/Atom feeds
+
+use super::{Feed, FeedError, FeedResult};
+
+use feed_rs::parser as feed_parser;
+use once_cell::sync::Lazy;
+use reqwest::header::{HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, Response, StatusCode};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+/// Shared feed clients by url. Clients are kept to perform conditional requests
+static CLIENTS: Lazy<Mutex<HashMap<String, Arc<FeedClient>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// RSS/Atom feed client.
+///
+/// The client keeps the last fetched feed along with its `ETag` and `Last-Modified` headers,
+/// so that the following requests are conditional and the feed is parsed only if it has changed.
+pub struct FeedClient {
+    url: String,
+    client: Client,
+    cache: Mutex<Option<CachedFeed>>,
+}
+
+/// The last feed fetched by the client
+struct CachedFeed {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    feed: Feed,
+}
+
+/// The response to a feed request
+pub(crate) enum FeedResponse {
+    /// The feed hasn't changed since the last request
+    NotModified,
+    /// The feed has changed
+    Modified {
+        body: String,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+}
+
+impl FeedClient {
+    /// Instantiate a new `FeedClient` for the feed at `url`
+    pub fn new(url: impl ToString) -> Self {
+        Self {
+            url: url.to_string(),
+            client: Client::new(),
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Get the shared client for the feed at `url`, so that its cache outlives the caller
+    pub fn shared(url: impl AsRef<str>) -> Arc<Self> {
+        CLIENTS
+            .lock()
+            .unwrap()
+            .entry(url.as_ref().to_string())
+            .or_insert_with(|| Arc::new(Self::new(url.as_ref())))
+            .clone()
+    }
+
+    /// Get the url of the feed
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Fetch feed
+    pub async fn fetch(&self) -> FeedResult<Feed> {
+        match self.fetch_feed().await? {
+            FeedResponse::NotModified => {
+                debug!("feed at {} has not been modified", self.url);
+                match self.cache.lock().unwrap().as_ref() {
+                    Some(cached) => Ok(cached.feed.clone()),
+                    None => Err(FeedError::HttpStatus(StatusCode::NOT_MODIFIED)),
+                }
+            }
+            FeedResponse::Modified {
+                body,
+                etag,
+                last_modified,
+            } => {
+                trace!("Got body {}", body);
+                let feed = self.parse_feed(body)?;
+                *self.cache.lock().unwrap() = Some(CachedFeed {
+                    etag,
+                    last_modified,
+                    feed: feed.clone(),
+                });
+                Ok(feed)
+            }
+        }
+    }
+
+    /// Fetch feed
+    pub(crate) async fn fetch_feed(&self) -> FeedResult<FeedResponse> {
+        debug!("fetching feed {}", self.url);
+        let mut request = self.client.get(&self.url);
+        if let Some(cached) = self.cache.lock().unwrap().as_ref() {
+            if let Some(etag) = cached.etag.as_deref() {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = cached.last_modified.as_deref() {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = request.send().await.map_err(FeedError::from)?;
+        match response.status() {
+            StatusCode::NOT_MODIFIED => return Ok(FeedResponse::NotModified),
+            status if !status.is_success() => return Err(FeedError::HttpStatus(status)),
+            _ => {}
+        }
+        let etag = Self::header(&response, ETAG);
+        let last_modified = Self::header(&response, LAST_MODIFIED);
+        let body = response.text().await.map_err(FeedError::from)?;
+        Ok(FeedResponse::Modified {
+            body,
+            etag,
+            last_modified,
+        })
+    }
+
+    /// Get header value from response
+    fn header(response: &Response, name: HeaderName) -> Option<String> {
+        response
+            .headers()
+            .get(name)
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.to_string())
+    }
+
+    /// Parse feed
+    fn parse_feed(&self, feed: String) -> FeedResult<Feed> {
+        debug!("parsing feed");
+        let reader = Cursor::new(feed);
+        feed_parser::parse(reader)
+            .map(Feed::from)
+            .map_err(FeedError::from)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use wiremock::matchers::{header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn should_fetch_rss_feed() {
+        let server = mock_server(ResponseTemplate::new(200).set_body_string(PODCAST)).await;
+        let client = FeedClient::new(format!("{}/podcast.xml", server.uri()));
+        let feed = client.fetch().await.unwrap();
+        let entries: Vec<_> = feed.entries().collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0].title.as_deref(),
+            Some("Episodio 2: il minimalismo digitale")
+        );
+        assert!(entries[0].date.is_some());
+    }
+
+    #[tokio::test]
+    async fn should_report_http_errors() {
+        let server = mock_server(ResponseTemplate::new(500).set_body_string("error")).await;
+        let client = FeedClient::new(format!("{}/podcast.xml", server.uri()));
+        assert!(matches!(
+            client.fetch().await.unwrap_err(),
+            FeedError::HttpStatus(StatusCode::INTERNAL_SERVER_ERROR)
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_fail_parsing_malformed_feed() {
+        let server = mock_server(ResponseTemplate::new(200).set_body_string("<rss>")).await;
+        let client = FeedClient::new(format!("{}/podcast.xml", server.uri()));
+        assert!(matches!(
+            client.fetch().await.unwrap_err(),
+            FeedError::FeedParserError(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_use_cached_feed_if_not_modified() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/podcast.xml"))
+            .and(header("If-None-Match", "\"v1\""))
+            .and(header_exists("If-Modified-Since"))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/podcast.xml"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .insert_header("Last-Modified", "Sat, 10 Sep 2022 16:00:00 GMT")
+                    .set_body_string(PODCAST),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = FeedClient::new(format!("{}/podcast.xml", server.uri()));
+        let feed = client.fetch().await.unwrap();
+        assert!(matches!(
+            client.fetch_feed().await.unwrap(),
+            FeedResponse::NotModified
+        ));
+        assert_eq!(client.fetch().await.unwrap(), feed);
+    }
+
+    #[test]
+    fn should_share_clients_by_url() {
+        let client = FeedClient::shared("http://localhost/shared.xml");
+        assert!(Arc::ptr_eq(
+            &client,
+            &FeedClient::shared("http://localhost/shared.xml")
+        ));
+        assert!(!Arc::ptr_eq(
+            &client,
+            &FeedClient::shared("http://localhost/other.xml")
+        ));
+        assert_eq!(client.url(), "http://localhost/shared.xml");
+    }
+
+    const PODCAST: &str = include_str!("../../tests/fixtures/feed/podcast.xml");
+
+    /// Start a mock server which serves the feed at `/podcast.xml` with `response`
+    async fn mock_server(response: ResponseTemplate) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/podcast.xml"))
+            .respond_with(response)
+            .mount(&server)
+            .await;
+        server
+    }
+}
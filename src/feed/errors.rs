@@ -0,0 +1,32 @@
+//! # Errors
+//!
+//! Feed client errors
+
+use feed_rs::parser::ParseFeedError;
+use reqwest::StatusCode;
+use thiserror::Error;
+
+pub type FeedResult<T> = Result<T, FeedError>;
+
+/// An error returned by the Feed Client
+#[derive(Debug, Error)]
+pub enum FeedError {
+    #[error("HTTP error: {0}")]
+    HttpError(reqwest::Error),
+    #[error("HTTP request failed with status {0}")]
+    HttpStatus(StatusCode),
+    #[error("Feed parser error: {0}")]
+    FeedParserError(ParseFeedError),
+}
+
+impl From<reqwest::Error> for FeedError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::HttpError(e)
+    }
+}
+
+impl From<ParseFeedError> for FeedError {
+    fn from(e: ParseFeedError) -> Self {
+        Self::FeedParserError(e)
+    }
+}
@@ -2,11 +2,20 @@
 //!
 //! This module exposes the types for the feed
 
+mod client;
+mod errors;
+
+pub use client::FeedClient;
+#[cfg(test)]
+pub(crate) use client::FeedResponse;
+pub use errors::{FeedError, FeedResult};
+
 use crate::utils::str as str_helpers;
 
 use chrono::{DateTime, Utc};
 use feed_rs::model::{Entry as RssEntry, Feed as RssFeed};
 use std::slice::Iter;
+use std::time::Duration;
 
 /// Contains, for a feed source, the list of entries fetched from remote
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -17,11 +26,31 @@ pub struct Feed {
 /// identifies a single article in the feed
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Entry {
+    pub id: String,
     pub title: Option<String>,
     pub authors: Vec<String>,
     pub summary: String,
     pub url: String,
     pub date: Option<DateTime<Utc>>,
+    /// Url of the first thumbnail in the entry media
+    pub thumbnail: Option<String>,
+    /// Description of the entry media
+    pub description: Option<String>,
+    /// Views count of the entry media
+    pub views: Option<u64>,
+    /// Audio file attached to the entry (e.g. a podcast episode)
+    pub enclosure: Option<Enclosure>,
+}
+
+/// An audio file attached to a feed entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Enclosure {
+    pub url: String,
+    pub mime_type: String,
+    /// File size in bytes
+    pub length: Option<u64>,
+    /// Play duration
+    pub duration: Option<Duration>,
 }
 
 impl Feed {
@@ -44,6 +73,23 @@ impl From<RssFeed> for Feed {
 impl From<RssEntry> for Entry {
     fn from(entry: RssEntry) -> Self {
         let content_or_summary = content_or_summary(&entry);
+        let thumbnail = entry
+            .media
+            .iter()
+            .flat_map(|x| x.thumbnails.iter())
+            .map(|x| x.image.uri.clone())
+            .next();
+        let description = entry
+            .media
+            .iter()
+            .find_map(|x| x.description.as_ref())
+            .map(|x| str_helpers::strip_html(x.content.as_str()));
+        let views = entry
+            .media
+            .iter()
+            .filter_map(|x| x.community.as_ref())
+            .find_map(|x| x.stats_views);
+        let enclosure = audio_enclosure(&entry);
         Self {
             title: entry
                 .title
@@ -52,14 +98,37 @@ impl From<RssEntry> for Entry {
             summary: content_or_summary,
             url: entry
                 .links
-                .get(0)
+                .first()
                 .map(|x| x.href.clone())
-                .unwrap_or(entry.id),
-            date: entry.published.or(entry.updated).map(DateTime::<Utc>::from),
+                .unwrap_or_else(|| entry.id.clone()),
+            id: entry.id,
+            date: entry.published.or(entry.updated),
+            thumbnail,
+            description,
+            views,
+            enclosure,
         }
     }
 }
 
+/// Get the first audio file attached to the entry
+fn audio_enclosure(entry: &RssEntry) -> Option<Enclosure> {
+    entry.media.iter().find_map(|media| {
+        media
+            .content
+            .iter()
+            .find_map(|x| match (x.url.as_ref(), x.content_type.as_ref()) {
+                (Some(url), Some(mime)) if mime.type_() == "audio" => Some(Enclosure {
+                    url: url.to_string(),
+                    mime_type: mime.to_string(),
+                    length: x.size,
+                    duration: x.duration.or(media.duration),
+                }),
+                _ => None,
+            })
+    })
+}
+
 /// This function returns content if any, otherwise the summary of the article.
 /// The reason is that content is USUALLY the entire article, BUT sometimes is not filled, so summary is preferred in these cases
 fn content_or_summary(entry: &RssEntry) -> String {
@@ -101,7 +170,12 @@ mod test {
         let entry = RssEntry::default();
         let article = Entry::from(entry);
         assert!(article.authors.is_empty());
+        assert_eq!(article.id, String::new());
         assert_eq!(article.date, None);
+        assert_eq!(article.thumbnail, None);
+        assert_eq!(article.description, None);
+        assert_eq!(article.views, None);
+        assert_eq!(article.enclosure, None);
         assert_eq!(article.summary, String::new());
         assert_eq!(article.title, None);
         assert_eq!(article.url, String::new());
@@ -132,4 +206,73 @@ mod test {
         let feed = Feed::from(feed);
         assert_eq!(feed.entries.len(), 2);
     }
+
+    #[test]
+    fn should_convert_entry_with_media() {
+        let feed = feed_rs::parser::parse(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns:yt="http://www.youtube.com/xml/schemas/2015" xmlns:media="http://search.yahoo.com/mrss/" xmlns="http://www.w3.org/2005/Atom">
+ <id>yt:channel:UCK3cMi97Kf_WENLvRFdztoQ</id>
+ <title>Spazio Grigio</title>
+ <entry>
+  <id>yt:video:rRQP8PNEouo</id>
+  <yt:videoId>rRQP8PNEouo</yt:videoId>
+  <title>La mia morning routine</title>
+  <link rel="alternate" href="https://www.youtube.com/watch?v=rRQP8PNEouo"/>
+  <published>2022-09-01T08:00:00+00:00</published>
+  <media:group>
+   <media:title>La mia morning routine</media:title>
+   <media:thumbnail url="https://i1.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg" width="480" height="360"/>
+   <media:description>Ciao sono Irina</media:description>
+   <media:community>
+    <media:starRating count="100" average="5.00" min="1" max="5"/>
+    <media:statistics views="1234"/>
+   </media:community>
+  </media:group>
+ </entry>
+</feed>"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let feed = Feed::from(feed);
+        let entry = feed.entries().next().unwrap();
+        assert_eq!(entry.id.as_str(), "yt:video:rRQP8PNEouo");
+        assert_eq!(
+            entry.url.as_str(),
+            "https://www.youtube.com/watch?v=rRQP8PNEouo"
+        );
+        assert_eq!(
+            entry.thumbnail.as_deref(),
+            Some("https://i1.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg")
+        );
+        assert_eq!(entry.description.as_deref(), Some("Ciao sono Irina"));
+        assert_eq!(entry.views, Some(1234));
+        assert_eq!(entry.enclosure, None);
+    }
+
+    #[test]
+    fn should_convert_entry_with_audio_enclosure() {
+        let feed = feed_rs::parser::parse(
+            include_str!("../../tests/fixtures/feed/podcast.xml").as_bytes(),
+        )
+        .unwrap();
+        let feed = Feed::from(feed);
+        let entry = feed.entries().next().unwrap();
+        assert_eq!(
+            entry.enclosure.as_ref().unwrap(),
+            &Enclosure {
+                url: String::from("https://www.spaziogrigio.com/podcast/episodio-2.mp3"),
+                mime_type: String::from("audio/mpeg"),
+                length: Some(24986239),
+                duration: Some(Duration::from_secs(1561)),
+            }
+        );
+        let feed =
+            feed_rs::parser::parse(include_str!("../../tests/fixtures/feed/blog.xml").as_bytes())
+                .unwrap();
+        let feed = Feed::from(feed);
+        let entry = feed.entries().next().unwrap();
+        assert_eq!(entry.enclosure, None);
+        assert_eq!(entry.summary.as_str(), "Sette giorni, sette stanze.");
+    }
 }
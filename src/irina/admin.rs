@@ -0,0 +1,297 @@
+//! # Admin
+//!
+//! The admin commands, reserved to the bot operators
+
+use super::automatize::Automatizer;
+use super::broadcast::Broadcasts;
+use super::commands::AdminCommand;
+use super::creator::Creator;
+use super::delivery::TargetKind;
+use super::job::{AutomatizerJob, JobHealth};
+use super::redis::Watermark;
+use super::repository::Repository;
+use super::topic::Topic;
+use super::{Answer, AnswerBuilder, Irina, RichText};
+
+use std::str::FromStr;
+use teloxide::types::{ChatId, UserId};
+use teloxide::utils::command::BotCommands;
+
+/// Date format of the job statuses
+const DATE_FORMAT: &str = "%d/%m %H:%M:%S";
+
+/// Admin commands handler
+pub struct Admin;
+
+impl Admin {
+    /// Answer admin command
+    pub async fn answer(
+        automatizer: &Automatizer,
+        broadcasts: &Broadcasts,
+        chat: ChatId,
+        user: UserId,
+        command: AdminCommand,
+    ) -> Answer {
+        match command {
+            AdminCommand::Admin => Answer::simple_text(AdminCommand::descriptions()),
+            AdminCommand::Stats => Self::stats().await,
+            AdminCommand::Jobs => Self::jobs(automatizer).await,
+            AdminCommand::RunJob(job) => Self::run_job(&job).await,
+            AdminCommand::ResetWatermark(args) => Self::reset_watermark(automatizer, &args).await,
+            AdminCommand::Broadcast => Self::broadcast(broadcasts, chat, user),
+        }
+    }
+
+    /// Start composing a broadcast by user in chat
+    fn broadcast(broadcasts: &Broadcasts, chat: ChatId, user: UserId) -> Answer {
+        broadcasts.start(chat, user);
+        Answer::simple_text(
+            "Inviami il messaggio da inviare a tutte le chat iscritte: testo, foto o album. Riceverai un'anteprima prima dell'invio",
+        )
+    }
+
+    /// Get the subscribers statistics
+    async fn stats() -> Answer {
+        match Self::collect_stats().await {
+            Ok(stats) => AnswerBuilder::default().text(stats).finalize(),
+            Err(err) => Irina::error(err),
+        }
+    }
+
+    /// Count subscribed chats, followers per creator, opt-outs per topic and forwarding targets per kind
+    async fn collect_stats() -> anyhow::Result<RichText> {
+        let repository = Repository::connect().await?;
+        let chats = repository.get_subscribed_chats().await?.len();
+        let mut stats = RichText::default()
+            .bold("📊 Statistiche")
+            .plain(format!("\n\nChat iscritte: {}\n\n", chats))
+            .bold("Follower")
+            .plain("\n");
+        for creator in Creator::all()?.iter() {
+            stats = stats.plain(format!(
+                "• {}: {}\n",
+                creator.id,
+                repository.count_followers(&creator.id).await?
+            ));
+        }
+        stats = stats.plain("\n").bold("Opt-out").plain("\n");
+        for topic in Topic::ALL {
+            stats = stats.plain(format!(
+                "• {}: {}\n",
+                topic,
+                repository.count_opt_outs(topic).await?
+            ));
+        }
+        let targets = repository.get_subscribed_targets(None, None).await?;
+        stats = stats.plain("\n").bold("Inoltri").plain("\n");
+        for kind in [TargetKind::Matrix, TargetKind::Discord, TargetKind::Email] {
+            stats = stats.plain(format!(
+                "• {}: {}\n",
+                kind,
+                targets.iter().filter(|(x, _)| *x == kind).count()
+            ));
+        }
+        Ok(stats)
+    }
+
+    /// Get the health of each job
+    async fn jobs(automatizer: &Automatizer) -> Answer {
+        match automatizer.job_health().await {
+            Ok(health) => AnswerBuilder::default()
+                .text(Self::job_health(&health))
+                .finalize(),
+            Err(err) => Irina::error(err),
+        }
+    }
+
+    /// Render the health of the jobs: the outcome of the last run, the last success and the consecutive failures
+    fn job_health(health: &[(AutomatizerJob, JobHealth)]) -> RichText {
+        let mut text = RichText::default().bold("⚙️ Job").plain("\n");
+        for (job, health) in health.iter() {
+            text = text.plain("\n");
+            let status = match health.last_run.as_ref() {
+                None => {
+                    text = text.plain("⏳ ").code(job).plain(": mai eseguito");
+                    continue;
+                }
+                Some(status) => status,
+            };
+            let outcome = if status.error.is_some() { "❌" } else { "✅" };
+            text = text.plain(format!("{} ", outcome)).code(job).plain(format!(
+                ": {} ({}s), {} consegne",
+                status.finished_at.format(DATE_FORMAT),
+                (status.finished_at - status.started_at).num_seconds(),
+                status.delivered
+            ));
+            if let Some(error) = status.error.as_deref() {
+                text = text.plain(" - ").italic(error);
+            }
+            if health.consecutive_failures > 0 {
+                text = text.plain(format!(
+                    "\n    fallimenti consecutivi: {}, ultimo successo: {}",
+                    health.consecutive_failures,
+                    Self::last_success(health)
+                ));
+            }
+        }
+        text
+    }
+
+    /// The alert sent to the admins when job keeps failing
+    pub fn failure_alert(job: AutomatizerJob, health: &JobHealth) -> Answer {
+        let mut text = RichText::default()
+            .plain("⚠️ Il job ")
+            .code(job)
+            .plain(format!(
+                " è fallito {} volte di fila. Ultimo successo: {}",
+                health.consecutive_failures,
+                Self::last_success(health)
+            ));
+        if let Some(error) = health.last_run.as_ref().and_then(|x| x.error.as_deref()) {
+            text = text.plain("\n\n").italic(error);
+        }
+        AnswerBuilder::default().text(text).finalize()
+    }
+
+    /// Format the date of the last successful run of a job
+    fn last_success(health: &JobHealth) -> String {
+        health
+            .last_success
+            .map(|x| x.format(DATE_FORMAT).to_string())
+            .unwrap_or_else(|| String::from("mai"))
+    }
+
+    /// Run job now
+    async fn run_job(job: &str) -> Answer {
+        let job = match AutomatizerJob::from_str(job.trim()) {
+            Ok(job) => job,
+            Err(err) => return Irina::error(err),
+        };
+        match Automatizer::run_job(job).await {
+            Ok(delivered) => {
+                Answer::simple_text(format!("Job {} completato: {} consegne", job, delivered))
+            }
+            Err(err) => Answer::simple_text(format!("Job {} fallito: {}", job, err)),
+        }
+    }
+
+    /// Reset the watermark of a source
+    async fn reset_watermark(automatizer: &Automatizer, args: &str) -> Answer {
+        let watermark = match Watermark::parse(args) {
+            Ok(watermark) => watermark,
+            Err(err) => return Irina::error(err),
+        };
+        match automatizer.reset_watermark(&watermark).await {
+            Ok(()) => Answer::simple_text(format!(
+                "Watermark azzerato. {}",
+                Self::reset_outcome(&watermark)
+            )),
+            Err(err) => Irina::error(err),
+        }
+    }
+
+    /// Describe what the job does on its next run, once `watermark` has been reset
+    fn reset_outcome(watermark: &Watermark) -> &'static str {
+        match watermark {
+            Watermark::Video(_) | Watermark::Instagram(_) => {
+                "Alla prossima esecuzione il job annuncerà di nuovo i contenuti recenti, a partire dal più vecchio"
+            }
+            Watermark::Newsletter(_) => {
+                "Alla prossima esecuzione il job annuncerà di nuovo l'ultima newsletter"
+            }
+            Watermark::Feed(_) => {
+                "Alla prossima esecuzione il job ripartirà dall'ultimo contenuto del feed, senza annunciarlo"
+            }
+            Watermark::EmailDigest => {
+                "Alla prossima esecuzione il job ripartirà da quel momento, senza inviare il riepilogo"
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::irina::job::JobStatus;
+
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_describe_watermark_reset_outcome() {
+        assert!(
+            Admin::reset_outcome(&Watermark::Video(String::from("spaziogrigio")))
+                .contains("annuncerà di nuovo")
+        );
+        assert!(
+            Admin::reset_outcome(&Watermark::Newsletter(String::from("spaziogrigio")))
+                .contains("annuncerà di nuovo")
+        );
+        assert!(
+            Admin::reset_outcome(&Watermark::Feed(String::from("podcast")))
+                .contains("senza annunciarlo")
+        );
+        assert!(Admin::reset_outcome(&Watermark::EmailDigest).contains("senza inviare"));
+    }
+
+    #[test]
+    fn should_render_job_health() {
+        let started_at = Utc.ymd(2022, 7, 14).and_hms(12, 30, 0);
+        let health = vec![
+            (
+                AutomatizerJob::Video,
+                JobHealth {
+                    last_run: Some(JobStatus {
+                        started_at,
+                        finished_at: Utc.ymd(2022, 7, 14).and_hms(12, 30, 3),
+                        error: None,
+                        delivered: 12,
+                    }),
+                    last_success: Some(started_at),
+                    consecutive_failures: 0,
+                },
+            ),
+            (
+                AutomatizerJob::Instagram,
+                JobHealth {
+                    last_run: Some(JobStatus {
+                        started_at,
+                        finished_at: started_at,
+                        error: Some(String::from("scraper error")),
+                        delivered: 0,
+                    }),
+                    last_success: Some(Utc.ymd(2022, 7, 10).and_hms(8, 40, 0)),
+                    consecutive_failures: 4,
+                },
+            ),
+            (AutomatizerJob::EmailDigest, JobHealth::default()),
+        ];
+        assert_eq!(
+            Admin::job_health(&health).to_plain().as_str(),
+            "⚙️ Job\n\n✅ video: 14/07 12:30:03 (3s), 12 consegne\n❌ instagram: 14/07 12:30:00 (0s), 0 consegne - scraper error\n    fallimenti consecutivi: 4, ultimo successo: 10/07 08:40:00\n⏳ email_digest: mai eseguito"
+        );
+    }
+
+    #[test]
+    fn should_make_failure_alert() {
+        let started_at = Utc.ymd(2022, 7, 14).and_hms(12, 30, 0);
+        let health = JobHealth {
+            last_run: Some(JobStatus {
+                started_at,
+                finished_at: started_at,
+                error: Some(String::from("feed error")),
+                delivered: 0,
+            }),
+            last_success: None,
+            consecutive_failures: 3,
+        };
+        assert_eq!(
+            Admin::failure_alert(AutomatizerJob::Feed, &health)
+                .plain_text()
+                .as_str(),
+            "⚠️ Il job feed è fallito 3 volte di fila. Ultimo successo: mai\n\nfeed error"
+        );
+    }
+}
@@ -3,12 +3,27 @@
 //! This module cares of providing answer script types and sending messages
 
 use std::str::FromStr;
+use std::time::Duration;
 
-use teloxide::{prelude::*, types::InputFile};
+use teloxide::{
+    prelude::*,
+    types::{
+        InlineKeyboardButtonKind, InlineKeyboardMarkup, InputFile, InputMedia, InputMediaPhoto,
+        ParseMode, Recipient,
+    },
+};
 use url::Url;
 
+use super::metrics::METRICS;
+use super::RichText;
+
 type AnswerResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
+/// Max size of the files Telegram downloads from an url (20MB)
+const TELEGRAM_URL_UPLOAD_LIMIT: u64 = 20 * 1024 * 1024;
+/// Max length of a photo caption
+const TELEGRAM_CAPTION_LIMIT: usize = 1024;
+
 /// A helper to build composed answers
 #[derive(Default)]
 pub struct AnswerBuilder {
@@ -16,20 +31,52 @@ pub struct AnswerBuilder {
 }
 
 impl AnswerBuilder {
-    /// Add text to script
-    pub fn text(mut self, text: impl ToString) -> Self {
-        self.answer.script.push(Media::Text(text.to_string()));
+    /// Add text to script. Formatted text is sent as Telegram HTML
+    pub fn text(mut self, text: impl Into<RichText>) -> Self {
+        self.answer.script.push(Media::Text(text.into()));
         self
     }
 
     /// Add image to script
     pub fn image<S: AsRef<str>>(mut self, url: S) -> Self {
         if let Ok(url) = Url::from_str(url.as_ref()) {
-            self.answer.script.push(Media::Image(InputFile::url(url)));
+            self.answer.script.push(Media::Image(url));
+        }
+        self
+    }
+
+    /// Add photos already uploaded to Telegram, identified by their file id, to script.
+    /// More than one photo are sent as an album; the caption is attached to the first photo
+    pub fn album(mut self, file_ids: Vec<String>, caption: Option<RichText>) -> Self {
+        if !file_ids.is_empty() {
+            self.answer.script.push(Media::Album(file_ids, caption));
+        }
+        self
+    }
+
+    /// Add audio to script. If the file exceeds the Telegram upload limit, a link to the file is sent instead
+    pub fn audio<S: AsRef<str>>(mut self, url: S, metadata: AudioMetadata) -> Self {
+        if metadata
+            .size
+            .map(|x| x > TELEGRAM_URL_UPLOAD_LIMIT)
+            .unwrap_or(false)
+        {
+            return self.text(Audio::link(url.as_ref()));
+        }
+        if let Ok(url) = Url::from_str(url.as_ref()) {
+            self.answer
+                .script
+                .push(Media::Audio(Audio { url, metadata }));
         }
         self
     }
 
+    /// Attach an inline keyboard to the last message of the script
+    pub fn keyboard(mut self, keyboard: InlineKeyboardMarkup) -> Self {
+        self.answer.keyboard = Some(keyboard);
+        self
+    }
+
     /// Finalize builder
     pub fn finalize(self) -> Answer {
         self.answer
@@ -40,51 +87,454 @@ impl AnswerBuilder {
 #[derive(Default, Clone)]
 pub struct Answer {
     script: Vec<Media>,
+    keyboard: Option<InlineKeyboardMarkup>,
 }
 
 #[derive(Clone)]
 /// A media in the chat
 enum Media {
-    Text(String),
-    Image(InputFile),
+    Text(RichText),
+    Image(Url),
+    ImageWithCaption(Url, RichText),
+    Album(Vec<String>, Option<RichText>),
+    Audio(Audio),
+}
+
+/// Metadata of an audio file
+#[derive(Debug, Default, Clone)]
+pub struct AudioMetadata {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub duration: Option<Duration>,
+    /// File size in bytes
+    pub size: Option<u64>,
+}
+
+#[derive(Clone)]
+/// An audio file in the chat
+struct Audio {
+    url: Url,
+    metadata: AudioMetadata,
+}
+
+impl Audio {
+    /// Get the text to send instead of the audio file
+    fn link(url: &str) -> RichText {
+        RichText::default().plain("🎧 Ascolta qui: ").link(url, url)
+    }
 }
 
 impl Answer {
     /// Build a simple one text answer
     pub fn simple_text(text: impl ToString) -> Self {
         Self {
-            script: vec![Media::Text(text.to_string())],
+            script: vec![Media::Text(RichText::from(text.to_string()))],
+            keyboard: None,
+        }
+    }
+
+    /// Get the answer as plain text. Texts are separated by an empty line, audio files are replaced by their link
+    pub fn plain_text(&self) -> String {
+        self.script
+            .iter()
+            .filter_map(|media| match media {
+                Media::Text(text)
+                | Media::ImageWithCaption(_, text)
+                | Media::Album(_, Some(text)) => Some(text.to_plain()),
+                Media::Audio(audio) => Some(Audio::link(audio.url.as_str()).to_plain()),
+                Media::Image(_) | Media::Album(_, None) => None,
+            })
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    }
+
+    /// Describe the answer script, one line per message, as it would be sent to telegram.
+    /// Texts are in Telegram HTML; the keyboard buttons are listed on the last line
+    pub fn describe(&self) -> String {
+        let mut lines: Vec<String> = self
+            .script
+            .iter()
+            .map(|media| match media {
+                Media::Text(text) => format!("[text] {}", text.to_html()),
+                Media::Image(url) => format!("[image] {}", url),
+                Media::ImageWithCaption(url, caption) => {
+                    format!("[image] {} {}", url, caption.to_html())
+                }
+                Media::Album(file_ids, caption) => format!(
+                    "[album] {} photos {}",
+                    file_ids.len(),
+                    caption.as_ref().map(|x| x.to_html()).unwrap_or_default()
+                ),
+                Media::Audio(audio) => format!(
+                    "[audio] {} {}",
+                    audio.url,
+                    audio.metadata.title.as_deref().unwrap_or_default()
+                ),
+            })
+            .collect();
+        if let Some(keyboard) = self.keyboard.as_ref() {
+            let buttons: Vec<&str> = keyboard
+                .inline_keyboard
+                .iter()
+                .flatten()
+                .map(|x| x.text.as_str())
+                .collect();
+            lines.push(format!("[keyboard] {}", buttons.join(" | ")));
         }
+        lines.join("\n")
+    }
+
+    /// Get the urls of the images in the answer
+    pub fn images(&self) -> impl Iterator<Item = &Url> {
+        self.script.iter().filter_map(|media| match media {
+            Media::Image(url) | Media::ImageWithCaption(url, _) => Some(url),
+            Media::Text(_) | Media::Album(_, _) | Media::Audio(_) => None,
+        })
     }
 
     /// Send answer
-    pub async fn send(self, bot: &AutoSend<Bot>, chat_id: ChatId) -> AnswerResult<()> {
-        for message in self.script.into_iter() {
-            match message {
-                Media::Image(image) => Self::send_image(bot, chat_id, image).await?,
-                Media::Text(text) => Self::send_text(bot, chat_id, text).await?,
+    pub async fn send(
+        self,
+        bot: &AutoSend<Bot>,
+        chat_id: impl Into<Recipient>,
+    ) -> AnswerResult<()> {
+        Self::send_script(self.script, self.keyboard, bot, chat_id.into()).await
+    }
+
+    /// Post answer to a channel.
+    /// An image followed by a text is posted as a single photo with caption, so the channel gets one post per announcement;
+    /// text-only posts keep the link preview.
+    /// Only the link buttons of the keyboard are kept, since callbacks act on the chat they come from
+    pub async fn send_to_channel(
+        self,
+        bot: &AutoSend<Bot>,
+        channel: Recipient,
+    ) -> AnswerResult<()> {
+        Self::send_script(
+            Self::channel_script(self.script),
+            self.keyboard.and_then(Self::channel_keyboard),
+            bot,
+            channel,
+        )
+        .await
+    }
+
+    /// Keep only the link buttons of keyboard
+    fn channel_keyboard(keyboard: InlineKeyboardMarkup) -> Option<InlineKeyboardMarkup> {
+        let rows: Vec<_> = keyboard
+            .inline_keyboard
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .filter(|button| matches!(button.kind, InlineKeyboardButtonKind::Url(_)))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|row| !row.is_empty())
+            .collect();
+        if rows.is_empty() {
+            None
+        } else {
+            Some(InlineKeyboardMarkup::new(rows))
+        }
+    }
+
+    /// Merge images with the following text into a single captioned image
+    fn channel_script(script: Vec<Media>) -> Vec<Media> {
+        let mut channel_script = Vec::with_capacity(script.len());
+        for media in script.into_iter() {
+            match (channel_script.pop(), media) {
+                (Some(Media::Image(image)), Media::Text(text))
+                    if text.len() <= TELEGRAM_CAPTION_LIMIT =>
+                {
+                    channel_script.push(Media::ImageWithCaption(image, text));
+                }
+                (prev, media) => {
+                    channel_script.extend(prev);
+                    channel_script.push(media);
+                }
             }
         }
+        channel_script
+    }
+
+    /// Send script to chat. The keyboard is attached to the last message
+    async fn send_script(
+        script: Vec<Media>,
+        mut keyboard: Option<InlineKeyboardMarkup>,
+        bot: &AutoSend<Bot>,
+        chat_id: Recipient,
+    ) -> AnswerResult<()> {
+        let last = script.len().saturating_sub(1);
+        for (i, message) in script.into_iter().enumerate() {
+            let chat_id = chat_id.clone();
+            let keyboard = if i == last { keyboard.take() } else { None };
+            let result = match message {
+                Media::Image(image) => {
+                    Self::send_image(bot, chat_id, InputFile::url(image), keyboard).await
+                }
+                Media::ImageWithCaption(image, caption) => {
+                    let mut request = bot
+                        .send_photo(chat_id, InputFile::url(image))
+                        .caption(caption.to_html())
+                        .parse_mode(ParseMode::Html);
+                    if let Some(keyboard) = keyboard {
+                        request = request.reply_markup(keyboard);
+                    }
+                    request.await.map(|_| ()).map_err(|e| e.into())
+                }
+                Media::Album(file_ids, caption) => {
+                    Self::send_album(bot, chat_id, file_ids, caption, keyboard).await
+                }
+                Media::Audio(audio) => Self::send_audio(bot, chat_id, audio, keyboard).await,
+                Media::Text(text) => Self::send_text(bot, chat_id, text, keyboard).await,
+            };
+            METRICS.message(result.is_ok());
+            result?;
+        }
         Ok(())
     }
 
     /// Write text to chat
-    async fn send_text(bot: &AutoSend<Bot>, chat_id: ChatId, message: String) -> AnswerResult<()> {
-        bot.send_message(chat_id, message)
-            .await
-            .map(|_| ())
-            .map_err(|e| e.into())
+    async fn send_text(
+        bot: &AutoSend<Bot>,
+        chat_id: Recipient,
+        message: RichText,
+        keyboard: Option<InlineKeyboardMarkup>,
+    ) -> AnswerResult<()> {
+        let mut request = bot
+            .send_message(chat_id, message.to_html())
+            .parse_mode(ParseMode::Html);
+        if let Some(keyboard) = keyboard {
+            request = request.reply_markup(keyboard);
+        }
+        request.await.map(|_| ()).map_err(|e| e.into())
     }
 
     /// Send image to chat
     async fn send_image(
         bot: &AutoSend<Bot>,
-        chat_id: ChatId,
+        chat_id: Recipient,
         image: InputFile,
+        keyboard: Option<InlineKeyboardMarkup>,
+    ) -> AnswerResult<()> {
+        let mut request = bot.send_photo(chat_id, image);
+        if let Some(keyboard) = keyboard {
+            request = request.reply_markup(keyboard);
+        }
+        request.await.map(|_| ()).map_err(|e| e.into())
+    }
+
+    /// Send photos to chat as an album. A single photo is sent as a photo, since albums can't have a keyboard
+    async fn send_album(
+        bot: &AutoSend<Bot>,
+        chat_id: Recipient,
+        file_ids: Vec<String>,
+        caption: Option<RichText>,
+        keyboard: Option<InlineKeyboardMarkup>,
     ) -> AnswerResult<()> {
-        bot.send_photo(chat_id, image)
+        if file_ids.len() == 1 {
+            let mut request = bot.send_photo(chat_id, InputFile::file_id(&file_ids[0]));
+            if let Some(caption) = caption {
+                request = request
+                    .caption(caption.to_html())
+                    .parse_mode(ParseMode::Html);
+            }
+            if let Some(keyboard) = keyboard {
+                request = request.reply_markup(keyboard);
+            }
+            return request.await.map(|_| ()).map_err(|e| e.into());
+        }
+        let mut caption = caption;
+        let media = file_ids.into_iter().map(|file_id| {
+            let photo = InputMediaPhoto::new(InputFile::file_id(file_id));
+            InputMedia::Photo(match caption.take() {
+                Some(caption) => photo.caption(caption.to_html()).parse_mode(ParseMode::Html),
+                None => photo,
+            })
+        });
+        bot.send_media_group(chat_id, media)
             .await
             .map(|_| ())
             .map_err(|e| e.into())
     }
+
+    /// Send audio to chat. If Telegram can't send the file, a link to the file is sent instead
+    async fn send_audio(
+        bot: &AutoSend<Bot>,
+        chat_id: Recipient,
+        audio: Audio,
+        keyboard: Option<InlineKeyboardMarkup>,
+    ) -> AnswerResult<()> {
+        let mut request = bot.send_audio(chat_id.clone(), InputFile::url(audio.url.clone()));
+        if let Some(keyboard) = keyboard.clone() {
+            request = request.reply_markup(keyboard);
+        }
+        if let Some(title) = audio.metadata.title {
+            request = request.title(title);
+        }
+        if let Some(performer) = audio.metadata.performer {
+            request = request.performer(performer);
+        }
+        if let Some(duration) = audio.metadata.duration {
+            request = request.duration(duration.as_secs() as u32);
+        }
+        if let Err(err) = request.await {
+            warn!(
+                "failed to send audio {} to {}: {}; sending link instead",
+                audio.url, chat_id, err
+            );
+            Self::send_text(bot, chat_id, Audio::link(audio.url.as_str()), keyboard).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use teloxide::types::InlineKeyboardButton;
+
+    #[test]
+    fn should_describe_answer() {
+        let answer = AnswerBuilder::default()
+            .image("https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg")
+            .text(
+                RichText::default()
+                    .plain("Nuovo video: ")
+                    .bold("Il mio armadio"),
+            )
+            .keyboard(InlineKeyboardMarkup::new(vec![vec![
+                InlineKeyboardButton::url(
+                    "Guarda su YouTube",
+                    Url::parse("https://www.youtube.com/watch?v=rRQP8PNEouo").unwrap(),
+                ),
+                InlineKeyboardButton::callback("Altri video", "videos:spaziogrigio"),
+            ]]))
+            .finalize();
+        assert_eq!(
+            answer.describe().as_str(),
+            "[image] https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg\n[text] Nuovo video: <b>Il mio armadio</b>\n[keyboard] Guarda su YouTube | Altri video"
+        );
+    }
+
+    #[test]
+    fn should_merge_image_and_text_for_channels() {
+        let answer = AnswerBuilder::default()
+            .image("https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg")
+            .text("Ho appena pubblicato questo nuovo mio video")
+            .text("👉 https://www.youtube.com/watch?v=rRQP8PNEouo")
+            .image("https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg")
+            .text("a".repeat(TELEGRAM_CAPTION_LIMIT + 1))
+            .finalize();
+        let script = Answer::channel_script(answer.script);
+        assert_eq!(script.len(), 4);
+        assert!(
+            matches!(&script[0], Media::ImageWithCaption(_, caption) if caption.to_plain() == "Ho appena pubblicato questo nuovo mio video")
+        );
+        assert!(matches!(&script[1], Media::Text(_)));
+        assert!(matches!(&script[2], Media::Image(_)));
+        assert!(matches!(&script[3], Media::Text(_)));
+    }
+
+    #[test]
+    fn should_count_caption_limit_in_utf16_code_units() {
+        let answer = AnswerBuilder::default()
+            .image("https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg")
+            .text("👋".repeat(TELEGRAM_CAPTION_LIMIT / 2))
+            .image("https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg")
+            .text("👋".repeat(TELEGRAM_CAPTION_LIMIT / 2 + 1))
+            .finalize();
+        let script = Answer::channel_script(answer.script);
+        assert_eq!(script.len(), 3);
+        assert!(matches!(&script[0], Media::ImageWithCaption(_, _)));
+        assert!(matches!(&script[1], Media::Image(_)));
+        assert!(matches!(&script[2], Media::Text(_)));
+    }
+
+    #[test]
+    fn should_keep_only_link_buttons_for_channels() {
+        let keyboard = InlineKeyboardMarkup::new([
+            vec![
+                InlineKeyboardButton::url(
+                    "Guarda su YouTube",
+                    Url::parse("https://www.youtube.com/watch?v=rRQP8PNEouo").unwrap(),
+                ),
+                InlineKeyboardButton::callback("Altri video", "videos:spaziogrigio"),
+            ],
+            vec![InlineKeyboardButton::callback(
+                "Disiscriviti da questo argomento",
+                "optout:video",
+            )],
+        ]);
+        let keyboard = Answer::channel_keyboard(keyboard).unwrap();
+        assert_eq!(keyboard.inline_keyboard.len(), 1);
+        assert_eq!(keyboard.inline_keyboard[0].len(), 1);
+        assert_eq!(
+            keyboard.inline_keyboard[0][0].text.as_str(),
+            "Guarda su YouTube"
+        );
+        assert!(Answer::channel_keyboard(InlineKeyboardMarkup::new([vec![
+            InlineKeyboardButton::callback("Altri video", "videos:spaziogrigio")
+        ]]))
+        .is_none());
+    }
+
+    #[test]
+    fn should_render_answer_as_plain_text() {
+        let answer = AnswerBuilder::default()
+            .image("https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg")
+            .text("È uscito un nuovo episodio")
+            .audio(
+                "https://www.spaziogrigio.com/podcast/episodio-2.mp3",
+                AudioMetadata::default(),
+            )
+            .finalize();
+        assert_eq!(
+            answer.plain_text().as_str(),
+            "È uscito un nuovo episodio\n\n🎧 Ascolta qui: https://www.spaziogrigio.com/podcast/episodio-2.mp3"
+        );
+        assert_eq!(
+            answer.images().map(|x| x.as_str()).collect::<Vec<&str>>(),
+            vec!["https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg"]
+        );
+    }
+
+    #[test]
+    fn should_add_audio_to_script() {
+        let answer = AnswerBuilder::default()
+            .audio(
+                "https://www.spaziogrigio.com/podcast/episodio-2.mp3",
+                AudioMetadata {
+                    title: Some(String::from("Episodio 2")),
+                    performer: Some(String::from("Irina")),
+                    duration: Some(Duration::from_secs(1561)),
+                    size: Some(12582912),
+                },
+            )
+            .finalize();
+        assert_eq!(answer.script.len(), 1);
+        assert!(
+            matches!(&answer.script[0], Media::Audio(audio) if audio.metadata.title.as_deref() == Some("Episodio 2"))
+        );
+    }
+
+    #[test]
+    fn should_send_link_if_audio_exceeds_upload_limit() {
+        let answer = AnswerBuilder::default()
+            .audio(
+                "https://www.spaziogrigio.com/podcast/episodio-1.mp3",
+                AudioMetadata {
+                    size: Some(62914560),
+                    ..Default::default()
+                },
+            )
+            .finalize();
+        assert_eq!(answer.script.len(), 1);
+        assert!(
+            matches!(&answer.script[0], Media::Text(text) if text.to_html() == "🎧 Ascolta qui: <a href=\"https://www.spaziogrigio.com/podcast/episodio-1.mp3\">https://www.spaziogrigio.com/podcast/episodio-1.mp3</a>")
+        );
+    }
 }
@@ -2,19 +2,46 @@
 //!
 //! A module to automatize messages
 
+use super::admin::Admin;
+use super::creator::Creator;
+use super::delivery::{DeliveryTarget, EmailTarget, SmtpConfig, TargetKind, TelegramTarget};
+use super::feeds::{FeedSource, Feeds};
+use super::inline::Inline;
 use super::instagram::InstagramService;
+use super::job::{AutomatizerJob, JobHealth, JobStatus};
+use super::keyboard;
+use super::metrics::METRICS;
 use super::newsletter::Newsletter;
-use super::redis::RedisRepository;
+use super::redis::{RedisRepository, Watermark};
 use super::repository::Repository;
+use super::template::Template;
+use super::topic::Topic;
 use super::youtube::Youtube;
-use super::AnswerBuilder;
+use super::{Answer, AnswerBuilder, AudioMetadata, Config, RichText};
+use crate::repository::content::Content;
+use crate::utils::str as str_helpers;
+use crate::youtube::{self, Entry, VideoKind};
 
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::UNIX_EPOCH;
-use teloxide::prelude::*;
 use teloxide::types::ChatId;
 use thiserror::Error;
 use tokio_cron_scheduler::{Job, JobScheduler, JobSchedulerError};
 
+/// Max length of the video description excerpt in notifications
+const DESCRIPTION_EXCERPT_LEN: usize = 256;
+/// Subject of the email digest
+const EMAIL_DIGEST_SUBJECT: &str = "Le novità di oggi da Spazio Grigio";
+/// Range of the codes confirming a forward to a delivery target
+const CONFIRMATION_CODE_RANGE: std::ops::Range<u32> = 100_000..1_000_000;
+/// Days the job runs are kept in the database
+const JOB_RUN_RETENTION_DAYS: i64 = 30;
+
+/// When set, jobs render the announcements without sending them and without advancing the watermarks
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
 type AutomatizerResult<T> = Result<T, AutomatizerError>;
 
 /// Automatizer error
@@ -22,6 +49,14 @@ type AutomatizerResult<T> = Result<T, AutomatizerError>;
 pub enum AutomatizerError {
     #[error("scheduler error: {0}")]
     Scheduler(JobSchedulerError),
+    #[error("configuration error: {0}")]
+    Config(anyhow::Error),
+}
+
+impl From<anyhow::Error> for AutomatizerError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Config(e)
+    }
 }
 
 impl From<JobSchedulerError> for AutomatizerError {
@@ -36,6 +71,17 @@ pub struct Automatizer {
 }
 
 impl Automatizer {
+    /// Enable dry run: the jobs fetch their sources and print the announcements they would send,
+    /// without sending anything, advancing the watermarks, archiving contents or recording the runs
+    pub fn set_dry_run(dry_run: bool) {
+        DRY_RUN.store(dry_run, Ordering::Relaxed);
+    }
+
+    /// Check whether the automatizer runs in dry run
+    pub fn is_dry_run() -> bool {
+        DRY_RUN.load(Ordering::Relaxed)
+    }
+
     /// Start automatizer
     pub async fn start() -> AutomatizerResult<Self> {
         debug!("starting automatizer");
@@ -44,10 +90,50 @@ impl Automatizer {
         })
     }
 
-    /// Subscribe a chat to the automatizer
+    /// Create an automatizer without any scheduled job
+    #[cfg(test)]
+    pub async fn idle() -> Self {
+        Self {
+            scheduler: JobScheduler::new()
+                .await
+                .expect("failed to create scheduler"),
+        }
+    }
+
+    /// Run job now, out of its schedule and without starting the scheduler. Returns the amount of delivered messages
+    pub async fn run_job(job: AutomatizerJob) -> anyhow::Result<usize> {
+        Self::run(job).await
+    }
+
+    /// Check whether the scheduler is running, with jobs to run
+    pub async fn is_running(&self) -> bool {
+        let mut scheduler = self.scheduler.clone();
+        scheduler.inited().await && matches!(scheduler.time_till_next_job().await, Ok(Some(_)))
+    }
+
+    /// Get the health of each job from the run history
+    pub async fn job_health(&self) -> anyhow::Result<Vec<(AutomatizerJob, JobHealth)>> {
+        let repository = Repository::connect().await?;
+        let mut health = Vec::with_capacity(AutomatizerJob::ALL.len());
+        for job in AutomatizerJob::ALL {
+            health.push((job, repository.get_job_health(job).await?));
+        }
+        Ok(health)
+    }
+
+    /// Reset watermark, so the next run of its job announces the latest content again
+    pub async fn reset_watermark(&self, watermark: &Watermark) -> anyhow::Result<()> {
+        RedisRepository::connect()?
+            .reset_watermark(watermark)
+            .await?;
+        info!("reset watermark {:?}", watermark);
+        Ok(())
+    }
+
+    /// Subscribe a chat to the automatizer. The chat will follow all the creators
     pub async fn subscribe(&self, chat: &ChatId) -> anyhow::Result<()> {
         let repository = Repository::connect().await?;
-        repository.insert_chat(*chat).await?;
+        repository.insert_chat(*chat, Creator::all()?).await?;
         info!("subscribed {} to the automatizer", chat);
         Ok(())
     }
@@ -60,49 +146,148 @@ impl Automatizer {
         Ok(())
     }
 
+    /// Make chat follow creator
+    pub async fn follow(&self, chat: &ChatId, creator: &Creator) -> anyhow::Result<()> {
+        let repository = Repository::connect().await?;
+        repository.follow(*chat, creator).await?;
+        info!("{} is now following {}", chat, creator.id);
+        Ok(())
+    }
+
+    /// Make chat unfollow creator
+    pub async fn unfollow(&self, chat: &ChatId, creator: &Creator) -> anyhow::Result<()> {
+        let repository = Repository::connect().await?;
+        repository.unfollow(*chat, creator).await?;
+        info!("{} is not following {} anymore", chat, creator.id);
+        Ok(())
+    }
+
+    /// Opt chat out from topic
+    pub async fn opt_out(&self, chat: &ChatId, topic: Topic) -> anyhow::Result<()> {
+        let repository = Repository::connect().await?;
+        repository.opt_out(*chat, topic).await?;
+        info!("{} opted out from {}", chat, topic);
+        Ok(())
+    }
+
+    /// Opt chat in to topic
+    pub async fn opt_in(&self, chat: &ChatId, topic: Topic) -> anyhow::Result<()> {
+        let repository = Repository::connect().await?;
+        repository.opt_in(*chat, topic).await?;
+        info!("{} opted in to {}", chat, topic);
+        Ok(())
+    }
+
+    /// Forward the announcements of the creators followed by chat to the delivery target at `address`.
+    /// Unless chat already forwards to the target, the forward must be confirmed with the code sent to it.
+    /// Returns whether the confirmation code has been sent
+    pub async fn forward(
+        &self,
+        chat: &ChatId,
+        target: TargetKind,
+        address: &str,
+    ) -> anyhow::Result<bool> {
+        let code = rand::thread_rng()
+            .gen_range(CONFIRMATION_CODE_RANGE)
+            .to_string();
+        let repository = Repository::connect().await?;
+        if !repository
+            .subscribe_target(*chat, target, address, &code)
+            .await?
+        {
+            info!(
+                "{} is now forwarding announcements to {} {}",
+                chat,
+                target,
+                target.redact(address)
+            );
+            return Ok(false);
+        }
+        let answer = Answer::simple_text(format!(
+            "Ciao sono Irina. Per inoltrare qui gli aggiornamenti di Spazio Grigio, invia al bot il comando /inoltra {} seguito da questo indirizzo e dal codice {}",
+            target, code
+        ));
+        let delivered = match target.target(address) {
+            Ok(delivery) => delivery.deliver(&answer).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = delivered {
+            error!(
+                "failed to send confirmation code to {} {}: {}",
+                target,
+                target.redact(address),
+                err
+            );
+            repository
+                .unsubscribe_target(*chat, target, address)
+                .await?;
+            anyhow::bail!(
+                "Non sono riuscita a inviare il codice di conferma a {}",
+                target.redact(address)
+            )
+        }
+        info!(
+            "sent forward confirmation code to {} {} for {}",
+            target,
+            target.redact(address),
+            chat
+        );
+        Ok(true)
+    }
+
+    /// Confirm forwarding the announcements to the delivery target at `address` with the code sent to it
+    pub async fn confirm_forward(
+        &self,
+        chat: &ChatId,
+        target: TargetKind,
+        address: &str,
+        code: &str,
+    ) -> anyhow::Result<()> {
+        let repository = Repository::connect().await?;
+        repository
+            .confirm_target(*chat, target, address, code)
+            .await?;
+        info!(
+            "{} is now forwarding announcements to {} {}",
+            chat,
+            target,
+            target.redact(address)
+        );
+        Ok(())
+    }
+
+    /// Stop forwarding announcements to the delivery target at `address`
+    pub async fn unforward(
+        &self,
+        chat: &ChatId,
+        target: TargetKind,
+        address: &str,
+    ) -> anyhow::Result<()> {
+        let repository = Repository::connect().await?;
+        repository
+            .unsubscribe_target(*chat, target, address)
+            .await?;
+        info!(
+            "{} stopped forwarding announcements to {} {}",
+            chat,
+            target,
+            target.redact(address)
+        );
+        Ok(())
+    }
+
     /// Setup cron scheduler
     async fn setup_cron_scheduler() -> AutomatizerResult<JobScheduler> {
         let sched = JobScheduler::new().await?;
-        // good_morning_job
-        let good_morning_job = Job::new_async("0 5 6 * * *", |_, _| {
-            Box::pin(async move {
-                info!("running good_morning_job");
-                if let Err(err) = Self::send_good_morning().await {
-                    error!("good_morning_job failed: {}", err);
-                }
-            })
-        })?;
-        sched.add(good_morning_job).await?;
-        // newsletter_job
-        let newsletter_job = Job::new_async("0 30 19 * * *", |_, _| {
-            Box::pin(async move {
-                info!("running newsletter_job");
-                if let Err(err) = Self::fetch_latest_newsletter().await {
-                    error!("newsletter_job failed: {}", err);
-                }
-            })
-        })?;
-        sched.add(newsletter_job).await?;
-        // newsletter_job
-        let instagram_job = Job::new_async("0 40 * * * *", |_, _| {
-            Box::pin(async move {
-                info!("running instagram_job");
-                if let Err(err) = Self::fetch_latest_unseen_instagram_post().await {
-                    error!("instagram_job failed: {}", err);
-                }
-            })
-        })?;
-        sched.add(instagram_job).await?;
-        // new video check
-        let new_video_check_job = Job::new_async("0 30 * * * *", |_, _| {
-            Box::pin(async move {
-                info!("running new_video_check_job");
-                if let Err(err) = Self::fetch_latest_video().await {
-                    error!("new_video_check_job failed: {}", err);
-                }
-            })
-        })?;
-        sched.add(new_video_check_job).await?;
+        let config = Config::load()?;
+        for job in AutomatizerJob::ALL {
+            let cron_job = Job::new_async(config.schedule(job), move |_, _| {
+                Box::pin(async move {
+                    let _ = Self::run(job).await;
+                })
+            })?;
+            sched.add(cron_job).await?;
+        }
 
         sched
             .start()
@@ -111,77 +296,206 @@ impl Automatizer {
             .map_err(AutomatizerError::from)
     }
 
-    async fn send_good_morning() -> anyhow::Result<()> {
-        let bot = Bot::from_env().auto_send();
+    /// Run job, recording its run and alerting the admins if it keeps failing.
+    /// Returns the amount of delivered messages
+    async fn run(job: AutomatizerJob) -> anyhow::Result<usize> {
+        info!("running {}_job", job);
+        let started_at = Utc::now();
+        let result = match job {
+            AutomatizerJob::GoodMorning => Self::send_good_morning().await,
+            AutomatizerJob::Newsletter => Self::fetch_latest_newsletter().await,
+            AutomatizerJob::Instagram => Self::fetch_latest_unseen_instagram_post().await,
+            AutomatizerJob::Video => Self::fetch_latest_video().await,
+            AutomatizerJob::Feed => Self::fetch_feeds().await,
+            AutomatizerJob::EmailDigest => Self::send_email_digest().await,
+        };
+        match result.as_ref() {
+            Ok(delivered) => info!("{}_job delivered {} messages", job, delivered),
+            Err(err) => error!("{}_job failed: {}", job, err),
+        }
+        let status = JobStatus {
+            started_at,
+            finished_at: Utc::now(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            delivered: result.as_ref().copied().unwrap_or_default(),
+        };
+        METRICS.job_run(
+            job,
+            (status.finished_at - started_at).num_milliseconds() as f64 / 1000.0,
+            status.error.is_some(),
+        );
+        if Self::is_dry_run() {
+            debug!("dry run: not recording {}_job run", job);
+        } else if let Err(err) = Self::record_run(job, &status).await {
+            error!("failed to record {}_job run: {}", job, err);
+        }
+        result
+    }
+
+    /// Record job run and alert the admins if the job has just reached the consecutive failures threshold
+    async fn record_run(job: AutomatizerJob, status: &JobStatus) -> anyhow::Result<()> {
+        let repository = Repository::connect().await?;
+        repository
+            .record_job_run(
+                job,
+                status,
+                Utc::now() - Duration::days(JOB_RUN_RETENTION_DAYS),
+            )
+            .await?;
+        let health = repository.get_job_health(job).await?;
+        let config = Config::load()?;
+        if health.consecutive_failures == 0
+            || health.consecutive_failures != config.job_failure_threshold()
+        {
+            return Ok(());
+        }
+        warn!(
+            "{}_job failed {} times in a row; alerting admins",
+            job, health.consecutive_failures
+        );
+        let bot = config.bot()?;
+        let alert = Admin::failure_alert(job, &health);
+        for admin in config.admin_ids()?.into_iter() {
+            if let Err(err) = alert.clone().send(&bot, ChatId::from(admin)).await {
+                error!("failed to alert admin {}: {}", admin, err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the amount of delivered messages, or an error listing `failures`, if any
+    fn outcome(delivered: usize, failures: Vec<String>) -> anyhow::Result<usize> {
+        if failures.is_empty() {
+            Ok(delivered)
+        } else {
+            anyhow::bail!("{}", failures.join("; "))
+        }
+    }
+
+    async fn send_good_morning() -> anyhow::Result<usize> {
+        let bot = Config::load()?.bot()?;
         let message = super::Irina::good_morning();
-        for chat in Self::subscribed_chats().await?.iter() {
+        let chats = Self::subscribed_chats().await?;
+        if Self::is_dry_run() {
+            let names: Vec<String> = chats.iter().map(|x| format!("chat {}", x)).collect();
+            Self::print_dry_run("good morning", &names, &message);
+            return Ok(chats.len());
+        }
+        let mut delivered = 0;
+        for chat in chats.iter() {
             debug!("sending scheduled good morning to {}", chat);
-            if let Err(err) = message.clone().send(&bot, *chat).await {
-                error!("failed to send scheduled good morning to {}: {}", chat, err);
+            match message.clone().send(&bot, *chat).await {
+                Ok(()) => delivered += 1,
+                Err(err) => error!("failed to send scheduled good morning to {}: {}", chat, err),
             }
         }
-        Ok(())
+        Ok(delivered)
     }
 
-    /// Send perla
-    async fn fetch_latest_newsletter() -> anyhow::Result<()> {
-        let message = match Newsletter::connect()
-            .await?
-            .get_latest_message("info@spaziogrigio.com")
-            .await
-        {
+    /// Fetch latest newsletter job
+    async fn fetch_latest_newsletter() -> anyhow::Result<usize> {
+        let mut newsletter = Newsletter::connect().await?;
+        let (mut delivered, mut failures) = (0, Vec::new());
+        for creator in Creator::all()?.iter() {
+            if let Some(sender) = creator.newsletter_sender.as_deref() {
+                match Self::fetch_latest_creator_newsletter(&mut newsletter, creator, sender).await
+                {
+                    Ok(count) => delivered += count,
+                    Err(err) => {
+                        error!("failed to fetch newsletter for {}: {}", creator.id, err);
+                        failures.push(format!("{}: {}", creator.id, err));
+                    }
+                }
+            }
+        }
+        Self::outcome(delivered, failures)
+    }
+
+    /// Fetch latest newsletter sent by `sender` for creator
+    async fn fetch_latest_creator_newsletter(
+        newsletter: &mut Newsletter,
+        creator: &Creator,
+        sender: &str,
+    ) -> anyhow::Result<usize> {
+        let message = match newsletter.get_latest_message(sender).await {
             Ok(Some(v)) => v,
             Ok(None) => {
-                info!("inbox is empty; return OK");
-                return Ok(());
+                info!("inbox has no message from {}; return OK", sender);
+                return Ok(0);
             }
             Err(err) => {
                 anyhow::bail!("failed to check latest message: {}", err)
             }
         };
-        let mut redis_client = RedisRepository::connect()?;
-        let last_post_pubdate = redis_client.get_last_newsletter_update().await?;
+        let mut redis_client = RedisRepository::connect()?.dry_run(Self::is_dry_run());
+        let last_post_pubdate = redis_client.get_last_newsletter_update(&creator.id).await?;
         debug!(
-            "last time I checked newsletter message, had date {:?}; latest has {}",
-            last_post_pubdate, message.date
+            "last time I checked {} newsletter message, had date {:?}; latest has {}",
+            creator.id, last_post_pubdate, message.date
         );
+        let mut delivered = 0;
         if last_post_pubdate.map(|x| x < message.date).unwrap_or(true) {
-            let bot = Bot::from_env().auto_send();
             info!(
-                "spazio grigio published a mail ({}): {}",
-                message.date, message.subject
+                "{} published a mail ({}) from {}: {}",
+                creator.id,
+                message.date,
+                message.sender_name.as_deref().unwrap_or(sender),
+                message.subject
             );
             let answer = AnswerBuilder::default()
-                .text(format!(
-                    "Ciao sono Irina.\n{}\n\n{}",
-                    message.subject, message.body,
-                ))
+                .text(
+                    RichText::default()
+                        .plain(Template::Newsletter.render(&[("persona", &creator.persona)]))
+                        .bold(&message.subject)
+                        .plain(format!("\n\n{}", message.body)),
+                )
                 .finalize();
-            for chat in Self::subscribed_chats().await?.iter() {
-                debug!("sending new newsletter notify to {}", chat);
-                if let Err(err) = answer.clone().send(&bot, *chat).await {
-                    error!("failed to send scheduled newsletter to {}: {}", chat, err);
-                }
-            }
+            delivered = Self::send_to_followers(creator, answer, Topic::Newsletter).await?;
+            Self::archive(Content::new(
+                format!("newsletter:{}:{}", creator.id, message.date.to_rfc3339()),
+                Topic::Newsletter,
+                Some(&creator.id),
+                &message.subject,
+                &message.body,
+                message.date,
+            ))
+            .await;
             redis_client
-                .set_last_newsletter_update(message.date)
+                .set_last_newsletter_update(&creator.id, message.date)
                 .await?;
         }
-        Ok(())
+        Ok(delivered)
     }
 
     /// Fetch latest video job
-    async fn fetch_latest_video() -> anyhow::Result<()> {
-        let mut redis_client = RedisRepository::connect()?;
+    async fn fetch_latest_video() -> anyhow::Result<usize> {
+        let (mut delivered, mut failures) = (0, Vec::new());
+        for creator in Creator::all()?.iter() {
+            if creator.youtube_channel_id.is_some() {
+                match Self::fetch_latest_creator_video(creator).await {
+                    Ok(count) => delivered += count,
+                    Err(err) => {
+                        error!("failed to fetch latest video for {}: {}", creator.id, err);
+                        failures.push(format!("{}: {}", creator.id, err));
+                    }
+                }
+            }
+        }
+        Self::outcome(delivered, failures)
+    }
+
+    /// Fetch latest video for creator
+    async fn fetch_latest_creator_video(creator: &Creator) -> anyhow::Result<usize> {
+        let mut redis_client = RedisRepository::connect()?.dry_run(Self::is_dry_run());
         let last_post_pubdate = redis_client
-            .get_last_video_pubdate()
+            .get_last_video_pubdate(&creator.id)
             .await?
             .unwrap_or_default();
-        let video = match Youtube::get_oldest_unseen_video(last_post_pubdate).await {
+        let video = match Youtube::get_oldest_unseen_video(creator, last_post_pubdate).await {
             Ok(Some(v)) => v,
             Ok(None) => {
-                debug!("could not find any unseen video from spazio grigio");
-                return Ok(());
+                debug!("could not find any unseen video from {}", creator.id);
+                return Ok(0);
             }
             Err(err) => {
                 anyhow::bail!("failed to check latest video: {}", err)
@@ -189,81 +503,486 @@ impl Automatizer {
         };
 
         debug!(
-                "last time I checked spazio-grigio videos, spazio-grigio video had date {:?}; latest has {:?}",
-                last_post_pubdate,
-                video.date
-            );
-        let bot = Bot::from_env().auto_send();
+            "last time I checked {} videos, video had date {:?}; latest has {:?}",
+            creator.id, last_post_pubdate, video.date
+        );
         info!(
-            "spazio grigio published a new video ({:?}): {}",
+            "{} published a new video ({:?}): {}",
+            creator.id,
             video.date,
             video.title.as_deref().unwrap_or_default()
         );
-        let message = AnswerBuilder::default()
-            .text(format!(
-                "Ciao sono Irina. Ho appena pubblicato questo nuovo mio video: {}\n👉 {}",
+        let (message, topic) = Self::video_announcement(creator, &video);
+        let delivered = Self::send_to_followers(creator, message, topic).await?;
+        Self::archive(
+            Content::new(
+                &video.id,
+                topic,
+                Some(&creator.id),
                 video.title.as_deref().unwrap_or_default(),
-                video.url
-            ))
-            .finalize();
-        for chat in Self::subscribed_chats().await?.iter() {
-            debug!("sending new video notify to {}", chat);
-            if let Err(err) = message.clone().send(&bot, *chat).await {
-                error!("failed to send scheduled video notify to {}: {}", chat, err);
-            }
-        }
+                video.description.as_deref().unwrap_or_default(),
+                video.date.unwrap_or_else(Utc::now),
+            )
+            .with_url(Some(video.url.clone()))
+            .with_image(youtube::thumbnail(&video)),
+        )
+        .await;
         if let Some(date) = video.date {
-            redis_client.set_last_video_pubdate(date).await?;
+            redis_client
+                .set_last_video_pubdate(&creator.id, date)
+                .await?;
         }
 
-        Ok(())
+        Ok(delivered)
     }
 
-    /// Fetch latest video job
-    async fn fetch_latest_unseen_instagram_post() -> anyhow::Result<()> {
-        let mut redis_client = RedisRepository::connect()?;
+    /// Render the announcement of `video` from `creator`, along with the topic it belongs to
+    fn video_announcement(creator: &Creator, video: &Entry) -> (Answer, Topic) {
+        let mut message = AnswerBuilder::default();
+        if let Some(thumbnail) = youtube::thumbnail(video) {
+            message = message.image(thumbnail);
+        }
+        let description = video
+            .description
+            .as_deref()
+            .map(|x| str_helpers::excerpt(x, DESCRIPTION_EXCERPT_LEN));
+        let kind = VideoKind::classify(video);
+        let template = match kind {
+            VideoKind::Video => Template::Video,
+            VideoKind::Short => Template::Short,
+            VideoKind::Premiere => Template::Premiere,
+            VideoKind::Live => Template::Live,
+        };
+        let mut text = RichText::default()
+            .plain(template.render(&[("persona", &creator.persona)]))
+            .link(video.title.as_deref().unwrap_or(&video.url), &video.url);
+        if let Some(description) = description {
+            text = text.plain("\n\n").italic(description);
+        }
+        let topic = match kind {
+            VideoKind::Short => Topic::Short,
+            VideoKind::Video | VideoKind::Premiere | VideoKind::Live => Topic::Video,
+        };
+        let message = message
+            .text(text)
+            .keyboard(keyboard::video(&video.url, &creator.id, Some(topic)))
+            .finalize();
+        (message, topic)
+    }
+
+    /// Render a sample video announcement from `creator`, to preview the message format
+    pub fn sample_announcement(creator: &Creator) -> Answer {
+        let video = Entry {
+            id: String::from("yt:video:rRQP8PNEouo"),
+            title: Some(String::from("Il mio armadio minimalista")),
+            authors: vec![creator.persona.clone()],
+            summary: String::new(),
+            url: String::from("https://www.youtube.com/watch?v=rRQP8PNEouo"),
+            date: Some(Utc::now()),
+            thumbnail: None,
+            description: Some(String::from(
+                "Questo è un messaggio di prova, per vedere come appaiono gli annunci dei nuovi video.",
+            )),
+            views: Some(1),
+            enclosure: None,
+        };
+        Self::video_announcement(creator, &video).0
+    }
+
+    /// Fetch latest instagram post job
+    async fn fetch_latest_unseen_instagram_post() -> anyhow::Result<usize> {
+        let (mut delivered, mut failures) = (0, Vec::new());
+        for creator in Creator::all()?.iter() {
+            if creator.instagram_account.is_some() {
+                match Self::fetch_latest_unseen_creator_instagram_post(creator).await {
+                    Ok(count) => delivered += count,
+                    Err(err) => {
+                        error!("failed to fetch latest post for {}: {}", creator.id, err);
+                        failures.push(format!("{}: {}", creator.id, err));
+                    }
+                }
+            }
+        }
+        Self::outcome(delivered, failures)
+    }
+
+    /// Fetch latest unseen instagram post for creator
+    async fn fetch_latest_unseen_creator_instagram_post(
+        creator: &Creator,
+    ) -> anyhow::Result<usize> {
+        let mut redis_client = RedisRepository::connect()?.dry_run(Self::is_dry_run());
         let last_post_pubdate = redis_client
-            .get_last_instagram_update()
+            .get_last_instagram_update(&creator.id)
             .await?
             .unwrap_or(UNIX_EPOCH);
-        let post = match InstagramService::get_oldest_unseen_post(last_post_pubdate).await {
+        let post = match InstagramService::get_oldest_unseen_post(creator, last_post_pubdate).await
+        {
             Ok(Some(v)) => v,
             Ok(None) => {
-                debug!("no unseen posts from instagram could be found");
-                return Ok(());
+                debug!("no unseen posts from {} could be found", creator.id);
+                return Ok(0);
             }
             Err(err) => {
                 anyhow::bail!("failed to check latest post: {}", err)
             }
         };
         debug!(
-                "last time I checked spazio-grigio posts, spazio-grigio post had date {:?}; latest has {:?}",
-                last_post_pubdate,
-                post.taken_at_timestamp
-            );
-        let bot = Bot::from_env().auto_send();
+            "last time I checked {} posts, post had date {:?}; latest has {:?}",
+            creator.id, last_post_pubdate, post.taken_at_timestamp
+        );
         info!(
-            "spazio grigio published a new ig post ({:?})",
-            post.taken_at_timestamp
+            "{} published a new ig post ({:?})",
+            creator.id, post.taken_at_timestamp
         );
         let message = AnswerBuilder::default()
             .text(format!(
-                "Ciao sono Irina. Ho appena pubblicato questo nuovo mio post su Instagram: {}",
-                post.caption.unwrap_or_default(),
+                "{}{}",
+                Template::Instagram.render(&[("persona", &creator.persona)]),
+                post.caption.as_deref().unwrap_or_default(),
+            ))
+            .image(&post.display_url)
+            .keyboard(keyboard::instagram_post(
+                &InstagramService::post_url(&post),
+                Some(Topic::Instagram),
             ))
-            .image(post.display_url)
             .finalize();
-        for chat in Self::subscribed_chats().await?.iter() {
-            debug!("sending new post notify to {}", chat);
-            if let Err(err) = message.clone().send(&bot, *chat).await {
-                error!("failed to send scheduled post notify to {}: {}", chat, err);
-            }
-        }
+        let delivered = Self::send_to_followers(creator, message, Topic::Instagram).await?;
+        Self::archive(
+            Content::new(
+                format!("instagram:{}", post.id),
+                Topic::Instagram,
+                Some(&creator.id),
+                format!("Nuovo post di {} su Instagram", creator.persona),
+                post.caption.as_deref().unwrap_or_default(),
+                DateTime::<Utc>::from(post.taken_at_timestamp),
+            )
+            .with_url(Some(InstagramService::post_url(&post)))
+            .with_image(Some(post.display_url.clone())),
+        )
+        .await;
         redis_client
-            .set_last_instagram_update(post.taken_at_timestamp)
+            .set_last_instagram_update(&creator.id, post.taken_at_timestamp)
             .await?;
 
-        Ok(())
+        Ok(delivered)
+    }
+
+    /// Fetch feed sources job
+    async fn fetch_feeds() -> anyhow::Result<usize> {
+        let (mut delivered, mut failures) = (0, Vec::new());
+        for source in FeedSource::all()?.iter() {
+            match Self::fetch_feed_source(source).await {
+                Ok(count) => delivered += count,
+                Err(err) => {
+                    error!("failed to fetch feed source {}: {}", source.id, err);
+                    failures.push(format!("{}: {}", source.id, err));
+                }
+            }
+        }
+        Self::outcome(delivered, failures)
+    }
+
+    /// Fetch oldest unseen entry from feed source and announce it.
+    /// If the source has never been fetched before, the watermark is set to its latest entry, without announcing anything
+    async fn fetch_feed_source(source: &FeedSource) -> anyhow::Result<usize> {
+        let mut redis_client = RedisRepository::connect()?.dry_run(Self::is_dry_run());
+        let entries = Feeds::get_entries(source).await?;
+        let last_pubdate = match redis_client.get_last_feed_pubdate(&source.id).await? {
+            Some(date) => date,
+            None => {
+                if let Some(date) = entries.iter().filter_map(|x| x.date).max() {
+                    info!("initializing watermark for {} to {}", source.id, date);
+                    redis_client.set_last_feed_pubdate(&source.id, date).await?;
+                }
+                return Ok(0);
+            }
+        };
+        let entry = match Feeds::oldest_unseen(entries, last_pubdate) {
+            Some(entry) => entry,
+            None => {
+                debug!("could not find any unseen entry from {}", source.id);
+                return Ok(0);
+            }
+        };
+        info!(
+            "{} published a new entry ({:?}): {}",
+            source.id,
+            entry.date,
+            entry.title.as_deref().unwrap_or_default()
+        );
+        let creator = match source.creator.as_deref() {
+            Some(creator) => Some(Creator::find(creator)?),
+            None => None,
+        };
+        let greeting = creator
+            .as_ref()
+            .map(|x| Template::Greeting.render(&[("persona", &x.persona)]))
+            .unwrap_or_default();
+        let (topic, template) = match entry.enclosure.as_ref() {
+            Some(_) => (Topic::Podcast, Template::Podcast),
+            None => (Topic::Blog, Template::Article),
+        };
+        let summary = entry
+            .description
+            .as_deref()
+            .or(Some(entry.summary.as_str()))
+            .filter(|x| !x.trim().is_empty())
+            .map(|x| str_helpers::excerpt(x, DESCRIPTION_EXCERPT_LEN));
+        let mut text = RichText::default()
+            .plain(format!("{}{}", greeting, template.render(&[])))
+            .bold(&source.name)
+            .plain(": ")
+            .link(entry.title.as_deref().unwrap_or(&entry.url), &entry.url);
+        if let Some(summary) = summary {
+            text = text.plain("\n\n").italic(summary);
+        }
+        let mut message = AnswerBuilder::default().text(text);
+        if let Some(enclosure) = entry.enclosure.as_ref() {
+            let performer = entry
+                .authors
+                .first()
+                .cloned()
+                .or_else(|| creator.as_ref().map(|x| x.persona.clone()))
+                .unwrap_or_else(|| source.name.clone());
+            message = message.audio(
+                &enclosure.url,
+                AudioMetadata {
+                    title: entry.title.clone(),
+                    performer: Some(performer),
+                    duration: enclosure.duration,
+                    size: enclosure.length,
+                },
+            );
+        }
+        let message = message.finalize();
+        let repository = Repository::connect().await?;
+        let chats = match creator.as_ref() {
+            Some(creator) => repository.get_followers(&creator.id, topic).await?,
+            None => repository.get_subscribers(topic).await?,
+        };
+        let delivered = Self::deliver(source.creator.as_deref(), &chats, message, topic).await;
+        Self::archive(
+            Content::new(
+                &entry.id,
+                topic,
+                source.creator.as_deref(),
+                entry.title.as_deref().unwrap_or_default(),
+                &entry.summary,
+                entry.date.unwrap_or_else(Utc::now),
+            )
+            .with_url(Some(entry.url.clone()))
+            .with_image(entry.thumbnail.clone()),
+        )
+        .await;
+        if let Some(date) = entry.date {
+            redis_client.set_last_feed_pubdate(&source.id, date).await?;
+        }
+
+        Ok(delivered)
+    }
+
+    /// Send `answer` to all the chats following `creator`, which haven't opted out from `topic`.
+    /// Returns the amount of delivered messages
+    async fn send_to_followers(
+        creator: &Creator,
+        answer: Answer,
+        topic: Topic,
+    ) -> anyhow::Result<usize> {
+        let repository = Repository::connect().await?;
+        let chats = repository.get_followers(&creator.id, topic).await?;
+        Ok(Self::deliver(Some(&creator.id), &chats, answer, topic).await)
+    }
+
+    /// Archive announced content. Failures are only logged, since the content has already been announced
+    async fn archive(content: Content) {
+        if Self::is_dry_run() {
+            debug!("dry run: not archiving {}", content.id());
+            return;
+        }
+        let result = match Repository::connect().await {
+            Ok(repository) => repository.archive(content.clone()).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(()) => Inline::cache(content),
+            Err(err) => error!("failed to archive content: {}", err),
+        }
+    }
+
+    /// Deliver `answer` about `topic` to `chats`, to the announcement channel, if configured,
+    /// and to the delivery targets subscribed to `creator` (all the targets if `None`).
+    /// Returns the amount of targets `answer` has been delivered to
+    async fn deliver(
+        creator: Option<&str>,
+        chats: &[ChatId],
+        answer: Answer,
+        topic: Topic,
+    ) -> usize {
+        let targets = Self::delivery_targets(creator, chats, topic).await;
+        if Self::is_dry_run() {
+            let names: Vec<String> = targets.iter().map(|x| x.name()).collect();
+            Self::print_dry_run(&topic.to_string(), &names, &answer);
+            return targets.len();
+        }
+        let mut delivered = 0;
+        for target in targets.iter() {
+            debug!("sending new {} notify to {}", topic, target.name());
+            match target.deliver(&answer).await {
+                Ok(()) => delivered += 1,
+                Err(err) => error!(
+                    "failed to send scheduled {} notify to {}: {}",
+                    topic,
+                    target.name(),
+                    err
+                ),
+            }
+        }
+        delivered
+    }
+
+    /// Collect the delivery targets for an announcement about `topic` from `creator` to `chats`.
+    /// Email targets are excluded, since they receive the daily digest instead
+    async fn delivery_targets(
+        creator: Option<&str>,
+        chats: &[ChatId],
+        topic: Topic,
+    ) -> Vec<Box<dyn DeliveryTarget>> {
+        let config = match Config::load() {
+            Ok(config) => config,
+            Err(err) => {
+                error!("could not load configuration: {}", err);
+                return Vec::new();
+            }
+        };
+        let bot = match config.bot() {
+            Ok(bot) => bot,
+            Err(err) => {
+                error!("could not create bot: {}", err);
+                return Vec::new();
+            }
+        };
+        let mut targets: Vec<Box<dyn DeliveryTarget>> = Vec::with_capacity(chats.len() + 1);
+        match config.announcement_channel() {
+            Ok(Some(channel)) => {
+                targets.push(Box::new(TelegramTarget::channel(bot.clone(), channel)))
+            }
+            Ok(None) => {}
+            Err(err) => error!("could not get announcement channel: {}", err),
+        }
+        for chat in chats.iter() {
+            targets.push(Box::new(TelegramTarget::chat(bot.clone(), *chat)));
+        }
+        let subscribed = match Repository::connect().await {
+            Ok(repository) => {
+                repository
+                    .get_subscribed_targets(creator, Some(topic))
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        match subscribed {
+            Ok(subscribed) => {
+                for (kind, address) in subscribed
+                    .into_iter()
+                    .filter(|(kind, _)| *kind != TargetKind::Email)
+                {
+                    match kind.target(&address) {
+                        Ok(target) => targets.push(target),
+                        Err(err) => error!(
+                            "could not setup {} target {}: {}",
+                            kind,
+                            kind.redact(&address),
+                            err
+                        ),
+                    }
+                }
+            }
+            Err(err) => error!("could not collect subscribed delivery targets: {}", err),
+        }
+        targets
+    }
+
+    /// Send the digest of the contents archived since the last digest to the email subscribers.
+    /// On the first run, the digest date is initialized without sending anything
+    async fn send_email_digest() -> anyhow::Result<usize> {
+        let mut redis_client = RedisRepository::connect()?.dry_run(Self::is_dry_run());
+        let now = Utc::now();
+        let last_digest = match redis_client.get_last_email_digest().await? {
+            Some(date) => date,
+            None => {
+                info!("initializing email digest date to {}", now);
+                return redis_client.set_last_email_digest(now).await.map(|_| 0);
+            }
+        };
+        let config = Config::load()?;
+        let repository = Repository::connect().await?;
+        let contents = repository.get_archived_since(last_digest).await?;
+        let (mut delivered, mut failures) = (0, Vec::new());
+        for (address, follows) in repository
+            .get_target_subscriptions(TargetKind::Email)
+            .await?
+            .into_iter()
+        {
+            let contents: Vec<&Content> = contents
+                .iter()
+                .filter(|x| follows.iter().any(|f| f.wants(x.creator(), x.topic())))
+                .collect();
+            if contents.is_empty() {
+                debug!("nothing new for {}", address);
+                continue;
+            }
+            if Self::is_dry_run() {
+                Self::print_dry_run(
+                    "email digest",
+                    &[format!("email {}", address)],
+                    &Self::email_digest(&contents),
+                );
+                delivered += 1;
+                continue;
+            }
+            let result = match SmtpConfig::try_from(config) {
+                Ok(smtp) => {
+                    EmailTarget::new(smtp, &address)
+                        .subject(EMAIL_DIGEST_SUBJECT)
+                        .deliver(&Self::email_digest(&contents))
+                        .await
+                }
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(()) => delivered += 1,
+                Err(err) => {
+                    error!("failed to send email digest to {}: {}", address, err);
+                    failures.push(format!("{}: {}", address, err));
+                }
+            }
+        }
+        redis_client.set_last_email_digest(now).await?;
+        Self::outcome(delivered, failures)
+    }
+
+    /// Print the announcement about `what` which would be delivered to `targets` in dry run
+    fn print_dry_run(what: &str, targets: &[String], answer: &Answer) {
+        println!(
+            "[dry run] {} to {} targets: {}\n{}\n",
+            what,
+            targets.len(),
+            targets.join(", "),
+            answer.describe()
+        );
+    }
+
+    /// Compose the email digest of `contents`
+    fn email_digest(contents: &[&Content]) -> Answer {
+        let mut digest = AnswerBuilder::default().text("Ciao! Ecco le ultime novità:");
+        for content in contents.iter() {
+            digest = digest.text(format!(
+                "• {}\n{}",
+                content.title(),
+                content.url().unwrap_or_else(|| content.summary())
+            ));
+        }
+        digest.finalize()
     }
 
     pub async fn subscribed_chats() -> anyhow::Result<Vec<ChatId>> {
@@ -282,3 +1001,73 @@ impl Drop for Automatizer {
         });
     }
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::irina::harness::{environment, lock_jobs};
+
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn should_announce_each_newsletter_once() {
+        let environment = environment().await;
+        let _jobs = lock_jobs().await;
+        let chat = ChatId(3001);
+        Repository::connect()
+            .await
+            .unwrap()
+            .insert_chat(chat, Creator::all().unwrap())
+            .await
+            .unwrap();
+        let creator = Creator::find("minimalista").unwrap();
+        let sender = "newsletter@minimalista.it";
+        let mut newsletter = Newsletter::connect().await.unwrap();
+        let announced = || async {
+            environment
+                .api
+                .calls_to(chat)
+                .await
+                .into_iter()
+                .map(|x| x.text().to_string())
+                .filter(|x| x.starts_with("Ciao sono Mario."))
+                .collect::<Vec<String>>()
+        };
+        assert!(
+            Automatizer::fetch_latest_creator_newsletter(&mut newsletter, &creator, sender)
+                .await
+                .unwrap()
+                >= 1
+        );
+        let messages = announced().await;
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Perché possedere meno"));
+        assert!(messages[0].contains("Possedere meno è più facile"));
+        // the same newsletter is not announced twice
+        assert_eq!(
+            Automatizer::fetch_latest_creator_newsletter(&mut newsletter, &creator, sender)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(announced().await.len(), 1);
+        // a newer one is
+        environment.imap.deliver(
+            b"From: Minimalismo <newsletter@minimalista.it>\r\n\
+            Subject: Meno cose, piu tempo\r\n\
+            Date: Mon, 05 Sep 2022 10:00:00 +0000\r\n\
+            \r\n\
+            Un nuovo numero della newsletter.\r\n",
+        );
+        assert!(
+            Automatizer::fetch_latest_creator_newsletter(&mut newsletter, &creator, sender)
+                .await
+                .unwrap()
+                >= 1
+        );
+        let messages = announced().await;
+        assert_eq!(messages.len(), 2);
+        assert!(messages[1].contains("Meno cose, piu tempo"));
+    }
+}
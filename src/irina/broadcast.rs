@@ -0,0 +1,326 @@
+//! # Broadcast
+//!
+//! Custom announcements composed by the admins and sent to every subscribed chat
+
+use super::keyboard;
+use super::repository::Repository;
+use super::{Answer, AnswerBuilder, RichText};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::UserId;
+
+/// Time to wait for the other photos of an album before sending the preview
+const ALBUM_WAIT: Duration = Duration::from_secs(2);
+
+/// A message the admin sent to compose the broadcast
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DraftItem {
+    Text(RichText),
+    Photo {
+        file_id: String,
+        caption: Option<RichText>,
+        /// Id of the album the photo belongs to
+        media_group: Option<String>,
+    },
+}
+
+impl DraftItem {
+    /// Get the draft item from message, keeping its formatting; `None` if the message is neither a text nor a photo
+    pub fn from_message(message: &Message) -> Option<Self> {
+        if let Some(text) = message.text() {
+            return Some(Self::Text(RichText::from_entities(
+                text,
+                message.entities().unwrap_or_default(),
+            )));
+        }
+        // the last size is the biggest one
+        let photo = message.photo()?.last()?;
+        Some(Self::Photo {
+            file_id: photo.file_id.clone(),
+            caption: message.caption().map(|caption| {
+                RichText::from_entities(caption, message.caption_entities().unwrap_or_default())
+            }),
+            media_group: message.media_group_id().map(|x| x.to_string()),
+        })
+    }
+}
+
+/// What to do after adding an item to the draft
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DraftUpdate {
+    /// The draft is complete: send the preview
+    Preview,
+    /// The first photo of an album: send the preview once the other photos have been received
+    AlbumStarted,
+    /// Another photo of the album
+    AlbumContinued,
+}
+
+/// The broadcast being composed by an admin
+#[derive(Debug, Default, Clone)]
+struct Draft {
+    text: Option<RichText>,
+    photos: Vec<String>,
+    caption: Option<RichText>,
+    media_group: Option<String>,
+}
+
+impl Draft {
+    /// Add item to draft. An item which doesn't belong to the album being received replaces the draft
+    fn add(&mut self, item: DraftItem) -> DraftUpdate {
+        match item {
+            DraftItem::Photo {
+                file_id,
+                caption,
+                media_group: Some(media_group),
+            } if self.media_group.as_deref() == Some(media_group.as_str()) => {
+                self.photos.push(file_id);
+                if self.caption.is_none() {
+                    self.caption = caption;
+                }
+                DraftUpdate::AlbumContinued
+            }
+            DraftItem::Photo {
+                file_id,
+                caption,
+                media_group,
+            } => {
+                let update = match media_group {
+                    Some(_) => DraftUpdate::AlbumStarted,
+                    None => DraftUpdate::Preview,
+                };
+                *self = Self {
+                    text: None,
+                    photos: vec![file_id],
+                    caption,
+                    media_group,
+                };
+                update
+            }
+            DraftItem::Text(text) => {
+                *self = Self {
+                    text: Some(text),
+                    ..Default::default()
+                };
+                DraftUpdate::Preview
+            }
+        }
+    }
+
+    /// Get the answer to broadcast; `None` if nothing has been composed yet
+    fn answer(&self) -> Option<Answer> {
+        if let Some(text) = self.text.clone() {
+            return Some(AnswerBuilder::default().text(text).finalize());
+        }
+        if self.photos.is_empty() {
+            return None;
+        }
+        Some(
+            AnswerBuilder::default()
+                .album(self.photos.clone(), self.caption.clone())
+                .finalize(),
+        )
+    }
+}
+
+/// The broadcasts being composed by the admins, by chat and admin,
+/// so that admins composing in the same chat don't replace each other's draft
+#[derive(Clone, Default)]
+pub struct Broadcasts {
+    drafts: Arc<Mutex<HashMap<(ChatId, UserId), Draft>>>,
+}
+
+impl Broadcasts {
+    /// Start composing a broadcast by user in chat
+    pub fn start(&self, chat: ChatId, user: UserId) {
+        self.drafts
+            .lock()
+            .unwrap()
+            .insert((chat, user), Draft::default());
+    }
+
+    /// Check whether user is composing a broadcast in chat
+    pub fn is_composing(&self, chat: ChatId, user: UserId) -> bool {
+        self.drafts.lock().unwrap().contains_key(&(chat, user))
+    }
+
+    /// Add item to the broadcast being composed by user in chat
+    pub fn add(&self, chat: ChatId, user: UserId, item: DraftItem) -> Option<DraftUpdate> {
+        self.drafts
+            .lock()
+            .unwrap()
+            .get_mut(&(chat, user))
+            .map(|draft| draft.add(item))
+    }
+
+    /// Get the answer composed by user in chat
+    pub fn answer(&self, chat: ChatId, user: UserId) -> Option<Answer> {
+        self.drafts
+            .lock()
+            .unwrap()
+            .get(&(chat, user))
+            .and_then(|draft| draft.answer())
+    }
+
+    /// Stop composing the broadcast of user in chat, returning the composed answer
+    pub fn take(&self, chat: ChatId, user: UserId) -> Option<Answer> {
+        self.drafts
+            .lock()
+            .unwrap()
+            .remove(&(chat, user))
+            .and_then(|draft| draft.answer())
+    }
+
+    /// Send the preview of the broadcast composed by user in chat, followed by the confirmation buttons
+    pub async fn send_preview(
+        &self,
+        bot: &AutoSend<Bot>,
+        chat: ChatId,
+        user: UserId,
+    ) -> anyhow::Result<()> {
+        let answer = match self.answer(chat, user) {
+            Some(answer) => answer,
+            None => return Ok(()),
+        };
+        let chats = Repository::connect()
+            .await?
+            .get_subscribed_chats()
+            .await?
+            .len();
+        answer
+            .send(bot, chat)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to send broadcast preview: {}", e))?;
+        AnswerBuilder::default()
+            .text(format!(
+                "Questa è l'anteprima. Confermi l'invio a {} chat?",
+                chats
+            ))
+            .keyboard(keyboard::broadcast())
+            .finalize()
+            .send(bot, chat)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to send broadcast confirmation: {}", e))
+    }
+
+    /// Send the preview of the album composed by user in chat, once all the photos have been received
+    pub fn send_album_preview(&self, bot: AutoSend<Bot>, chat: ChatId, user: UserId) {
+        let broadcasts = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(ALBUM_WAIT).await;
+            if let Err(err) = broadcasts.send_preview(&bot, chat, user).await {
+                error!("{}", err);
+            }
+        });
+    }
+}
+
+/// Send answer to every subscribed chat. Returns the amount of chats the answer has been delivered to and failed for
+pub async fn send(bot: &AutoSend<Bot>, answer: Answer) -> anyhow::Result<(usize, usize)> {
+    let chats = Repository::connect().await?.get_subscribed_chats().await?;
+    info!("broadcasting to {} chats", chats.len());
+    let (mut delivered, mut failed) = (0, 0);
+    for chat in chats.into_iter() {
+        match answer.clone().send(bot, chat).await {
+            Ok(()) => delivered += 1,
+            Err(err) => {
+                error!("failed to broadcast to {}: {}", chat, err);
+                failed += 1;
+            }
+        }
+    }
+    Ok((delivered, failed))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_compose_text_broadcast() {
+        let mut draft = Draft::default();
+        assert!(draft.answer().is_none());
+        assert_eq!(
+            draft.add(DraftItem::Text(
+                RichText::default().bold("Live Q&A stasera")
+            )),
+            DraftUpdate::Preview
+        );
+        assert_eq!(
+            draft.answer().unwrap().plain_text().as_str(),
+            "Live Q&A stasera"
+        );
+        assert_eq!(
+            draft.answer().unwrap().describe().as_str(),
+            "[text] <b>Live Q&amp;A stasera</b>"
+        );
+    }
+
+    #[test]
+    fn should_compose_album_broadcast() {
+        let mut draft = Draft::default();
+        assert_eq!(
+            draft.add(photo("1", Some("Il mio evento"), Some("a"))),
+            DraftUpdate::AlbumStarted
+        );
+        assert_eq!(
+            draft.add(photo("2", None, Some("a"))),
+            DraftUpdate::AlbumContinued
+        );
+        assert_eq!(draft.photos, vec![String::from("1"), String::from("2")]);
+        assert_eq!(
+            draft.answer().unwrap().plain_text().as_str(),
+            "Il mio evento"
+        );
+        // a photo of another album replaces the draft
+        assert_eq!(
+            draft.add(photo("3", None, Some("b"))),
+            DraftUpdate::AlbumStarted
+        );
+        assert_eq!(draft.photos, vec![String::from("3")]);
+        // a single photo replaces the draft
+        assert_eq!(draft.add(photo("4", None, None)), DraftUpdate::Preview);
+        assert_eq!(draft.photos, vec![String::from("4")]);
+        assert!(draft.caption.is_none());
+    }
+
+    #[test]
+    fn should_start_and_take_broadcast() {
+        let broadcasts = Broadcasts::default();
+        let chat = ChatId(1234);
+        let (user, other) = (UserId(1), UserId(2));
+        assert!(!broadcasts.is_composing(chat, user));
+        assert!(broadcasts
+            .add(chat, user, DraftItem::Text(RichText::from("ciao")))
+            .is_none());
+        broadcasts.start(chat, user);
+        assert!(broadcasts.is_composing(chat, user));
+        assert!(broadcasts.answer(chat, user).is_none());
+        assert_eq!(
+            broadcasts.add(chat, user, DraftItem::Text(RichText::from("ciao"))),
+            Some(DraftUpdate::Preview)
+        );
+        // drafts are kept by admin
+        assert!(!broadcasts.is_composing(chat, other));
+        broadcasts.start(chat, other);
+        assert!(broadcasts.answer(chat, other).is_none());
+        assert!(broadcasts.take(chat, user).is_some());
+        assert!(!broadcasts.is_composing(chat, user));
+        assert!(broadcasts.take(chat, user).is_none());
+        assert!(broadcasts.is_composing(chat, other));
+    }
+
+    fn photo(file_id: &str, caption: Option<&str>, media_group: Option<&str>) -> DraftItem {
+        DraftItem::Photo {
+            file_id: file_id.to_string(),
+            caption: caption.map(RichText::from),
+            media_group: media_group.map(|x| x.to_string()),
+        }
+    }
+}
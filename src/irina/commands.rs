@@ -16,20 +16,91 @@ pub enum Command {
         description = "disinscriviti dalla newsletter di Spazio Grigio e rinnega tutti i tuoi valori morali"
     )]
     SiAlConsumismo,
+    #[command(description = "visualizza chi puoi seguire")]
+    Creatori,
+    #[command(description = "ricevi gli aggiornamenti di un creator. Uso: /segui <creator>")]
+    Segui(String),
+    #[command(
+        description = "smetti di ricevere gli aggiornamenti di un creator. Uso: /nonseguire <creator>"
+    )]
+    NonSeguire(String),
+    #[command(
+        description = "inoltra gli aggiornamenti dei creator che segui su matrix, discord o email (riepilogo giornaliero). Uso: /inoltra <matrix|discord|email> <stanza|webhook|indirizzo> [codice di conferma]"
+    )]
+    Inoltra(String),
+    #[command(
+        description = "smetti di inoltrare gli aggiornamenti. Uso: /noninoltrare <matrix|discord|email> <stanza|webhook|indirizzo>"
+    )]
+    NonInoltrare(String),
+    #[command(description = "non ricevere più le notifiche degli short")]
+    NienteShorts,
+    #[command(description = "ricevi di nuovo le notifiche degli short")]
+    VoglioShorts,
     #[command(
         description = "comincia nel modo più minimalista la tua giornata con un video della mia morning routine"
     )]
     BuongiornoIrina,
     #[command(description = "ottieni il link al mio ultimo post instagram")]
-    PostMinimalista,
+    PostMinimalista(String),
     #[command(description = "ottieni il link al mio ultimo video")]
-    VideoMinimalista,
+    VideoMinimalista(String),
     #[command(
         description = "una vita nel minimalismo è una vita senza TV. Per fortuna c'è spazio grigio"
     )]
-    SerataSenzaTv,
+    SerataSenzaTv(String),
     #[command(description = "Dai inizio al tuo percorso verso il minimalismo")]
     Start,
     #[command(description = "visualizza l'aiuto")]
     Help,
 }
+
+#[derive(BotCommands, Clone, Debug)]
+#[command(
+    rename = "lowercase",
+    description = "Comandi di amministrazione, riservati agli operatori del bot:"
+)]
+pub enum AdminCommand {
+    #[command(description = "visualizza le statistiche degli iscritti")]
+    Stats,
+    #[command(description = "visualizza lo stato dell'ultima esecuzione di ogni job")]
+    Jobs,
+    #[command(description = "esegui subito un job. Uso: /runjob <job>")]
+    RunJob(String),
+    #[command(
+        description = "azzera il watermark di una sorgente. Uso: /resetwatermark <video|instagram|newsletter> <creator>, /resetwatermark feed <sorgente> oppure /resetwatermark digest"
+    )]
+    ResetWatermark(String),
+    #[command(
+        description = "invia un messaggio (testo, foto o album) a tutte le chat iscritte, dopo un'anteprima"
+    )]
+    Broadcast,
+    #[command(description = "visualizza i comandi di amministrazione")]
+    Admin,
+}
+
+/// Get the name of the command in `text`, without the leading slash and the bot username
+pub fn command_name(text: &str) -> Option<&str> {
+    text.split_whitespace()
+        .next()?
+        .strip_prefix('/')?
+        .split('@')
+        .next()
+        .filter(|x| !x.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_get_command_name() {
+        assert_eq!(command_name("/segui spaziogrigio"), Some("segui"));
+        assert_eq!(command_name("/help@spaziogrigio_bot"), Some("help"));
+        assert_eq!(command_name("ciao irina"), None);
+        assert_eq!(command_name("/"), None);
+        assert_eq!(command_name(""), None);
+    }
+}
@@ -1,25 +1,806 @@
 //! # Config
 //!
-//! spazio-grigio-bot configuration
+//! spazio-grigio-bot configuration.
+//! The configuration is read from an optional TOML file and from the environment, which overrides the file
 
-#[derive(Debug, Deserialize, Serialize)]
+use super::creator::Creator;
+use super::feeds::FeedSource;
+use super::job::AutomatizerJob;
+use super::template::Template;
+use crate::mail::ImapSecurity;
+
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use teloxide::adaptors::AutoSend;
+use teloxide::requests::RequesterExt;
+use teloxide::types::{ChatId, Recipient, UserId};
+use teloxide::Bot;
+use tokio_cron_scheduler::Job;
+use url::Url;
+
+/// Config file path set from the command line; takes precedence over `CONFIG_FILE`
+static CONFIG_FILE: OnceCell<PathBuf> = OnceCell::new();
+/// Configuration loaded and validated at startup
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+/// Environment variable with the path of the config file
+const CONFIG_FILE_ENV: &str = "CONFIG_FILE";
+/// Prefix of the environment variables overriding the job schedules
+const SCHEDULE_ENV_PREFIX: &str = "SCHEDULE_";
+/// Prefix of the environment variables overriding the message templates
+const TEMPLATE_ENV_PREFIX: &str = "TEMPLATE_";
+/// Config file table with the job schedules
+const SCHEDULES_TABLE: &str = "schedules";
+/// Config file table with the message templates
+const TEMPLATES_TABLE: &str = "templates";
+
+/// Default consecutive failures of a job after which the admins are alerted
+const DEFAULT_JOB_FAILURE_THRESHOLD: usize = 3;
+/// Default path telegram sends the updates to, in webhook mode
+const DEFAULT_WEBHOOK_PATH: &str = "/webhook";
+/// Default port the webhooks server listens on
+const DEFAULT_WEBHOOK_PORT: u16 = 8443;
+/// Keys of the config which must be set
+const REQUIRED_KEYS: [&str; 9] = [
+    "database_url",
+    "email_address",
+    "email_password",
+    "imap_server",
+    "imap_port",
+    "instagram_password",
+    "instagram_username",
+    "redis_url",
+    "teloxide_token",
+];
+/// Keys of the config which can be omitted
+const OPTIONAL_KEYS: [&str; 24] = [
+    "admin_ids",
+    "announcement_channel",
+    "creators",
+    "feed_sources",
+    "host",
+    "http_address",
+    "imap_security",
+    "job_failure_threshold",
+    "matrix_access_token",
+    "matrix_homeserver",
+    "port",
+    "smtp_password",
+    "smtp_port",
+    "smtp_sender",
+    "smtp_server",
+    "smtp_starttls",
+    "smtp_username",
+    "telegram_api_url",
+    "webhook_address",
+    "webhook_certificate",
+    "webhook_path",
+    "webhook_secret_token",
+    "webhook_url",
+    "youtube_url",
+];
+/// Max length of the webhook secret token
+const WEBHOOK_SECRET_TOKEN_MAX_LEN: usize = 256;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 /// Application config
 pub struct Config {
+    /// Comma separated ids of the telegram users allowed to use the admin commands
+    pub admin_ids: Option<String>,
+    /// Telegram channel (id or @username) where all the announcements are mirrored
+    pub announcement_channel: Option<String>,
+    /// JSON list of the creators followed by the bot
+    pub creators: Option<String>,
     pub database_url: String,
     pub email_address: String,
     pub email_password: String,
+    /// JSON list of the RSS/Atom feed sources polled by the automatizer
+    pub feed_sources: Option<String>,
+    /// Public host of the heroku app; used with `PORT` when `WEBHOOK_URL` is unset
+    pub host: Option<String>,
+    /// Address to bind the http server to when running without webhooks (e.g. `0.0.0.0:8080`)
+    pub http_address: Option<String>,
+    /// Security of the connection to the imap server: `tls`, `starttls` or `none`; tls if unset
+    pub imap_security: Option<String>,
     pub imap_server: String,
     pub imap_port: u16,
     pub instagram_password: String,
     pub instagram_username: String,
+    /// Consecutive failures of a job after which the admins are alerted; 3 if unset
+    pub job_failure_threshold: Option<usize>,
+    /// Access token of the matrix account announcements are delivered with
+    pub matrix_access_token: Option<String>,
+    /// Url of the matrix homeserver (e.g. `https://matrix.org`)
+    pub matrix_homeserver: Option<String>,
+    /// Port assigned by heroku; enables webhooks on `HOST` when `WEBHOOK_URL` is unset
+    pub port: Option<u16>,
     pub redis_url: String,
+    pub smtp_password: Option<String>,
+    /// SMTP port; 587 if unset
+    pub smtp_port: Option<u16>,
+    /// Address the email digests are sent from; `EMAIL_ADDRESS` if unset
+    pub smtp_sender: Option<String>,
+    /// SMTP server the email digests are sent with
+    pub smtp_server: Option<String>,
+    /// Whether to use STARTTLS; true if unset
+    pub smtp_starttls: Option<bool>,
+    pub smtp_username: Option<String>,
+    /// Url of the telegram Bot API (e.g. a local Bot API server); api.telegram.org if unset
+    pub telegram_api_url: Option<String>,
     pub teloxide_token: String,
+    /// Address the webhooks server binds to (e.g. `0.0.0.0:8443`); `0.0.0.0:$PORT` or `0.0.0.0:8443` if unset
+    pub webhook_address: Option<String>,
+    /// Path to the PEM public key certificate uploaded to telegram, when using a self-signed certificate
+    pub webhook_certificate: Option<String>,
+    /// Path telegram sends the updates to; `/webhook` if unset
+    pub webhook_path: Option<String>,
+    /// Secret token telegram sends in the `X-Telegram-Bot-Api-Secret-Token` header; generated if unset
+    pub webhook_secret_token: Option<String>,
+    /// Public url of the bot (e.g. `https://bot.example.com`). Enables the webhook mode
+    pub webhook_url: Option<String>,
+    /// Url to fetch youtube feeds from; youtube.com if unset
+    pub youtube_url: Option<String>,
+    /// Cron schedules of the jobs, by job name; the default schedule is used for the missing jobs
+    #[serde(skip)]
+    pub schedules: HashMap<String, String>,
+    /// Message templates, by template name; the default text is used for the missing templates
+    #[serde(skip)]
+    pub templates: HashMap<String, String>,
+}
+
+/// Webhook mode configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookConfig {
+    /// Public url telegram sends the updates to
+    pub url: Url,
+    /// Address the webhooks server binds to
+    pub address: SocketAddr,
+    /// Secret token validated on the incoming updates; generated if `None`
+    pub secret_token: Option<String>,
+    /// Self-signed public key certificate to upload to telegram
+    pub certificate: Option<PathBuf>,
 }
 
 impl Config {
-    /// Try to create config from env
-    pub fn try_from_env() -> anyhow::Result<Self> {
-        envy::from_env()
-            .map_err(|e| anyhow::anyhow!("could not load config from environment: {}", e))
+    /// Set the path of the config file, overriding `CONFIG_FILE`
+    pub fn set_file(path: PathBuf) -> anyhow::Result<()> {
+        CONFIG_FILE
+            .set(path)
+            .map_err(|_| anyhow::anyhow!("config file is already set"))
+    }
+
+    /// Load and validate config from the config file, if any, and from the environment.
+    /// The config is kept for the following calls to `load`
+    pub fn init() -> Result<&'static Self, Vec<String>> {
+        CONFIG.get_or_try_init(Self::check)
+    }
+
+    /// Get the config loaded with `init`
+    pub fn load() -> anyhow::Result<&'static Self> {
+        CONFIG
+            .get()
+            .ok_or_else(|| anyhow::anyhow!("configuration has not been loaded"))
+    }
+
+    /// Load and validate config, reporting all the problems found
+    pub fn check() -> Result<Self, Vec<String>> {
+        let config = Self::read(Self::file().as_deref(), std::env::vars())?;
+        let problems = config.validate();
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Get the path of the config file, from the command line or from `CONFIG_FILE`
+    fn file() -> Option<PathBuf> {
+        CONFIG_FILE
+            .get()
+            .cloned()
+            .or_else(|| std::env::var_os(CONFIG_FILE_ENV).map(PathBuf::from))
+    }
+
+    /// Read config from `file` and from `env`, which overrides the file
+    fn read(
+        file: Option<&Path>,
+        env: impl Iterator<Item = (String, String)>,
+    ) -> Result<Self, Vec<String>> {
+        let table = match file {
+            Some(file) => {
+                let content = std::fs::read_to_string(file)
+                    .map_err(|e| vec![format!("could not read {}: {}", file.display(), e)])?;
+                toml::from_str::<toml::value::Table>(&content)
+                    .map_err(|e| vec![format!("invalid syntax in {}: {}", file.display(), e)])?
+            }
+            None => toml::value::Table::new(),
+        };
+        Self::merge(table, env)
+    }
+
+    /// Merge the config file `table` with `env`, which overrides the file
+    fn merge(
+        mut table: toml::value::Table,
+        env: impl Iterator<Item = (String, String)>,
+    ) -> Result<Self, Vec<String>> {
+        let mut problems = Vec::new();
+        let mut schedules = Self::string_table(&mut table, SCHEDULES_TABLE, &mut problems);
+        let mut templates = Self::string_table(&mut table, TEMPLATES_TABLE, &mut problems);
+        let keys = Self::keys();
+        let mut vars = HashMap::new();
+        for (key, value) in table.into_iter() {
+            if !keys.contains(&key) {
+                problems.push(format!("unknown key {} in config file", key));
+                continue;
+            }
+            let value = match value {
+                toml::Value::String(value) => value,
+                // a list of ids, as in the comma separated environment variable
+                toml::Value::Array(ids) if key == "admin_ids" => ids
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>()
+                    .join(","),
+                // creators and feed sources are a JSON list in the environment
+                value @ toml::Value::Array(_) => match serde_json::to_string(&value) {
+                    Ok(json) => json,
+                    Err(err) => {
+                        problems.push(format!("invalid {}: {}", key, err));
+                        continue;
+                    }
+                },
+                value => value.to_string(),
+            };
+            vars.insert(key, value);
+        }
+        for (key, value) in env {
+            if let Some(job) = key.strip_prefix(SCHEDULE_ENV_PREFIX) {
+                schedules.insert(job.to_lowercase(), value);
+            } else if let Some(template) = key.strip_prefix(TEMPLATE_ENV_PREFIX) {
+                templates.insert(template.to_lowercase(), value);
+            } else if keys.contains(&key.to_lowercase()) {
+                vars.insert(key.to_lowercase(), value);
+            }
+        }
+        for key in Self::required_keys() {
+            if !vars.contains_key(&key) {
+                problems.push(format!("{} is not set", key.to_uppercase()));
+            }
+        }
+        if !problems.is_empty() {
+            // report the schedules and templates problems too, since they don't need the config
+            problems.extend(validate_schedules(&schedules));
+            problems.extend(validate_templates(&templates));
+            return Err(problems);
+        }
+        let mut config: Self =
+            envy::from_iter(vars.into_iter().map(|(k, v)| (k.to_uppercase(), v)))
+                .map_err(|e| vec![format!("could not load config: {}", e)])?;
+        config.schedules = schedules;
+        config.templates = templates;
+        Ok(config)
+    }
+
+    /// Take the table `name` of strings out of `table`
+    fn string_table(
+        table: &mut toml::value::Table,
+        name: &str,
+        problems: &mut Vec<String>,
+    ) -> HashMap<String, String> {
+        match table.remove(name) {
+            None => HashMap::new(),
+            Some(toml::Value::Table(entries)) => entries
+                .into_iter()
+                .filter_map(|(key, value)| match value {
+                    toml::Value::String(value) => Some((key, value)),
+                    _ => {
+                        problems.push(format!("{}.{} must be a string", name, key));
+                        None
+                    }
+                })
+                .collect(),
+            Some(_) => {
+                problems.push(format!("{} must be a table", name));
+                HashMap::new()
+            }
+        }
+    }
+
+    /// Get the keys of the config
+    fn keys() -> Vec<String> {
+        REQUIRED_KEYS
+            .iter()
+            .chain(OPTIONAL_KEYS.iter())
+            .map(|x| x.to_string())
+            .collect()
+    }
+
+    /// Get the keys which must be set
+    fn required_keys() -> Vec<String> {
+        REQUIRED_KEYS.iter().map(|x| x.to_string()).collect()
+    }
+
+    /// Validate config, returning all the problems found
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check = |result: anyhow::Result<()>| {
+            if let Err(err) = result {
+                problems.push(err.to_string());
+            }
+        };
+        check(self.admin_ids().map(|_| ()));
+        check(self.announcement_channel().map(|_| ()));
+        check(self.webhook().map(|_| ()));
+        check(Creator::from_config(self).map(|_| ()));
+        check(FeedSource::from_config(self).map(|_| ()));
+        check(self.bot().map(|_| ()));
+        check(self.imap_security().map(|_| ()));
+        if let Some(address) = self.http_address.as_deref() {
+            check(
+                address
+                    .parse::<SocketAddr>()
+                    .map(|_| ())
+                    .map_err(|e| anyhow::anyhow!("invalid HTTP_ADDRESS {}: {}", address, e)),
+            );
+        }
+        if let Some(url) = self.youtube_url.as_deref() {
+            check(
+                Url::parse(url)
+                    .map(|_| ())
+                    .map_err(|e| anyhow::anyhow!("invalid YOUTUBE_URL {}: {}", url, e)),
+            );
+        }
+        if self.job_failure_threshold == Some(0) {
+            check(Err(anyhow::anyhow!(
+                "JOB_FAILURE_THRESHOLD must be greater than 0"
+            )));
+        }
+        problems.extend(validate_schedules(&self.schedules));
+        problems.extend(validate_templates(&self.templates));
+        problems
+    }
+
+    /// Create the telegram bot with the configured token and Bot API url
+    pub fn bot(&self) -> anyhow::Result<AutoSend<Bot>> {
+        let bot = Bot::new(&self.teloxide_token);
+        let bot = match self.telegram_api_url.as_deref() {
+            Some(url) => bot.set_api_url(
+                Url::parse(url)
+                    .map_err(|e| anyhow::anyhow!("invalid TELEGRAM_API_URL {}: {}", url, e))?,
+            ),
+            None => bot,
+        };
+        Ok(bot.auto_send())
+    }
+
+    /// Get the cron schedule of job
+    pub fn schedule(&self, job: AutomatizerJob) -> &str {
+        self.schedules
+            .get(job.as_str())
+            .map(|x| x.as_str())
+            .unwrap_or_else(|| job.default_schedule())
+    }
+
+    /// Get the channel where announcements are mirrored, if configured
+    pub fn announcement_channel(&self) -> anyhow::Result<Option<Recipient>> {
+        self.announcement_channel
+            .as_deref()
+            .map(parse_channel)
+            .transpose()
+    }
+
+    /// Get the consecutive failures of a job after which the admins are alerted
+    pub fn job_failure_threshold(&self) -> usize {
+        self.job_failure_threshold
+            .unwrap_or(DEFAULT_JOB_FAILURE_THRESHOLD)
+    }
+
+    /// Get the webhook configuration; `None` if the bot must poll for updates.
+    /// The webhook mode is enabled by `WEBHOOK_URL`, or by `PORT` and `HOST` on heroku
+    pub fn webhook(&self) -> anyhow::Result<Option<WebhookConfig>> {
+        let url = match (self.webhook_url.as_deref(), self.port, self.host.as_deref()) {
+            (Some(url), _, _) => {
+                let mut url = Url::parse(url)
+                    .map_err(|e| anyhow::anyhow!("invalid WEBHOOK_URL {}: {}", url, e))?;
+                // the webhook path is appended to the path of the url, if any
+                let path = format!(
+                    "{}/{}",
+                    url.path().trim_end_matches('/'),
+                    self.webhook_path
+                        .as_deref()
+                        .unwrap_or(DEFAULT_WEBHOOK_PATH)
+                        .trim_start_matches('/')
+                );
+                url.set_path(&path);
+                url
+            }
+            (None, Some(_), Some(host)) => Url::parse(&format!(
+                "https://{}/webhooks/{}",
+                host, self.teloxide_token
+            ))
+            .map_err(|e| anyhow::anyhow!("invalid HOST {}: {}", host, e))?,
+            (None, Some(_), None) => anyhow::bail!("HOST is not SET"),
+            (None, None, _) => return Ok(None),
+        };
+        let address = match self.webhook_address.as_deref() {
+            Some(address) => address
+                .parse()
+                .map_err(|e| anyhow::anyhow!("invalid WEBHOOK_ADDRESS {}: {}", address, e))?,
+            None => SocketAddr::from(([0, 0, 0, 0], self.port.unwrap_or(DEFAULT_WEBHOOK_PORT))),
+        };
+        let secret_token = self
+            .webhook_secret_token
+            .as_deref()
+            .map(parse_secret_token)
+            .transpose()?;
+        Ok(Some(WebhookConfig {
+            url,
+            address,
+            secret_token,
+            certificate: self.webhook_certificate.as_ref().map(PathBuf::from),
+        }))
+    }
+
+    /// Get the security of the connection to the imap server
+    pub fn imap_security(&self) -> anyhow::Result<ImapSecurity> {
+        self.imap_security
+            .as_deref()
+            .map(|x| {
+                ImapSecurity::from_str(x)
+                    .map_err(|e| anyhow::anyhow!("invalid IMAP_SECURITY: {}", e))
+            })
+            .unwrap_or_else(|| Ok(ImapSecurity::default()))
+    }
+
+    /// Get the ids of the telegram users allowed to use the admin commands
+    pub fn admin_ids(&self) -> anyhow::Result<Vec<UserId>> {
+        self.admin_ids
+            .as_deref()
+            .map(parse_user_ids)
+            .unwrap_or_else(|| Ok(Vec::new()))
+    }
+}
+
+/// Parse a webhook secret token: 1-256 characters among `A-Z`, `a-z`, `0-9`, `_` and `-`
+fn parse_secret_token(token: &str) -> anyhow::Result<String> {
+    if token.is_empty()
+        || token.len() > WEBHOOK_SECRET_TOKEN_MAX_LEN
+        || !token
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '-')
+    {
+        anyhow::bail!(
+            "invalid WEBHOOK_SECRET_TOKEN: expected 1-256 characters among A-Z, a-z, 0-9, _ and -"
+        )
+    }
+    Ok(token.to_string())
+}
+
+/// Parse a comma separated list of user ids
+fn parse_user_ids(ids: &str) -> anyhow::Result<Vec<UserId>> {
+    ids.split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            x.parse::<u64>()
+                .map(UserId)
+                .map_err(|e| anyhow::anyhow!("invalid ADMIN_IDS user id {}: {}", x, e))
+        })
+        .collect()
+}
+
+/// Validate the job schedules, returning all the problems found
+fn validate_schedules(schedules: &HashMap<String, String>) -> Vec<String> {
+    schedules
+        .iter()
+        .filter_map(|(job, schedule)| {
+            if let Err(err) = AutomatizerJob::from_str(job) {
+                return Some(format!("invalid schedule: {}", err));
+            }
+            Job::new(schedule.as_str(), |_, _| {})
+                .err()
+                .map(|e| format!("invalid schedule {} for {}: {:?}", schedule, job, e))
+        })
+        .collect()
+}
+
+/// Validate the message templates, returning all the problems found
+fn validate_templates(templates: &HashMap<String, String>) -> Vec<String> {
+    templates
+        .iter()
+        .filter_map(|(template, text)| {
+            Template::from_str(template)
+                .and_then(|x| x.validate(text))
+                .err()
+                .map(|e| e.to_string())
+        })
+        .collect()
+}
+
+/// Parse a channel id or a channel username
+fn parse_channel(channel: &str) -> anyhow::Result<Recipient> {
+    if let Ok(id) = channel.parse::<i64>() {
+        Ok(Recipient::Id(ChatId(id)))
+    } else if channel.starts_with('@') && channel.len() > 1 {
+        Ok(Recipient::ChannelUsername(channel.to_string()))
+    } else {
+        anyhow::bail!(
+            "invalid ANNOUNCEMENT_CHANNEL {}: expected a channel id or @username",
+            channel
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_list_all_the_config_keys() {
+        // the keys are listed explicitly; they must match the fields of the config
+        let fields = match serde_json::to_value(Config::default()).unwrap() {
+            serde_json::Value::Object(fields) => fields,
+            _ => panic!("config is not an object"),
+        };
+        let mut keys = Config::keys();
+        keys.sort();
+        assert_eq!(keys, fields.keys().cloned().collect::<Vec<String>>());
+        let mut required: Vec<String> = fields
+            .into_iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, _)| k)
+            .collect();
+        required.sort();
+        let mut required_keys = Config::required_keys();
+        required_keys.sort();
+        assert_eq!(required_keys, required);
+    }
+
+    #[test]
+    fn should_parse_channel() {
+        assert_eq!(
+            parse_channel("-1001234567890").unwrap(),
+            Recipient::Id(ChatId(-1001234567890))
+        );
+        assert_eq!(
+            parse_channel("@spaziogrigio").unwrap(),
+            Recipient::ChannelUsername(String::from("@spaziogrigio"))
+        );
+        assert!(parse_channel("spaziogrigio").is_err());
+        assert!(parse_channel("@").is_err());
+    }
+
+    #[test]
+    fn should_get_webhook_config() {
+        assert!(config(&[]).webhook().unwrap().is_none());
+        assert_eq!(
+            config(&[
+                ("WEBHOOK_URL", "https://bot.spaziogrigio.com"),
+                ("WEBHOOK_ADDRESS", "127.0.0.1:8080"),
+                ("WEBHOOK_SECRET_TOKEN", "s3cret_token-1"),
+                ("WEBHOOK_CERTIFICATE", "/etc/irina/cert.pem"),
+            ])
+            .webhook()
+            .unwrap()
+            .unwrap(),
+            WebhookConfig {
+                url: Url::parse("https://bot.spaziogrigio.com/webhook").unwrap(),
+                address: "127.0.0.1:8080".parse().unwrap(),
+                secret_token: Some(String::from("s3cret_token-1")),
+                certificate: Some(PathBuf::from("/etc/irina/cert.pem")),
+            }
+        );
+        let webhook = config(&[
+            ("WEBHOOK_URL", "https://bot.spaziogrigio.com"),
+            ("WEBHOOK_PATH", "/telegram/updates"),
+        ])
+        .webhook()
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            webhook.url.as_str(),
+            "https://bot.spaziogrigio.com/telegram/updates"
+        );
+        assert_eq!(webhook.address, "0.0.0.0:8443".parse().unwrap());
+        assert!(webhook.secret_token.is_none());
+        assert!(config(&[
+            ("WEBHOOK_URL", "https://bot.spaziogrigio.com"),
+            ("WEBHOOK_SECRET_TOKEN", "not valid!"),
+        ])
+        .webhook()
+        .is_err());
+        let webhook = config(&[
+            ("WEBHOOK_URL", "https://www.spaziogrigio.com/irina/"),
+            ("WEBHOOK_PATH", "/telegram/updates"),
+        ])
+        .webhook()
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            webhook.url.as_str(),
+            "https://www.spaziogrigio.com/irina/telegram/updates"
+        );
+        assert!(config(&[("WEBHOOK_URL", "bot.spaziogrigio.com")])
+            .webhook()
+            .is_err());
+    }
+
+    #[test]
+    fn should_get_heroku_webhook_config() {
+        let webhook = config(&[("PORT", "5000"), ("HOST", "spaziogrigio.herokuapp.com")])
+            .webhook()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            webhook.url.as_str(),
+            "https://spaziogrigio.herokuapp.com/webhooks/123:abc"
+        );
+        assert_eq!(webhook.address, "0.0.0.0:5000".parse().unwrap());
+        assert!(config(&[("PORT", "5000")]).webhook().is_err());
+    }
+
+    #[test]
+    fn should_parse_user_ids() {
+        assert_eq!(
+            parse_user_ids("1234, 5678,").unwrap(),
+            vec![UserId(1234), UserId(5678)]
+        );
+        assert!(parse_user_ids("").unwrap().is_empty());
+        assert!(parse_user_ids("1234,irina").is_err());
+    }
+
+    #[test]
+    fn should_merge_config_file_with_env() {
+        let table: toml::value::Table = toml::from_str(
+            r#"
+            database_url = "/tmp/irina.db"
+            email_address = "irina@spaziogrigio.com"
+            email_password = "password"
+            imap_server = "imap.spaziogrigio.com"
+            imap_port = 993
+            instagram_password = "password"
+            instagram_username = "spaziogrigio"
+            redis_url = "redis://localhost/"
+            teloxide_token = "123:abc"
+            admin_ids = [1234, 5678]
+            smtp_starttls = true
+
+            [[creators]]
+            id = "spaziogrigio"
+            persona = "Irina"
+            youtube_channel_id = "UCI2qSuaUUGkYP3H_mQUIRRA"
+
+            [schedules]
+            video = "0 0 * * * *"
+
+            [templates]
+            video = "{persona} ha un nuovo video: "
+            "#,
+        )
+        .unwrap();
+        let env = [
+            ("IMAP_PORT", "143"),
+            ("SCHEDULE_FEED", "0 0 8 * * *"),
+            ("TEMPLATE_LIVE", "{persona} è in diretta: "),
+            ("PATH", "/usr/bin"),
+        ];
+        let config = Config::merge(
+            table,
+            env.iter().map(|(k, v)| (k.to_string(), v.to_string())),
+        )
+        .unwrap();
+        assert_eq!(config.imap_port, 143);
+        assert_eq!(config.email_address.as_str(), "irina@spaziogrigio.com");
+        assert_eq!(config.smtp_starttls, Some(true));
+        assert_eq!(
+            config.admin_ids().unwrap(),
+            vec![UserId(1234), UserId(5678)]
+        );
+        assert_eq!(
+            Creator::from_config(&config).unwrap()[0].persona.as_str(),
+            "Irina"
+        );
+        assert_eq!(config.schedule(AutomatizerJob::Video), "0 0 * * * *");
+        assert_eq!(config.schedule(AutomatizerJob::Feed), "0 0 8 * * *");
+        assert_eq!(
+            config.schedule(AutomatizerJob::Instagram),
+            AutomatizerJob::Instagram.default_schedule()
+        );
+        assert_eq!(
+            config.templates.get("live").map(|x| x.as_str()),
+            Some("{persona} è in diretta: ")
+        );
+        assert!(config.validate().is_empty());
+    }
+
+    #[test]
+    fn should_report_all_loading_problems() {
+        let table: toml::value::Table = toml::from_str(
+            r#"
+            database_url = "/tmp/irina.db"
+            colour = "grey"
+            schedules = "daily"
+            "#,
+        )
+        .unwrap();
+        let problems = Config::merge(
+            table,
+            [
+                (
+                    String::from("REDIS_URL"),
+                    String::from("redis://localhost/"),
+                ),
+                (String::from("SCHEDULE_VIDEO"), String::from("every hour")),
+            ]
+            .into_iter(),
+        )
+        .unwrap_err();
+        assert!(problems.contains(&String::from("unknown key colour in config file")));
+        assert!(problems.contains(&String::from("schedules must be a table")));
+        assert!(problems.contains(&String::from("TELOXIDE_TOKEN is not set")));
+        assert!(problems.contains(&String::from("IMAP_PORT is not set")));
+        assert!(!problems.contains(&String::from("REDIS_URL is not set")));
+        assert!(!problems.contains(&String::from("DATABASE_URL is not set")));
+        assert!(problems
+            .iter()
+            .any(|x| x.starts_with("invalid schedule every hour for video")));
+    }
+
+    #[test]
+    fn should_report_all_validation_problems() {
+        let mut config = config(&[
+            ("ADMIN_IDS", "1234,irina"),
+            ("CREATORS", "[]"),
+            ("IMAP_SECURITY", "ssh"),
+            ("JOB_FAILURE_THRESHOLD", "0"),
+        ]);
+        config
+            .schedules
+            .insert(String::from("video"), String::from("every hour"));
+        config
+            .schedules
+            .insert(String::from("tv"), String::from("0 0 * * * *"));
+        config
+            .templates
+            .insert(String::from("video"), String::from("{url} è online"));
+        assert_eq!(config.validate().len(), 7);
+        assert!(self::config(&[]).validate().is_empty());
+        assert_eq!(
+            self::config(&[]).imap_security().unwrap(),
+            ImapSecurity::Tls
+        );
+        assert_eq!(
+            self::config(&[("IMAP_SECURITY", "starttls")])
+                .imap_security()
+                .unwrap(),
+            ImapSecurity::StartTls
+        );
+    }
+
+    fn config(vars: &[(&str, &str)]) -> Config {
+        let required = [
+            ("DATABASE_URL", "/tmp/irina.db"),
+            ("EMAIL_ADDRESS", "irina@spaziogrigio.com"),
+            ("EMAIL_PASSWORD", "password"),
+            ("IMAP_SERVER", "imap.spaziogrigio.com"),
+            ("IMAP_PORT", "993"),
+            ("INSTAGRAM_PASSWORD", "password"),
+            ("INSTAGRAM_USERNAME", "spaziogrigio"),
+            ("REDIS_URL", "redis://localhost/"),
+            ("TELOXIDE_TOKEN", "123:abc"),
+        ];
+        envy::from_iter(
+            required
+                .iter()
+                .chain(vars.iter())
+                .map(|(k, v)| (k.to_string(), v.to_string())),
+        )
+        .unwrap()
     }
 }
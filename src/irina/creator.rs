@@ -0,0 +1,126 @@
+//! # Creator
+//!
+//! A creator followed by the bot, with its own channels and persona
+
+use super::config::Config;
+
+use once_cell::sync::OnceCell;
+
+/// Identifier of the creator configured by default
+pub const DEFAULT_CREATOR_ID: &str = "spaziogrigio";
+
+/// Creators configured for this instance, parsed once
+static CREATORS: OnceCell<Vec<Creator>> = OnceCell::new();
+
+/// A content creator followed by the bot
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Creator {
+    /// Unique identifier of the creator (used in commands and keys)
+    pub id: String,
+    /// The name the bot uses to introduce itself for this creator (e.g. "Irina")
+    pub persona: String,
+    /// Youtube channel id
+    pub youtube_channel_id: Option<String>,
+    /// Instagram account name
+    pub instagram_account: Option<String>,
+    /// Address the newsletter is sent from
+    pub newsletter_sender: Option<String>,
+}
+
+impl Default for Creator {
+    fn default() -> Self {
+        Self {
+            id: DEFAULT_CREATOR_ID.to_string(),
+            persona: String::from("Irina"),
+            youtube_channel_id: Some(String::from("UCK3cMi97Kf_WENLvRFdztoQ")),
+            instagram_account: Some(String::from("spaziogrigio")),
+            newsletter_sender: Some(String::from("info@spaziogrigio.com")),
+        }
+    }
+}
+
+impl Creator {
+    /// Get all the creators configured for this instance, which are parsed on the first call.
+    /// If no creator is configured, spazio grigio is returned
+    pub fn all() -> anyhow::Result<&'static [Self]> {
+        CREATORS
+            .get_or_try_init(|| Self::from_config(Config::load()?))
+            .map(|x| x.as_slice())
+    }
+
+    /// Get the creators configured in `config`
+    pub fn from_config(config: &Config) -> anyhow::Result<Vec<Self>> {
+        match config.creators.as_deref() {
+            None => Ok(vec![Self::default()]),
+            Some(creators) => Self::parse(creators),
+        }
+    }
+
+    /// Get the creator to use when none is specified (the first configured)
+    pub fn main() -> anyhow::Result<Self> {
+        Ok(Self::all()?[0].clone())
+    }
+
+    /// Find creator by its id. If `id` is empty, the main creator is returned
+    pub fn find(id: &str) -> anyhow::Result<Self> {
+        let id = id.trim();
+        if id.is_empty() {
+            return Self::main();
+        }
+        match Self::all()?.iter().find(|x| x.id == id) {
+            Some(creator) => Ok(creator.clone()),
+            None => anyhow::bail!("Non conosco nessuno che si chiami \"{}\". Digita /creatori per vedere chi puoi seguire", id),
+        }
+    }
+
+    /// Parse creators from their JSON representation
+    fn parse(creators: &str) -> anyhow::Result<Vec<Self>> {
+        let creators: Vec<Self> = serde_json::from_str(creators)
+            .map_err(|e| anyhow::anyhow!("invalid CREATORS syntax: {}", e))?;
+        if creators.is_empty() {
+            anyhow::bail!("CREATORS must contain at least one creator");
+        }
+        Ok(creators)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_parse_creators() {
+        let creators = Creator::parse(
+            r#"[
+                {
+                    "id": "spaziogrigio",
+                    "persona": "Irina",
+                    "youtube_channel_id": "UCK3cMi97Kf_WENLvRFdztoQ",
+                    "instagram_account": "spaziogrigio",
+                    "newsletter_sender": "info@spaziogrigio.com"
+                },
+                {
+                    "id": "minimalista",
+                    "persona": "Mario",
+                    "youtube_channel_id": "UCTpU7OQg9QVsqayEYXTL1LQ"
+                }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(creators.len(), 2);
+        assert_eq!(creators[0], Creator::default());
+        assert_eq!(creators[1].id.as_str(), "minimalista");
+        assert_eq!(creators[1].persona.as_str(), "Mario");
+        assert_eq!(creators[1].instagram_account, None);
+        assert_eq!(creators[1].newsletter_sender, None);
+    }
+
+    #[test]
+    fn should_not_parse_empty_creators() {
+        assert!(Creator::parse("[]").is_err());
+        assert!(Creator::parse("{}").is_err());
+    }
+}
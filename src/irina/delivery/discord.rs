@@ -0,0 +1,129 @@
+//! # Discord
+//!
+//! Discord incoming webhooks delivery target
+
+use super::{Answer, DeliveryTarget};
+
+use async_trait::async_trait;
+
+/// Max length of a discord message
+const DISCORD_CONTENT_LIMIT: usize = 2000;
+/// Max amount of embeds in a discord message
+const DISCORD_EMBEDS_LIMIT: usize = 10;
+
+/// Delivers answers to a discord incoming webhook. Images are sent as embeds
+pub struct DiscordTarget {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+impl DiscordTarget {
+    pub fn new(webhook_url: impl ToString) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            webhook_url: webhook_url.to_string(),
+        }
+    }
+
+    /// Make the webhook payload for answer
+    fn payload(answer: &Answer) -> serde_json::Value {
+        let embeds: Vec<serde_json::Value> = answer
+            .images()
+            .take(DISCORD_EMBEDS_LIMIT)
+            .map(|url| serde_json::json!({ "image": { "url": url.as_str() } }))
+            .collect();
+        serde_json::json!({
+            "content": answer.plain_text().chars().take(DISCORD_CONTENT_LIMIT).collect::<String>(),
+            "embeds": embeds,
+        })
+    }
+}
+
+#[async_trait]
+impl DeliveryTarget for DiscordTarget {
+    async fn deliver(&self, answer: &Answer) -> anyhow::Result<()> {
+        let response = self
+            .client
+            .post(&self.webhook_url)
+            .json(&Self::payload(answer))
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to call discord webhook: {}", e))?;
+        if !response.status().is_success() {
+            anyhow::bail!("discord webhook returned {}", response.status());
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        // don't log the webhook token
+        format!(
+            "discord {}",
+            self.webhook_url
+                .rsplit_once('/')
+                .map(|(x, _)| x)
+                .unwrap_or_default()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::irina::AnswerBuilder;
+
+    use pretty_assertions::assert_eq;
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn should_deliver_to_discord_webhook() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/webhooks/1234/token"))
+            .and(body_json(serde_json::json!({
+                "content": "Ciao sono Irina",
+                "embeds": [
+                    { "image": { "url": "https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg" } }
+                ],
+            })))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let target = DiscordTarget::new(format!("{}/api/webhooks/1234/token", server.uri()));
+        let answer = AnswerBuilder::default()
+            .image("https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg")
+            .text("Ciao sono Irina")
+            .finalize();
+        assert!(target.deliver(&answer).await.is_ok());
+        assert_eq!(
+            target.name(),
+            format!("discord {}/api/webhooks/1234", server.uri())
+        );
+    }
+
+    #[tokio::test]
+    async fn should_report_discord_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        let target = DiscordTarget::new(format!("{}/api/webhooks/1234/token", server.uri()));
+        assert!(target
+            .deliver(&Answer::simple_text("Ciao sono Irina"))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn should_truncate_discord_content() {
+        let payload = DiscordTarget::payload(&Answer::simple_text("a".repeat(3000)));
+        assert_eq!(
+            payload["content"].as_str().unwrap().len(),
+            DISCORD_CONTENT_LIMIT
+        );
+    }
+}
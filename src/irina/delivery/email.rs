@@ -0,0 +1,204 @@
+//! # Email
+//!
+//! Email delivery target, sending messages through a SMTP server
+
+use super::{Answer, Config, DeliveryTarget};
+
+use async_trait::async_trait;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+/// Default email subject
+const DEFAULT_SUBJECT: &str = "Le novità di Spazio Grigio";
+/// Default SMTP port (submission)
+const DEFAULT_SMTP_PORT: u16 = 587;
+
+/// SMTP server configuration
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub server: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Address the emails are sent from
+    pub sender: String,
+    /// Whether to upgrade the connection with STARTTLS
+    pub starttls: bool,
+}
+
+impl TryFrom<&Config> for SmtpConfig {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &Config) -> Result<Self, Self::Error> {
+        let server = match config.smtp_server.as_deref() {
+            Some(server) => server.to_string(),
+            None => anyhow::bail!("SMTP_SERVER must be set to deliver emails"),
+        };
+        Ok(Self {
+            server,
+            port: config.smtp_port.unwrap_or(DEFAULT_SMTP_PORT),
+            username: config.smtp_username.clone(),
+            password: config.smtp_password.clone(),
+            sender: config
+                .smtp_sender
+                .clone()
+                .unwrap_or_else(|| config.email_address.clone()),
+            starttls: config.smtp_starttls.unwrap_or(true),
+        })
+    }
+}
+
+/// Delivers answers to an email address as plain text emails
+pub struct EmailTarget {
+    smtp: SmtpConfig,
+    to: String,
+    subject: String,
+}
+
+impl EmailTarget {
+    pub fn new(smtp: SmtpConfig, to: impl ToString) -> Self {
+        Self {
+            smtp,
+            to: to.to_string(),
+            subject: DEFAULT_SUBJECT.to_string(),
+        }
+    }
+
+    /// Set email subject
+    pub fn subject(mut self, subject: impl ToString) -> Self {
+        self.subject = subject.to_string();
+        self
+    }
+
+    /// Build SMTP transport
+    fn transport(&self) -> anyhow::Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let mut builder = if self.smtp.starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.smtp.server)
+                .map_err(|e| anyhow::anyhow!("failed to setup SMTP transport: {}", e))?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.smtp.server)
+        }
+        .port(self.smtp.port);
+        if let (Some(username), Some(password)) = (&self.smtp.username, &self.smtp.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        Ok(builder.build())
+    }
+}
+
+#[async_trait]
+impl DeliveryTarget for EmailTarget {
+    async fn deliver(&self, answer: &Answer) -> anyhow::Result<()> {
+        let email = Message::builder()
+            .from(
+                self.smtp
+                    .sender
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("invalid sender address: {}", e))?,
+            )
+            .to(self
+                .to
+                .parse()
+                .map_err(|e| anyhow::anyhow!("invalid recipient address: {}", e))?)
+            .subject(&self.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(answer.plain_text())
+            .map_err(|e| anyhow::anyhow!("failed to build email: {}", e))?;
+        self.transport()?
+            .send(email)
+            .await
+            .map(|_| ())
+            .map_err(|e| anyhow::anyhow!("failed to send email: {}", e))
+    }
+
+    fn name(&self) -> String {
+        format!("email {}", self.to)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    #[tokio::test]
+    async fn should_deliver_email() {
+        let (port, server) = smtp_server().await;
+        let target = EmailTarget::new(smtp_config(port), "irina@spaziogrigio.com")
+            .subject("Il digest di Spazio Grigio");
+        assert!(target
+            .deliver(&Answer::simple_text("Ciao sono Irina"))
+            .await
+            .is_ok());
+        let transcript = server.await.unwrap();
+        assert!(transcript.contains("MAIL FROM:<bot@spaziogrigio.com>"));
+        assert!(transcript.contains("RCPT TO:<irina@spaziogrigio.com>"));
+        assert!(transcript.contains("Subject: Il digest di Spazio Grigio"));
+        assert!(transcript.contains("Ciao sono Irina"));
+    }
+
+    #[tokio::test]
+    async fn should_fail_delivering_to_invalid_address() {
+        let target = EmailTarget::new(smtp_config(25), "irina");
+        assert!(target
+            .deliver(&Answer::simple_text("Ciao sono Irina"))
+            .await
+            .is_err());
+    }
+
+    fn smtp_config(port: u16) -> SmtpConfig {
+        SmtpConfig {
+            server: String::from("127.0.0.1"),
+            port,
+            username: None,
+            password: None,
+            sender: String::from("bot@spaziogrigio.com"),
+            starttls: false,
+        }
+    }
+
+    /// Start a stand-in SMTP server, accepting a single session. The task returns the session transcript
+    async fn smtp_server() -> (u16, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = socket.into_split();
+            let mut reader = BufReader::new(reader);
+            let mut transcript = String::new();
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            let mut data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                transcript.push_str(&line);
+                let reply: &[u8] = if data {
+                    if line.trim_end() == "." {
+                        data = false;
+                        b"250 OK\r\n"
+                    } else {
+                        continue;
+                    }
+                } else if line.starts_with("DATA") {
+                    data = true;
+                    b"354 Start mail input\r\n"
+                } else if line.starts_with("QUIT") {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 OK\r\n"
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+            transcript
+        });
+        (port, handle)
+    }
+}
@@ -0,0 +1,155 @@
+//! # Matrix
+//!
+//! Matrix rooms delivery target, using the client-server API
+
+use super::{Answer, DeliveryTarget};
+
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Transaction counter, to make transaction ids unique within the same millisecond
+static TRANSACTION_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Delivers answers to a matrix room as text messages
+pub struct MatrixTarget {
+    client: reqwest::Client,
+    homeserver: String,
+    access_token: String,
+    room_id: String,
+}
+
+impl MatrixTarget {
+    pub fn new(
+        homeserver: impl ToString,
+        access_token: impl ToString,
+        room_id: impl ToString,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            homeserver: homeserver.to_string(),
+            access_token: access_token.to_string(),
+            room_id: room_id.to_string(),
+        }
+    }
+
+    /// Get the url to send a message to the room with `transaction_id`
+    fn send_url(&self, transaction_id: &str) -> anyhow::Result<Url> {
+        let mut url = Url::parse(&self.homeserver)
+            .map_err(|e| anyhow::anyhow!("invalid matrix homeserver url: {}", e))?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("invalid matrix homeserver url"))?
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                &self.room_id,
+                "send",
+                "m.room.message",
+                transaction_id,
+            ]);
+        Ok(url)
+    }
+
+    /// Generate a new transaction id
+    fn transaction_id() -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        format!(
+            "spaziogrigio-{}-{}",
+            now,
+            TRANSACTION_COUNTER.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+}
+
+#[async_trait]
+impl DeliveryTarget for MatrixTarget {
+    async fn deliver(&self, answer: &Answer) -> anyhow::Result<()> {
+        let url = self.send_url(&Self::transaction_id())?;
+        let response = self
+            .client
+            .put(url)
+            .bearer_auth(&self.access_token)
+            .json(&serde_json::json!({
+                "msgtype": "m.text",
+                "body": answer.plain_text(),
+            }))
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to send matrix message: {}", e))?;
+        if !response.status().is_success() {
+            anyhow::bail!("matrix homeserver returned {}", response.status());
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        format!("matrix {}", self.room_id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::irina::AnswerBuilder;
+
+    use wiremock::matchers::{body_json, header, method, path_regex};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn should_deliver_to_matrix_room() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path_regex(
+                r"^/_matrix/client/v3/rooms/!spaziogrigio:matrix\.org/send/m\.room\.message/spaziogrigio-[0-9]+-[0-9]+$",
+            ))
+            .and(header("Authorization", "Bearer secret"))
+            .and(body_json(serde_json::json!({
+                "msgtype": "m.text",
+                "body": "Ciao sono Irina\n\n🎧 Ascolta qui: https://www.spaziogrigio.com/podcast/episodio-2.mp3",
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"event_id":"$1"}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let target = MatrixTarget::new(server.uri(), "secret", "!spaziogrigio:matrix.org");
+        let answer = AnswerBuilder::default()
+            .image("https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg")
+            .text("Ciao sono Irina")
+            .audio(
+                "https://www.spaziogrigio.com/podcast/episodio-2.mp3",
+                Default::default(),
+            )
+            .finalize();
+        assert!(target.deliver(&answer).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn should_report_matrix_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+        let target = MatrixTarget::new(server.uri(), "secret", "!spaziogrigio:matrix.org");
+        assert!(target
+            .deliver(&Answer::simple_text("Ciao sono Irina"))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn should_make_unique_transaction_ids() {
+        assert_ne!(
+            MatrixTarget::transaction_id(),
+            MatrixTarget::transaction_id()
+        );
+    }
+}
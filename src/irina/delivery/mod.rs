@@ -0,0 +1,233 @@
+//! # Delivery
+//!
+//! This module exposes the targets answers can be delivered to: Telegram chats and channels, Matrix rooms,
+//! Discord webhooks and email addresses
+
+mod discord;
+mod email;
+mod matrix;
+mod telegram;
+
+pub use discord::DiscordTarget;
+pub use email::{EmailTarget, SmtpConfig};
+pub use matrix::MatrixTarget;
+pub use telegram::TelegramTarget;
+
+use super::config::Config;
+use super::Answer;
+
+use async_trait::async_trait;
+use std::fmt;
+use std::str::FromStr;
+
+/// Accepted discord webhook urls
+const DISCORD_WEBHOOK_PREFIXES: [&str; 2] = [
+    "https://discord.com/api/webhooks/",
+    "https://discordapp.com/api/webhooks/",
+];
+
+/// A target answers can be delivered to
+#[async_trait]
+pub trait DeliveryTarget: Send + Sync {
+    /// Deliver `answer` to the target
+    async fn deliver(&self, answer: &Answer) -> anyhow::Result<()>;
+
+    /// Describe the target in logs
+    fn name(&self) -> String;
+}
+
+/// The kinds of the delivery targets outside of Telegram, which subscriptions are stored for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TargetKind {
+    /// A matrix room; the address is the room id
+    Matrix,
+    /// A discord incoming webhook; the address is the webhook url
+    Discord,
+    /// An email address, which receives a daily digest
+    Email,
+}
+
+impl TargetKind {
+    /// Get target kind as str
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Matrix => "matrix",
+            Self::Discord => "discord",
+            Self::Email => "email",
+        }
+    }
+
+    /// Parse `<kind> <address>` arguments, validating the address for the target kind
+    pub fn parse_address(args: &str) -> anyhow::Result<(Self, String)> {
+        let mut args = args.split_whitespace();
+        let (kind, address) = match (args.next(), args.next(), args.next()) {
+            (Some(kind), Some(address), None) => (Self::from_str(kind)?, address),
+            _ => anyhow::bail!("Uso: <matrix|discord|email> <stanza|webhook|indirizzo>"),
+        };
+        let valid = match kind {
+            Self::Matrix => address.starts_with('!') && address.contains(':'),
+            Self::Discord => DISCORD_WEBHOOK_PREFIXES
+                .iter()
+                .any(|prefix| address.starts_with(prefix)),
+            Self::Email => lettre::Address::from_str(address).is_ok(),
+        };
+        if !valid {
+            anyhow::bail!("{} non è un indirizzo {} valido", address, kind);
+        }
+        Ok((kind, address.to_string()))
+    }
+
+    /// Parse `<kind> <address> [code]` arguments, where `code` confirms a previous request
+    pub fn parse_forward(args: &str) -> anyhow::Result<(Self, String, Option<String>)> {
+        let mut args: Vec<&str> = args.split_whitespace().collect();
+        let code = match args.len() {
+            3 => args.pop().map(String::from),
+            _ => None,
+        };
+        Self::parse_address(&args.join(" ")).map(|(kind, address)| (kind, address, code))
+    }
+
+    /// Redact `address` to show it in replies and logs. The token of discord webhooks is omitted
+    pub fn redact(&self, address: &str) -> String {
+        match self {
+            Self::Discord => format!(
+                "{}/…",
+                DISCORD_WEBHOOK_PREFIXES
+                    .iter()
+                    .find_map(|prefix| address.strip_prefix(prefix))
+                    .and_then(|x| x.split('/').next())
+                    .map(|id| format!("discord.com/api/webhooks/{}", id))
+                    .unwrap_or_else(|| String::from("discord.com/api/webhooks"))
+            ),
+            Self::Matrix | Self::Email => address.to_string(),
+        }
+    }
+
+    /// Instantiate the delivery target for `address`
+    pub fn target(&self, address: &str) -> anyhow::Result<Box<dyn DeliveryTarget>> {
+        let config = Config::load()?;
+        match self {
+            Self::Matrix => {
+                let (homeserver, access_token) =
+                    match (&config.matrix_homeserver, &config.matrix_access_token) {
+                        (Some(homeserver), Some(access_token)) => (homeserver, access_token),
+                        _ => anyhow::bail!(
+                            "MATRIX_HOMESERVER and MATRIX_ACCESS_TOKEN must be set to deliver to matrix"
+                        ),
+                    };
+                Ok(Box::new(MatrixTarget::new(
+                    homeserver,
+                    access_token,
+                    address,
+                )))
+            }
+            Self::Discord => Ok(Box::new(DiscordTarget::new(address))),
+            Self::Email => Ok(Box::new(EmailTarget::new(
+                SmtpConfig::try_from(config)?,
+                address,
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for TargetKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for TargetKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "matrix" => Ok(Self::Matrix),
+            "discord" => Ok(Self::Discord),
+            "email" => Ok(Self::Email),
+            _ => anyhow::bail!("unknown delivery target: {}", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_convert_target_kind_to_and_from_str() {
+        for kind in [TargetKind::Matrix, TargetKind::Discord, TargetKind::Email] {
+            assert_eq!(TargetKind::from_str(kind.as_str()).unwrap(), kind);
+            assert_eq!(kind.to_string().as_str(), kind.as_str());
+        }
+        assert!(TargetKind::from_str("telegram").is_err());
+    }
+
+    #[test]
+    fn should_parse_target_address() {
+        assert_eq!(
+            TargetKind::parse_address("matrix !spaziogrigio:matrix.org").unwrap(),
+            (TargetKind::Matrix, String::from("!spaziogrigio:matrix.org"))
+        );
+        assert_eq!(
+            TargetKind::parse_address("discord https://discord.com/api/webhooks/1234/token")
+                .unwrap(),
+            (
+                TargetKind::Discord,
+                String::from("https://discord.com/api/webhooks/1234/token")
+            )
+        );
+        assert_eq!(
+            TargetKind::parse_address(" email  irina@spaziogrigio.com ").unwrap(),
+            (TargetKind::Email, String::from("irina@spaziogrigio.com"))
+        );
+        assert!(TargetKind::parse_address("").is_err());
+        assert!(TargetKind::parse_address("email").is_err());
+        assert!(TargetKind::parse_address("email irina@spaziogrigio.com pippo").is_err());
+        assert!(TargetKind::parse_address("matrix spaziogrigio").is_err());
+        assert!(TargetKind::parse_address("discord https://www.spaziogrigio.com").is_err());
+        assert!(TargetKind::parse_address("email irina").is_err());
+        assert!(TargetKind::parse_address("telegram @spaziogrigio").is_err());
+    }
+
+    #[test]
+    fn should_parse_forward_request() {
+        assert_eq!(
+            TargetKind::parse_forward("email irina@spaziogrigio.com").unwrap(),
+            (
+                TargetKind::Email,
+                String::from("irina@spaziogrigio.com"),
+                None
+            )
+        );
+        assert_eq!(
+            TargetKind::parse_forward("email irina@spaziogrigio.com 123456").unwrap(),
+            (
+                TargetKind::Email,
+                String::from("irina@spaziogrigio.com"),
+                Some(String::from("123456"))
+            )
+        );
+        assert!(TargetKind::parse_forward("email").is_err());
+        assert!(TargetKind::parse_forward("email irina 123456").is_err());
+        assert!(TargetKind::parse_forward("email irina@spaziogrigio.com 1 2").is_err());
+    }
+
+    #[test]
+    fn should_redact_address() {
+        assert_eq!(
+            TargetKind::Discord.redact("https://discord.com/api/webhooks/1234/token"),
+            "discord.com/api/webhooks/1234/…"
+        );
+        assert_eq!(
+            TargetKind::Discord.redact("https://discordapp.com/api/webhooks/1234/token"),
+            "discord.com/api/webhooks/1234/…"
+        );
+        assert_eq!(
+            TargetKind::Email.redact("irina@spaziogrigio.com"),
+            "irina@spaziogrigio.com"
+        );
+    }
+}
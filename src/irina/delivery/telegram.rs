@@ -0,0 +1,55 @@
+//! # Telegram
+//!
+//! Telegram chats and channels delivery target
+
+use super::{Answer, DeliveryTarget};
+
+use async_trait::async_trait;
+use teloxide::prelude::*;
+use teloxide::types::Recipient;
+
+/// Delivers answers to a Telegram chat or channel
+pub struct TelegramTarget {
+    bot: AutoSend<Bot>,
+    recipient: Recipient,
+    channel: bool,
+}
+
+impl TelegramTarget {
+    /// Deliver to a chat
+    pub fn chat(bot: AutoSend<Bot>, chat: ChatId) -> Self {
+        Self {
+            bot,
+            recipient: Recipient::Id(chat),
+            channel: false,
+        }
+    }
+
+    /// Deliver to a channel; answers are formatted as channel posts
+    pub fn channel(bot: AutoSend<Bot>, channel: Recipient) -> Self {
+        Self {
+            bot,
+            recipient: channel,
+            channel: true,
+        }
+    }
+}
+
+#[async_trait]
+impl DeliveryTarget for TelegramTarget {
+    async fn deliver(&self, answer: &Answer) -> anyhow::Result<()> {
+        let result = if self.channel {
+            answer
+                .clone()
+                .send_to_channel(&self.bot, self.recipient.clone())
+                .await
+        } else {
+            answer.clone().send(&self.bot, self.recipient.clone()).await
+        };
+        result.map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    fn name(&self) -> String {
+        format!("telegram {}", self.recipient)
+    }
+}
@@ -0,0 +1,133 @@
+//! # Feeds
+//!
+//! This module exposes the function to fetch the latest entries from the configured RSS/Atom sources (blogs, podcasts, ...)
+
+use super::config::Config;
+use crate::feed::{Entry, FeedClient};
+
+use chrono::{DateTime, Utc};
+
+/// A RSS/Atom feed source polled by the automatizer
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FeedSource {
+    /// Unique identifier of the source (used for watermarks)
+    pub id: String,
+    /// Name of the source displayed in the announcements
+    pub name: String,
+    /// Feed url
+    pub url: String,
+    /// Id of the creator who publishes on this source. If unset, entries are announced to all the subscribed chats
+    pub creator: Option<String>,
+}
+
+impl FeedSource {
+    /// Get all the feed sources configured for this instance
+    pub fn all() -> anyhow::Result<Vec<Self>> {
+        Self::from_config(Config::load()?)
+    }
+
+    /// Get the feed sources configured in `config`
+    pub fn from_config(config: &Config) -> anyhow::Result<Vec<Self>> {
+        match config.feed_sources.as_deref() {
+            None => Ok(Vec::new()),
+            Some(sources) => Self::parse(sources),
+        }
+    }
+
+    /// Parse feed sources from their JSON representation
+    fn parse(sources: &str) -> anyhow::Result<Vec<Self>> {
+        serde_json::from_str(sources)
+            .map_err(|e| anyhow::anyhow!("invalid FEED_SOURCES syntax: {}", e))
+    }
+}
+
+pub struct Feeds;
+
+impl Feeds {
+    /// Get the latest entries of source, sorted by date (oldest first)
+    pub async fn get_entries(source: &FeedSource) -> anyhow::Result<Vec<Entry>> {
+        let feed = FeedClient::shared(&source.url).fetch().await.map_err(|e| {
+            anyhow::anyhow!(
+                "Non riesco ad ottenere gli aggiornamenti di {}: {}",
+                source.name,
+                e
+            )
+        })?;
+        let mut entries: Vec<Entry> = feed.entries().cloned().collect();
+        entries.sort_by_key(|x| x.date);
+        Ok(entries)
+    }
+
+    /// Get oldest entry published after `last_pubdate`
+    pub fn oldest_unseen(entries: Vec<Entry>, last_pubdate: DateTime<Utc>) -> Option<Entry> {
+        entries
+            .into_iter()
+            .find(|entry| entry.date > Some(last_pubdate))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_parse_feed_sources() {
+        let sources = FeedSource::parse(
+            r#"[
+                {
+                    "id": "podcast",
+                    "name": "Il podcast di Spazio Grigio",
+                    "url": "https://www.spaziogrigio.com/podcast.xml",
+                    "creator": "spaziogrigio"
+                },
+                {
+                    "id": "blog",
+                    "name": "Il blog di Spazio Grigio",
+                    "url": "https://www.spaziogrigio.com/blog.xml"
+                }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].creator.as_deref(), Some("spaziogrigio"));
+        assert_eq!(sources[1].id.as_str(), "blog");
+        assert_eq!(sources[1].creator, None);
+        assert!(FeedSource::parse(r#"[{"id": "blog"}]"#).is_err());
+    }
+
+    #[test]
+    fn should_get_oldest_unseen_entry() {
+        let entries = vec![entry("1", 1), entry("2", 2), entry("3", 3)];
+        assert_eq!(
+            Feeds::oldest_unseen(entries.clone(), date(1))
+                .unwrap()
+                .id
+                .as_str(),
+            "2"
+        );
+        assert_eq!(Feeds::oldest_unseen(entries, date(3)), None);
+    }
+
+    fn date(day: u32) -> DateTime<Utc> {
+        Utc.ymd(2022, 9, day).and_hms(8, 0, 0)
+    }
+
+    fn entry(id: &str, day: u32) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: None,
+            authors: Vec::new(),
+            summary: String::new(),
+            url: String::new(),
+            date: Some(date(day)),
+            thumbnail: None,
+            description: None,
+            views: None,
+            enclosure: None,
+        }
+    }
+}
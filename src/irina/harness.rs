@@ -0,0 +1,577 @@
+//! # Harness
+//!
+//! End-to-end test harness: updates are driven through the bot handlers, which talk to a fake Telegram Bot API
+//! recording the requests it receives. Redis is replaced by an in-memory fake too.
+//!
+//! The configuration is read from the process environment, shared by all the tests, so each test must use its own chats.
+//! The automatizer jobs announce to every subscribed chat instead, so the tests running them hold `lock_jobs`
+
+use super::automatize::Automatizer;
+use super::broadcast::Broadcasts;
+use super::config::Config;
+use super::{Irina, State};
+use crate::mail::test_server::{ImapServer, LATIN1, MULTIPART};
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::sync::{Arc, Mutex};
+use teloxide::dptree;
+use teloxide::prelude::*;
+use teloxide::types::{Me, Update};
+use tempfile::NamedTempFile;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{MutexGuard, OnceCell};
+use url::Url;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+/// Token of the test bot
+pub const TOKEN: &str = "123:abc";
+/// Telegram user allowed to use the admin commands
+pub const ADMIN: UserId = UserId(1000);
+/// Telegram user owning the groups, which is the only administrator of them
+pub const GROUP_OWNER: UserId = UserId(1001);
+/// Username of the test bot
+const BOT_USERNAME: &str = "spaziogrigio_bot";
+
+const YOUTUBE_FEED: &str = include_str!("../../tests/fixtures/youtube/feed.xml");
+
+/// Environment shared by the tests
+static ENVIRONMENT: OnceCell<Environment> = OnceCell::const_new();
+/// Held by the tests running the automatizer jobs
+static JOBS: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+/// Services the configuration of the tests points to
+pub struct Environment {
+    _database: NamedTempFile,
+    _youtube: MockServer,
+    _redis: FakeRedis,
+    /// Mailbox the newsletters are read from, without security
+    pub imap: ImapServer,
+    /// Bot API used by the code paths creating their own bot, such as the automatizer jobs
+    pub api: FakeBotApi,
+    /// Matrix homeserver accepting every message
+    matrix: MockServer,
+}
+
+impl Environment {
+    /// Start the services and point the configuration to them
+    async fn start() -> Self {
+        let database = NamedTempFile::new().expect("failed to create database");
+        let youtube = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/feeds/videos.xml"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(YOUTUBE_FEED))
+            .mount(&youtube)
+            .await;
+        let redis = FakeRedis::start().await;
+        let imap = ImapServer::start(&[MULTIPART, LATIN1]).await;
+        let api = FakeBotApi::start().await;
+        let matrix = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "event_id": "$event" })),
+            )
+            .mount(&matrix)
+            .await;
+        let creators = serde_json::json!([
+            {
+                "id": "spaziogrigio",
+                "persona": "Irina",
+                "youtube_channel_id": "UCK3cMi97Kf_WENLvRFdztoQ",
+                "instagram_account": "spaziogrigio",
+                "newsletter_sender": "info@spaziogrigio.com",
+            },
+            {
+                "id": "minimalista",
+                "persona": "Mario",
+                "newsletter_sender": "newsletter@minimalista.it",
+            },
+        ]);
+        for (key, value) in [
+            ("CREATORS", creators.to_string()),
+            (
+                "DATABASE_URL",
+                database.path().to_string_lossy().to_string(),
+            ),
+            ("EMAIL_ADDRESS", String::from("irina@spaziogrigio.com")),
+            ("EMAIL_PASSWORD", String::from("password")),
+            ("IMAP_PORT", imap.port().to_string()),
+            ("IMAP_SECURITY", String::from("none")),
+            ("IMAP_SERVER", String::from("127.0.0.1")),
+            ("INSTAGRAM_PASSWORD", String::from("password")),
+            ("INSTAGRAM_USERNAME", String::from("spaziogrigio")),
+            ("MATRIX_ACCESS_TOKEN", String::from("token")),
+            ("MATRIX_HOMESERVER", matrix.uri()),
+            ("REDIS_URL", redis.url()),
+            ("TELEGRAM_API_URL", api.url()),
+            ("TELOXIDE_TOKEN", String::from(TOKEN)),
+            ("YOUTUBE_URL", youtube.uri()),
+        ] {
+            std::env::set_var(key, value);
+        }
+        Config::init().expect("invalid configuration");
+        Environment {
+            _database: database,
+            _youtube: youtube,
+            _redis: redis,
+            imap,
+            api,
+            matrix,
+        }
+    }
+
+    /// Get the text of the messages sent to the matrix `room`, in order
+    pub async fn matrix_messages(&self, room: &str) -> Vec<String> {
+        self.matrix
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter(|x| {
+                x.url
+                    .path_segments()
+                    .map(|mut x| x.any(|x| x == room))
+                    .unwrap_or_default()
+            })
+            .filter_map(|x| serde_json::from_slice::<serde_json::Value>(&x.body).ok())
+            .filter_map(|x| x["body"].as_str().map(String::from))
+            .collect()
+    }
+}
+
+/// Runtime of the services of the environment, which must outlive the runtime of the test starting them
+static RUNTIME: Lazy<tokio::runtime::Runtime> = Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build the environment runtime")
+});
+
+/// Setup the environment shared by the tests, once
+pub async fn environment() -> &'static Environment {
+    ENVIRONMENT
+        .get_or_init(|| async {
+            RUNTIME
+                .spawn(Environment::start())
+                .await
+                .expect("failed to start the environment")
+        })
+        .await
+}
+
+/// Wait until no other test is running the automatizer jobs. Until the guard is dropped,
+/// the chats receive only the announcements of the jobs run by the caller
+pub async fn lock_jobs() -> MutexGuard<'static, ()> {
+    JOBS.lock().await
+}
+
+/// An in-memory redis, implementing the commands of the redis client
+pub struct FakeRedis {
+    port: u16,
+}
+
+impl FakeRedis {
+    /// Start the fake redis
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind redis");
+        let port = listener.local_addr().unwrap().port();
+        let store = Arc::new(Mutex::new(HashMap::new()));
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(Self::serve(stream, store.clone()));
+            }
+        });
+        Self { port }
+    }
+
+    /// Get the url of the fake redis
+    pub fn url(&self) -> String {
+        format!("redis://127.0.0.1:{}/", self.port)
+    }
+
+    /// Serve a client connection
+    async fn serve(
+        stream: TcpStream,
+        store: Arc<Mutex<HashMap<String, String>>>,
+    ) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        while let Some(command) = Self::read_command(&mut reader).await? {
+            let args: Vec<&str> = command.iter().map(|x| x.as_str()).collect();
+            let response = match args.as_slice() {
+                [name, rest @ ..] => match (name.to_ascii_uppercase().as_str(), rest) {
+                    ("PING", []) => String::from("+PONG\r\n"),
+                    ("GET", [key]) => match store.lock().unwrap().get(*key) {
+                        Some(value) => format!("${}\r\n{}\r\n", value.len(), value),
+                        None => String::from("$-1\r\n"),
+                    },
+                    ("SET", [key, value]) => {
+                        store
+                            .lock()
+                            .unwrap()
+                            .insert(key.to_string(), value.to_string());
+                        String::from("+OK\r\n")
+                    }
+                    ("DEL", keys) => {
+                        let mut store = store.lock().unwrap();
+                        let deleted = keys.iter().filter(|x| store.remove(**x).is_some()).count();
+                        format!(":{}\r\n", deleted)
+                    }
+                    _ => format!("-ERR unsupported command {}\r\n", name),
+                },
+                [] => String::from("-ERR empty command\r\n"),
+            };
+            writer.write_all(response.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// Read a command, sent as an array of bulk strings
+    async fn read_command(
+        reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>,
+    ) -> std::io::Result<Option<Vec<String>>> {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let len: usize = line
+            .trim_start_matches('*')
+            .trim()
+            .parse()
+            .unwrap_or_default();
+        let mut command = Vec::with_capacity(len);
+        for _ in 0..len {
+            line.clear();
+            reader.read_line(&mut line).await?;
+            let size: usize = line
+                .trim_start_matches('$')
+                .trim()
+                .parse()
+                .unwrap_or_default();
+            let mut value = vec![0; size + 2];
+            reader.read_exact(&mut value).await?;
+            value.truncate(size);
+            command.push(String::from_utf8_lossy(&value).to_string());
+        }
+        Ok(Some(command))
+    }
+}
+
+/// A fake Telegram Bot API, answering every method with a successful response and recording the requests
+pub struct FakeBotApi {
+    server: MockServer,
+}
+
+impl FakeBotApi {
+    /// Start the fake Bot API
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(BotApiResponder)
+            .mount(&server)
+            .await;
+        Self { server }
+    }
+
+    /// Get the url of the fake Bot API
+    pub fn url(&self) -> String {
+        self.server.uri()
+    }
+
+    /// Create a bot which talks to the fake Bot API
+    pub fn bot(&self) -> AutoSend<Bot> {
+        Bot::new(TOKEN)
+            .set_api_url(Url::parse(&self.url()).unwrap())
+            .auto_send()
+    }
+
+    /// Get the requests received by the fake Bot API, in order
+    pub async fn calls(&self) -> Vec<ApiCall> {
+        self.server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .map(ApiCall::from)
+            .collect()
+    }
+
+    /// Get the requests about `chat`, in order
+    pub async fn calls_to(&self, chat: ChatId) -> Vec<ApiCall> {
+        self.calls()
+            .await
+            .into_iter()
+            .filter(|x| x.chat_id() == Some(chat))
+            .collect()
+    }
+}
+
+/// Answers the Bot API methods with the result telegram would return
+struct BotApiResponder;
+
+impl Respond for BotApiResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let call = ApiCall::from(request);
+        let message = serde_json::json!({
+            "message_id": 1,
+            "date": 1662019200,
+            "chat": {
+                "id": call.chat_id().map(|x| x.0).unwrap_or(1),
+                "type": "private",
+                "first_name": "Mario",
+            },
+            "text": call.text(),
+        });
+        let result = match call.method.as_str() {
+            "sendMediaGroup" => serde_json::json!([message]),
+            "getChatMember" => {
+                let user = call.param("user_id").unwrap_or_default();
+                let user = serde_json::json!({
+                    "id": user.parse::<u64>().unwrap_or_default(),
+                    "is_bot": false,
+                    "first_name": "Mario",
+                });
+                if call.param("user_id") == Some(GROUP_OWNER.0.to_string().as_str()) {
+                    serde_json::json!({ "status": "creator", "user": user, "is_anonymous": false })
+                } else {
+                    serde_json::json!({ "status": "member", "user": user })
+                }
+            }
+            "answerCallbackQuery" | "answerInlineQuery" | "setWebhook" | "deleteWebhook" => {
+                serde_json::json!(true)
+            }
+            _ => message,
+        };
+        ResponseTemplate::new(200)
+            .set_body_json(serde_json::json!({ "ok": true, "result": result }))
+    }
+}
+
+/// A request received by the fake Bot API
+#[derive(Debug, Clone)]
+pub struct ApiCall {
+    /// Bot API method (e.g. `sendMessage`)
+    pub method: String,
+    /// Parameters of the request; values which are not strings are JSON encoded
+    pub params: HashMap<String, String>,
+}
+
+impl ApiCall {
+    /// Get the chat the request is about
+    pub fn chat_id(&self) -> Option<ChatId> {
+        self.params
+            .get("chat_id")
+            .and_then(|x| x.parse().ok())
+            .map(ChatId)
+    }
+
+    /// Get the text or the caption of the message sent
+    pub fn text(&self) -> &str {
+        self.params
+            .get("text")
+            .or_else(|| self.params.get("caption"))
+            .map(|x| x.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Get a parameter of the request
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(|x| x.as_str())
+    }
+}
+
+impl From<&Request> for ApiCall {
+    fn from(request: &Request) -> Self {
+        // teloxide capitalizes the method names, which telegram reads case insensitively
+        let method = request
+            .url
+            .path_segments()
+            .and_then(|mut x| x.next_back())
+            .unwrap_or_default();
+        let mut chars = method.chars();
+        let method = match chars.next() {
+            Some(first) => first.to_lowercase().chain(chars).collect(),
+            None => String::new(),
+        };
+        let content_type = request
+            .headers
+            .get(&"content-type".into())
+            .map(|x| x.as_str().to_string())
+            .unwrap_or_default();
+        let body = String::from_utf8_lossy(&request.body);
+        let params = match content_type.split_once("boundary=") {
+            Some((_, boundary)) => multipart_params(&body, boundary),
+            None => json_params(&body),
+        };
+        Self { method, params }
+    }
+}
+
+/// Read the parameters of a JSON request
+fn json_params(body: &str) -> HashMap<String, String> {
+    match serde_json::from_str(body) {
+        Ok(serde_json::Value::Object(params)) => params
+            .into_iter()
+            .map(|(name, value)| match value {
+                serde_json::Value::String(value) => (name, value),
+                value => (name, value.to_string()),
+            })
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// Read the parameters of a multipart request
+fn multipart_params(body: &str, boundary: &str) -> HashMap<String, String> {
+    body.split(&format!("--{}", boundary.trim_matches('"')))
+        .filter_map(|part| {
+            let (headers, value) = part.split_once("\r\n\r\n")?;
+            let name = headers.split("name=\"").nth(1)?.split('"').next()?;
+            Some((name.to_string(), value.trim_end_matches("\r\n").to_string()))
+        })
+        .collect()
+}
+
+/// Drives updates through the bot handlers
+pub struct Harness {
+    pub api: FakeBotApi,
+    bot: AutoSend<Bot>,
+    me: Me,
+    state: State,
+}
+
+impl Harness {
+    /// Setup a bot talking to its own fake Bot API, with an automatizer without scheduled jobs
+    pub async fn new() -> Self {
+        environment().await;
+        let api = FakeBotApi::start().await;
+        let bot = api.bot();
+        let automatizer: &'static Automatizer = Box::leak(Box::new(Automatizer::idle().await));
+        let me = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "is_bot": true,
+            "first_name": "Irina",
+            "username": BOT_USERNAME,
+            "can_join_groups": true,
+            "can_read_all_group_messages": false,
+            "supports_inline_queries": true,
+        }))
+        .expect("invalid me");
+        Self {
+            api,
+            bot,
+            me,
+            state: State {
+                automatizer,
+                admins: vec![ADMIN],
+                broadcasts: Broadcasts::default(),
+            },
+        }
+    }
+
+    /// Send `text` to the bot from the private chat of user `chat`, as telegram would, and wait for the handlers
+    pub async fn send_text(&self, chat: ChatId, text: &str) {
+        self.send_formatted_text(chat, text, serde_json::json!([]))
+            .await
+    }
+
+    /// Send `text` formatted with the message `entities` from the private chat of user `chat`, and wait for the handlers
+    pub async fn send_formatted_text(&self, chat: ChatId, text: &str, entities: serde_json::Value) {
+        self.send_update(serde_json::json!({
+            "message": {
+                "message_id": 1,
+                "date": 1662019200,
+                "chat": { "id": chat.0, "type": "private", "first_name": "Mario" },
+                "from": { "id": chat.0, "is_bot": false, "first_name": "Mario" },
+                "text": text,
+                "entities": entities,
+            },
+        }))
+        .await
+    }
+
+    /// Press the inline keyboard button with callback `data` as `user`, on a message sent to `chat`,
+    /// which is a group if negative. Returns the requests the bot made in response
+    pub async fn press(&self, chat: ChatId, user: UserId, data: &str) -> Vec<ApiCall> {
+        let before = self.api.calls().await.len();
+        self.send_update(serde_json::json!({
+            "callback_query": {
+                "id": "1",
+                "from": { "id": user.0, "is_bot": false, "first_name": "Mario" },
+                "message": {
+                    "message_id": 1,
+                    "date": 1662019200,
+                    "chat": Self::chat(chat),
+                    "text": "Ciao sono Irina",
+                },
+                "chat_instance": "1",
+                "data": data,
+            },
+        }))
+        .await;
+        self.api.calls().await.split_off(before)
+    }
+
+    /// Send a change of the bot membership in `chat` from `old` to `new` status, made by the group owner
+    pub async fn change_membership(&self, chat: ChatId, old: &str, new: &str) {
+        let member = |status: &str| {
+            serde_json::json!({
+                "status": status,
+                "user": { "id": 1, "is_bot": true, "first_name": "Irina", "username": BOT_USERNAME },
+            })
+        };
+        self.send_update(serde_json::json!({
+            "my_chat_member": {
+                "chat": Self::chat(chat),
+                "from": { "id": GROUP_OWNER.0, "is_bot": false, "first_name": "Mario" },
+                "date": 1662019200,
+                "old_chat_member": member(old),
+                "new_chat_member": member(new),
+            },
+        }))
+        .await
+    }
+
+    /// Dispatch `update` to the handlers, as telegram would, and wait for them
+    async fn send_update(&self, mut update: serde_json::Value) {
+        update["update_id"] = serde_json::json!(1);
+        // updates must be read from text: the deserializer of `Update` doesn't support `serde_json::Value`
+        let update: Update = serde_json::from_str(&update.to_string()).expect("invalid update");
+        let id = format!("{:?}", update.kind);
+        let result = Irina::handler()
+            .dispatch(dptree::deps![
+                self.bot.clone(),
+                self.me.clone(),
+                update,
+                self.state.clone()
+            ])
+            .await;
+        match result {
+            ControlFlow::Break(Ok(())) => {}
+            ControlFlow::Break(Err(err)) => panic!("handler failed on {}: {}", id, err),
+            ControlFlow::Continue(_) => panic!("update {} has not been handled", id),
+        }
+    }
+
+    /// Get the JSON of `chat`: a group if negative, otherwise the private chat of a user
+    fn chat(chat: ChatId) -> serde_json::Value {
+        if chat.0 < 0 {
+            serde_json::json!({ "id": chat.0, "type": "group", "title": "Minimalisti" })
+        } else {
+            serde_json::json!({ "id": chat.0, "type": "private", "first_name": "Mario" })
+        }
+    }
+
+    /// Send `text` and get the requests the bot made about `chat` in response
+    pub async fn ask(&self, chat: ChatId, text: &str) -> Vec<ApiCall> {
+        let before = self.api.calls_to(chat).await.len();
+        self.send_text(chat, text).await;
+        self.api.calls_to(chat).await.split_off(before)
+    }
+}
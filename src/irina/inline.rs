@@ -0,0 +1,374 @@
+//! # Inline
+//!
+//! Inline mode: search the recent videos and the archived contents, to share them in any chat
+
+use super::creator::Creator;
+use super::repository::Repository;
+use super::topic::Topic;
+use super::youtube::Youtube;
+use super::RichText;
+use crate::repository::content::Content;
+use crate::utils::str as str_helpers;
+use crate::youtube::{self, VideoKind};
+
+use chrono::Utc;
+
+use once_cell::sync::Lazy;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineQueryResult, InlineQueryResultArticle, InlineQueryResultPhoto, InputMessageContent,
+    InputMessageContentText, ParseMode,
+};
+use url::Url;
+
+/// Max amount of results Telegram accepts for an inline query
+const MAX_RESULTS: usize = 50;
+/// Amount of archived contents to search in
+const ARCHIVE_SEARCH_LIMIT: u32 = 200;
+/// Time the archived contents and the recent videos are cached for, besides the contents archived meanwhile by the jobs
+const ARCHIVE_CACHE_TTL: Duration = Duration::from_secs(300);
+/// Max length of an inline result id
+const MAX_RESULT_ID_LEN: usize = 64;
+/// Max length of the description shown in the results
+const DESCRIPTION_LEN: usize = 128;
+/// Seconds Telegram caches the results of an empty query (the latest contents)
+const LATEST_CACHE_TIME: u32 = 300;
+/// Seconds Telegram caches the results of a search
+const SEARCH_CACHE_TIME: u32 = 60;
+
+/// Archived contents and recent videos served to the inline queries
+static ARCHIVE: Lazy<Mutex<Option<CachedArchive>>> = Lazy::new(|| Mutex::new(None));
+
+/// Archived contents and recent videos, along with the time they have been loaded
+struct CachedArchive {
+    loaded_at: Instant,
+    contents: Vec<Content>,
+    videos: Vec<Content>,
+}
+
+impl CachedArchive {
+    /// Get the archived contents followed by the recent videos
+    fn all(&self) -> Vec<Content> {
+        self.contents
+            .iter()
+            .chain(self.videos.iter())
+            .cloned()
+            .collect()
+    }
+}
+
+/// Inline queries handler
+pub struct Inline;
+
+impl Inline {
+    /// Answer inline query with the contents matching the query
+    pub async fn answer(bot: &AutoSend<Bot>, query: InlineQuery) -> anyhow::Result<()> {
+        let results: Vec<InlineQueryResult> = Self::search(Self::contents().await, &query.query)
+            .iter()
+            .map(Self::result)
+            .collect();
+        debug!(
+            "inline query {:?} matched {} contents",
+            query.query,
+            results.len()
+        );
+        let cache_time = if query.query.trim().is_empty() {
+            LATEST_CACHE_TIME
+        } else {
+            SEARCH_CACHE_TIME
+        };
+        bot.answer_inline_query(query.id, results)
+            .cache_time(cache_time)
+            .await
+            .map(|_| ())
+            .map_err(|e| anyhow::anyhow!("failed to answer inline query: {}", e))
+    }
+
+    /// Get the archived contents and the recent videos, loading them again once the cache has expired.
+    /// If the repository fails, the expired archived contents are served
+    async fn contents() -> Vec<Content> {
+        if let Some(cached) = ARCHIVE
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|x| x.loaded_at.elapsed() < ARCHIVE_CACHE_TTL)
+        {
+            return cached.all();
+        }
+        // no topics means every topic
+        let archived = match Repository::connect().await {
+            Ok(repository) => repository.get_archived(&[], ARCHIVE_SEARCH_LIMIT).await,
+            Err(err) => Err(err),
+        };
+        let videos = Self::recent_videos().await;
+        let mut cache = ARCHIVE.lock().unwrap();
+        match archived {
+            Ok(contents) => {
+                let cached = CachedArchive {
+                    loaded_at: Instant::now(),
+                    contents,
+                    videos,
+                };
+                let contents = cached.all();
+                *cache = Some(cached);
+                contents
+            }
+            Err(err) => {
+                error!("failed to get archived contents: {}", err);
+                let mut contents = cache
+                    .as_ref()
+                    .map(|x| x.contents.clone())
+                    .unwrap_or_default();
+                contents.extend(videos);
+                contents
+            }
+        }
+    }
+
+    /// Collect the recent videos of all the creators. Creators whose feed fails are logged and skipped
+    async fn recent_videos() -> Vec<Content> {
+        let mut videos = Vec::new();
+        for creator in Creator::all().unwrap_or_default() {
+            if creator.youtube_channel_id.is_none() {
+                continue;
+            }
+            match Youtube::get_latest_videos(creator).await {
+                Ok(feed) => videos.extend(feed.entries().map(|video| {
+                    let topic = match VideoKind::classify(video) {
+                        VideoKind::Short => Topic::Short,
+                        VideoKind::Video | VideoKind::Premiere | VideoKind::Live => Topic::Video,
+                    };
+                    Content::new(
+                        &video.id,
+                        topic,
+                        Some(&creator.id),
+                        video.title.as_deref().unwrap_or_default(),
+                        video.description.as_deref().unwrap_or_default(),
+                        video.date.unwrap_or_else(Utc::now),
+                    )
+                    .with_url(Some(video.url.clone()))
+                    .with_image(youtube::thumbnail(video))
+                })),
+                Err(err) => error!("failed to get latest videos of {}: {}", creator.id, err),
+            }
+        }
+        videos
+    }
+
+    /// Add a content archived by the jobs to the cached contents
+    pub fn cache(content: Content) {
+        if let Some(cached) = ARCHIVE.lock().unwrap().as_mut() {
+            cached.contents.push(content);
+        }
+    }
+
+    /// Get the contents matching all the words in `query`, newest first and without duplicates.
+    /// An empty query matches every content
+    fn search(mut contents: Vec<Content>, query: &str) -> Vec<Content> {
+        let terms: Vec<String> = query.split_whitespace().map(|x| x.to_lowercase()).collect();
+        contents.sort_by_key(|x| std::cmp::Reverse(x.published_at().ok()));
+        let mut seen = HashSet::new();
+        contents
+            .into_iter()
+            .filter(|x| seen.insert(x.id().to_string()))
+            .filter(|x| Self::matches(x, &terms))
+            .take(MAX_RESULTS)
+            .collect()
+    }
+
+    /// Check whether every term is contained in the content title or summary
+    fn matches(content: &Content, terms: &[String]) -> bool {
+        let title = content.title().to_lowercase();
+        let summary = content.summary().to_lowercase();
+        terms
+            .iter()
+            .all(|term| title.contains(term) || summary.contains(term))
+    }
+
+    /// Make the inline result for content. Contents with a JPEG image are shared as a photo with caption,
+    /// since Telegram accepts only JPEG photos; the other contents are shared as an article with the image as thumbnail
+    fn result(content: &Content) -> InlineQueryResult {
+        let id = Self::result_id(content.id());
+        let title = if content.title().is_empty() {
+            content.url().unwrap_or(content.id())
+        } else {
+            content.title()
+        };
+        let description =
+            str_helpers::excerpt(&str_helpers::strip_html(content.summary()), DESCRIPTION_LEN);
+        let text = match content.url() {
+            Some(url) => RichText::default().link(title, url),
+            None => RichText::default().bold(title),
+        };
+        let image = content.image().and_then(|x| Url::parse(x).ok());
+        match image.filter(Self::is_jpeg) {
+            Some(image) => InlineQueryResult::Photo(
+                InlineQueryResultPhoto::new(id, image.clone(), image)
+                    .title(title)
+                    .description(description)
+                    .caption(text.to_html())
+                    .parse_mode(ParseMode::Html),
+            ),
+            None => {
+                let mut article = InlineQueryResultArticle::new(
+                    id,
+                    title,
+                    InputMessageContent::Text(
+                        InputMessageContentText::new(text.to_html()).parse_mode(ParseMode::Html),
+                    ),
+                )
+                .description(description);
+                if let Some(url) = content.url().and_then(|x| Url::parse(x).ok()) {
+                    article = article.url(url);
+                }
+                if let Some(image) = content.image().and_then(|x| Url::parse(x).ok()) {
+                    article = article.thumb_url(image);
+                }
+                InlineQueryResult::Article(article)
+            }
+        }
+    }
+
+    /// Check whether the image at `url` is a JPEG, based on its extension
+    fn is_jpeg(url: &Url) -> bool {
+        let path = url.path().to_lowercase();
+        path.ends_with(".jpg") || path.ends_with(".jpeg")
+    }
+
+    /// Get the inline result id for content id. Ids longer than Telegram accepts are hashed
+    fn result_id(id: &str) -> String {
+        if id.len() <= MAX_RESULT_ID_LEN {
+            return id.to_string();
+        }
+        let mut hasher = DefaultHasher::new();
+        id.hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::irina::topic::Topic;
+
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn should_serve_cached_archive() {
+        *ARCHIVE.lock().unwrap() = Some(CachedArchive {
+            loaded_at: Instant::now(),
+            contents: vec![content("rRQP8PNEouo", "Declutter del mio armadio", 1)],
+            videos: vec![content(
+                "yt:video:ZEHVgvLAv6Q",
+                "Il mio armadio minimalista",
+                3,
+            )],
+        });
+        Inline::cache(content("B4sZIm7tbiE", "La mia morning routine", 2));
+        let ids: Vec<String> = Inline::search(Inline::contents().await, "")
+            .iter()
+            .map(|x| x.id().to_string())
+            .collect();
+        assert_eq!(
+            ids,
+            vec!["yt:video:ZEHVgvLAv6Q", "B4sZIm7tbiE", "rRQP8PNEouo"]
+        );
+    }
+
+    #[test]
+    fn should_search_contents() {
+        let contents = vec![
+            content("rRQP8PNEouo", "Declutter del mio armadio", 1),
+            content("instagram:1", "Nuovo post su Instagram", 3),
+            content("rRQP8PNEouo", "Declutter del mio armadio", 1),
+            content("B4sZIm7tbiE", "La mia morning routine", 2),
+        ];
+        let ids = |contents: Vec<Content>| {
+            contents
+                .iter()
+                .map(|x| x.id().to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            ids(Inline::search(contents.clone(), "")),
+            vec!["instagram:1", "B4sZIm7tbiE", "rRQP8PNEouo"]
+        );
+        assert_eq!(
+            ids(Inline::search(contents.clone(), "  DECLUTTER  armadio")),
+            vec!["rRQP8PNEouo"]
+        );
+        assert_eq!(
+            ids(Inline::search(contents.clone(), "minimalismo")),
+            vec!["instagram:1", "B4sZIm7tbiE", "rRQP8PNEouo"]
+        );
+        assert!(Inline::search(contents, "declutter tv").is_empty());
+    }
+
+    #[test]
+    fn should_make_photo_result_for_contents_with_image() {
+        let result = Inline::result(
+            &content("rRQP8PNEouo", "Declutter del mio armadio", 1).with_image(Some(String::from(
+                "https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg",
+            ))),
+        );
+        assert!(matches!(
+            result,
+            InlineQueryResult::Photo(photo) if photo.id == "rRQP8PNEouo"
+                && photo.caption.as_deref() == Some("<a href=\"https://www.youtube.com/watch?v=rRQP8PNEouo\">Declutter del mio armadio</a>")
+        ));
+    }
+
+    #[test]
+    fn should_make_article_result_for_contents_without_image() {
+        let result = Inline::result(&content("rRQP8PNEouo", "Declutter del mio armadio", 1));
+        assert!(matches!(
+            result,
+            InlineQueryResult::Article(article) if article.title == "Declutter del mio armadio"
+                && article.url.as_ref().map(|x| x.as_str()) == Some("https://www.youtube.com/watch?v=rRQP8PNEouo")
+                && article.description.as_deref() == Some("Un video sul minimalismo")
+                && article.thumb_url.is_none()
+        ));
+    }
+
+    #[test]
+    fn should_make_article_result_with_thumbnail_for_contents_with_other_images() {
+        let result = Inline::result(
+            &content("instagram:1", "Nuovo post su Instagram", 3).with_image(Some(String::from(
+                "https://www.spaziogrigio.com/images/post.webp",
+            ))),
+        );
+        assert!(matches!(
+            result,
+            InlineQueryResult::Article(article) if article.title == "Nuovo post su Instagram"
+                && article.thumb_url.as_ref().map(|x| x.as_str()) == Some("https://www.spaziogrigio.com/images/post.webp")
+        ));
+    }
+
+    #[test]
+    fn should_make_result_id() {
+        assert_eq!(Inline::result_id("rRQP8PNEouo").as_str(), "rRQP8PNEouo");
+        let long_id = format!("https://www.spaziogrigio.com/{}", "a".repeat(64));
+        assert_eq!(Inline::result_id(&long_id).len(), 16);
+        assert_eq!(Inline::result_id(&long_id), Inline::result_id(&long_id));
+    }
+
+    fn content(id: &str, title: &str, day: u32) -> Content {
+        Content::new(
+            id,
+            Topic::Video,
+            Some("spaziogrigio"),
+            title,
+            "Un video sul minimalismo",
+            Utc.ymd(2022, 7, day).and_hms(12, 0, 0),
+        )
+        .with_url(Some(format!("https://www.youtube.com/watch?v={}", id)))
+    }
+}
@@ -1,45 +1,59 @@
 //! # Instagram
 //!
-//! Big luca bot instagram scraper
+//! Creators instagram scraper
 
 use std::time::SystemTime;
 
 use instagram_scraper_rs::{InstagramScraper, Post};
 
+use super::creator::Creator;
 use super::Config;
 
 pub struct InstagramService;
 
-const BIGLUCA_ACCOUNT_ID: &str = "spaziogrigio";
-
 impl InstagramService {
+    /// Get the url of the post page
+    pub fn post_url(post: &Post) -> String {
+        format!("https://www.instagram.com/p/{}/", post.shortcode)
+    }
+
     /// Get newest (latest) post from instagram
-    pub async fn get_latest_post() -> anyhow::Result<Post> {
-        let config = Config::try_from_env()?;
+    pub async fn get_latest_post(creator: &Creator) -> anyhow::Result<Post> {
+        let account = Self::account(creator)?;
+        let config = Config::load()?;
         debug!("creating instagram scraper");
-        let mut scraper = InstagramScraper::default()
-            .authenticate_with_login(config.instagram_username, config.instagram_password);
+        let mut scraper = InstagramScraper::default().authenticate_with_login(
+            config.instagram_username.clone(),
+            config.instagram_password.clone(),
+        );
         scraper.login().await?;
-        let user_id = Self::get_user_id(&mut scraper).await?;
+        let user_id = Self::get_user_id(&mut scraper, account).await?;
         let posts = Self::get_posts(&mut scraper, &user_id, 1).await?;
         scraper.logout().await?;
-        if let Some(post) = posts.get(0) {
+        if let Some(post) = posts.first() {
             Ok(post.clone())
         } else {
-            anyhow::bail!("Non ho trovato nessun post sull'instagram del papi");
+            anyhow::bail!(
+                "Non ho trovato nessun post sull'instagram di {}",
+                creator.persona
+            );
         }
     }
 
     /// Get oldest unseen post from instagram
     pub async fn get_oldest_unseen_post(
+        creator: &Creator,
         last_post_pubdate: SystemTime,
     ) -> anyhow::Result<Option<Post>> {
-        let config = Config::try_from_env()?;
+        let account = Self::account(creator)?;
+        let config = Config::load()?;
         debug!("creating instagram scraper");
-        let mut scraper = InstagramScraper::default()
-            .authenticate_with_login(config.instagram_username, config.instagram_password);
+        let mut scraper = InstagramScraper::default().authenticate_with_login(
+            config.instagram_username.clone(),
+            config.instagram_password.clone(),
+        );
         scraper.login().await?;
-        let user_id = Self::get_user_id(&mut scraper).await?;
+        let user_id = Self::get_user_id(&mut scraper, account).await?;
         let mut posts = Self::get_posts(&mut scraper, &user_id, 50).await?;
         scraper.logout().await?;
         posts.sort_by_key(|x| x.taken_at_timestamp);
@@ -62,9 +76,20 @@ impl InstagramService {
         Ok(posts)
     }
 
-    /// Get user id from instagram for bigluca
-    async fn get_user_id(scraper: &mut InstagramScraper) -> anyhow::Result<String> {
-        let user = scraper.scrape_userinfo(BIGLUCA_ACCOUNT_ID).await?;
+    /// Get user id from instagram for account
+    async fn get_user_id(scraper: &mut InstagramScraper, account: &str) -> anyhow::Result<String> {
+        let user = scraper.scrape_userinfo(account).await?;
         Ok(user.id)
     }
+
+    /// Get instagram account for creator
+    fn account(creator: &Creator) -> anyhow::Result<&str> {
+        match creator.instagram_account.as_deref() {
+            Some(account) => Ok(account),
+            None => anyhow::bail!(
+                "Ciao sono {}. Non ho un profilo instagram da mostrarti.",
+                creator.persona
+            ),
+        }
+    }
 }
@@ -0,0 +1,122 @@
+//! # Job
+//!
+//! The jobs run by the automatizer, their run status and health
+
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+/// A job run by the automatizer
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AutomatizerJob {
+    /// Send the morning routine to the subscribed chats
+    GoodMorning,
+    /// Announce the latest newsletters
+    Newsletter,
+    /// Announce the new instagram posts
+    Instagram,
+    /// Announce the new videos
+    Video,
+    /// Announce the new entries of the feed sources
+    Feed,
+    /// Send the daily email digest
+    EmailDigest,
+}
+
+impl AutomatizerJob {
+    /// All the jobs
+    pub const ALL: [AutomatizerJob; 6] = [
+        Self::GoodMorning,
+        Self::Newsletter,
+        Self::Instagram,
+        Self::Video,
+        Self::Feed,
+        Self::EmailDigest,
+    ];
+
+    /// Get job name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::GoodMorning => "good_morning",
+            Self::Newsletter => "newsletter",
+            Self::Instagram => "instagram",
+            Self::Video => "video",
+            Self::Feed => "feed",
+            Self::EmailDigest => "email_digest",
+        }
+    }
+
+    /// Get the cron schedule of the job, when not configured
+    pub fn default_schedule(&self) -> &'static str {
+        match self {
+            Self::GoodMorning => "0 5 6 * * *",
+            Self::Newsletter => "0 30 19 * * *",
+            Self::Instagram => "0 40 * * * *",
+            Self::Video => "0 30 * * * *",
+            Self::Feed => "0 15,45 * * * *",
+            Self::EmailDigest => "0 0 20 * * *",
+        }
+    }
+}
+
+impl fmt::Display for AutomatizerJob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for AutomatizerJob {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|job| job.as_str() == s)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Job sconosciuto: {}. I job sono: {}",
+                    s,
+                    Self::ALL.map(|x| x.as_str()).join(", ")
+                )
+            })
+    }
+}
+
+/// The outcome of the last run of a job
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobStatus {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// The error the job failed with, if any
+    pub error: Option<String>,
+    /// Amount of messages delivered by the run
+    pub delivered: usize,
+}
+
+/// The health of a job, according to its run history
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JobHealth {
+    /// The outcome of the last run; `None` if the job has never run
+    pub last_run: Option<JobStatus>,
+    /// When the last successful run started
+    pub last_success: Option<DateTime<Utc>>,
+    /// Amount of failed runs since the last successful one
+    pub consecutive_failures: usize,
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_convert_job_to_and_from_str() {
+        for job in AutomatizerJob::ALL {
+            assert_eq!(AutomatizerJob::from_str(job.as_str()).unwrap(), job);
+            assert_eq!(job.to_string().as_str(), job.as_str());
+        }
+        assert!(AutomatizerJob::from_str("tv").is_err());
+    }
+}
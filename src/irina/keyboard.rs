@@ -0,0 +1,172 @@
+//! # Keyboard
+//!
+//! Inline keyboards attached to the announcements and the callbacks of their buttons
+
+use super::topic::Topic;
+
+use std::fmt;
+use std::str::FromStr;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+use url::Url;
+
+/// An action triggered by an inline keyboard button. It's sent to the bot as the callback data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Callback {
+    /// Opt the chat out from a topic
+    OptOut(Topic),
+    /// Get the latest videos of a creator
+    LatestVideos(String),
+    /// Send the broadcast composed by an admin
+    ConfirmBroadcast,
+    /// Discard the broadcast composed by an admin
+    CancelBroadcast,
+}
+
+impl fmt::Display for Callback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OptOut(topic) => write!(f, "optout:{}", topic),
+            Self::LatestVideos(creator) => write!(f, "videos:{}", creator),
+            Self::ConfirmBroadcast => write!(f, "broadcast:confirm"),
+            Self::CancelBroadcast => write!(f, "broadcast:cancel"),
+        }
+    }
+}
+
+impl FromStr for Callback {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("optout", topic)) => Ok(Self::OptOut(Topic::from_str(topic)?)),
+            Some(("videos", creator)) if !creator.is_empty() => {
+                Ok(Self::LatestVideos(creator.to_string()))
+            }
+            Some(("broadcast", "confirm")) => Ok(Self::ConfirmBroadcast),
+            Some(("broadcast", "cancel")) => Ok(Self::CancelBroadcast),
+            _ => anyhow::bail!("unknown callback: {}", s),
+        }
+    }
+}
+
+/// Keyboard for a video: watch it and get the other videos of the creator.
+/// If `topic` is set, a button to opt out from the topic is added
+pub fn video(url: &str, creator: &str, topic: Option<Topic>) -> InlineKeyboardMarkup {
+    let mut row = Vec::with_capacity(2);
+    if let Ok(url) = Url::parse(url) {
+        row.push(InlineKeyboardButton::url("Guarda su YouTube", url));
+    }
+    row.push(InlineKeyboardButton::callback(
+        "Altri video",
+        Callback::LatestVideos(creator.to_string()).to_string(),
+    ));
+    with_opt_out(InlineKeyboardMarkup::new([row]), topic)
+}
+
+/// Keyboard for an instagram post.
+/// If `topic` is set, a button to opt out from the topic is added
+pub fn instagram_post(url: &str, topic: Option<Topic>) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+    if let Ok(url) = Url::parse(url) {
+        keyboard = keyboard.append_row([InlineKeyboardButton::url("Apri su Instagram", url)]);
+    }
+    with_opt_out(keyboard, topic)
+}
+
+/// Keyboard to confirm or cancel a broadcast
+pub fn broadcast() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback("Conferma", Callback::ConfirmBroadcast.to_string()),
+        InlineKeyboardButton::callback("Annulla", Callback::CancelBroadcast.to_string()),
+    ]])
+}
+
+/// Add the opt out button for `topic` to keyboard
+fn with_opt_out(keyboard: InlineKeyboardMarkup, topic: Option<Topic>) -> InlineKeyboardMarkup {
+    match topic {
+        Some(topic) => keyboard.append_row([InlineKeyboardButton::callback(
+            "Disiscriviti da questo argomento",
+            Callback::OptOut(topic).to_string(),
+        )]),
+        None => keyboard,
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use teloxide::types::InlineKeyboardButtonKind;
+
+    #[test]
+    fn should_convert_callback_to_and_from_str() {
+        for callback in [
+            Callback::OptOut(Topic::Short),
+            Callback::LatestVideos(String::from("spaziogrigio")),
+            Callback::ConfirmBroadcast,
+            Callback::CancelBroadcast,
+        ] {
+            assert_eq!(Callback::from_str(&callback.to_string()).unwrap(), callback);
+        }
+        assert_eq!(
+            Callback::OptOut(Topic::Instagram).to_string().as_str(),
+            "optout:instagram"
+        );
+        assert!(Callback::from_str("optout:tv").is_err());
+        assert!(Callback::from_str("videos:").is_err());
+        assert!(Callback::from_str("segui").is_err());
+        assert!(Callback::from_str("broadcast:").is_err());
+    }
+
+    #[test]
+    fn should_make_video_keyboard() {
+        let keyboard = video(
+            "https://www.youtube.com/watch?v=rRQP8PNEouo",
+            "spaziogrigio",
+            Some(Topic::Video),
+        );
+        assert_eq!(keyboard.inline_keyboard.len(), 2);
+        assert_eq!(keyboard.inline_keyboard[0].len(), 2);
+        assert!(matches!(
+            &keyboard.inline_keyboard[0][0].kind,
+            InlineKeyboardButtonKind::Url(url) if url.as_str() == "https://www.youtube.com/watch?v=rRQP8PNEouo"
+        ));
+        assert!(matches!(
+            &keyboard.inline_keyboard[0][1].kind,
+            InlineKeyboardButtonKind::CallbackData(data) if data.as_str() == "videos:spaziogrigio"
+        ));
+        assert!(matches!(
+            &keyboard.inline_keyboard[1][0].kind,
+            InlineKeyboardButtonKind::CallbackData(data) if data.as_str() == "optout:video"
+        ));
+        assert_eq!(
+            video(
+                "https://www.youtube.com/watch?v=rRQP8PNEouo",
+                "spaziogrigio",
+                None
+            )
+            .inline_keyboard
+            .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn should_make_instagram_post_keyboard() {
+        let keyboard = instagram_post(
+            "https://www.instagram.com/p/CgDkR4aIGkR/",
+            Some(Topic::Instagram),
+        );
+        assert_eq!(keyboard.inline_keyboard.len(), 2);
+        assert_eq!(
+            keyboard.inline_keyboard[0][0].text.as_str(),
+            "Apri su Instagram"
+        );
+        assert_eq!(
+            keyboard.inline_keyboard[1][0].text.as_str(),
+            "Disiscriviti da questo argomento"
+        );
+    }
+}
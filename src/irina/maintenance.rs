@@ -0,0 +1,327 @@
+//! # Maintenance
+//!
+//! Operations run from the command line while the bot is not running: database migration, chats export and import,
+//! one-off job runs and test messages
+
+use super::automatize::Automatizer;
+use super::creator::Creator;
+use super::job::AutomatizerJob;
+use super::repository::{ChatRecord, Repository, SubscriptionRecord};
+use super::Config;
+
+use std::fmt;
+use std::str::FromStr;
+use teloxide::types::ChatId;
+
+/// CSV header of the exported chats
+const CSV_HEADER: &str = "chat_id,created_at,creators,opt_outs,subscriptions";
+/// Separator of the lists in a CSV field
+const CSV_LIST_SEPARATOR: char = ';';
+/// Separator of the target, address and creator of a subscription in a CSV field
+const CSV_SUBSCRIPTION_SEPARATOR: char = '|';
+/// Characters which can't be written in a CSV value, since fields are neither quoted nor escaped
+const CSV_RESERVED: [char; 6] = [
+    ',',
+    CSV_LIST_SEPARATOR,
+    CSV_SUBSCRIPTION_SEPARATOR,
+    '"',
+    '\n',
+    '\r',
+];
+
+/// Format of the exported chats
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChatsFormat {
+    Json,
+    Csv,
+}
+
+impl fmt::Display for ChatsFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json => write!(f, "json"),
+            Self::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+impl FromStr for ChatsFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => anyhow::bail!("unknown format {}; formats are: json, csv", s),
+        }
+    }
+}
+
+/// Maintenance operations
+pub struct Maintenance;
+
+impl Maintenance {
+    /// Create the missing database tables and make the chats which don't follow anybody follow the main creator
+    pub async fn migrate() -> anyhow::Result<()> {
+        Repository::connect()
+            .await?
+            .follow_if_orphan(&Creator::main()?)
+            .await
+    }
+
+    /// Get the subscribed chats
+    pub async fn list_chats() -> anyhow::Result<Vec<ChatRecord>> {
+        Repository::connect().await?.get_chat_records().await
+    }
+
+    /// Export the subscribed chats in `format`
+    pub async fn export_chats(format: ChatsFormat) -> anyhow::Result<String> {
+        let records = Self::list_chats().await?;
+        match format {
+            ChatsFormat::Json => serde_json::to_string_pretty(&records)
+                .map(|x| format!("{}\n", x))
+                .map_err(|e| anyhow::anyhow!("could not serialize chats: {}", e)),
+            ChatsFormat::Csv => to_csv(&records),
+        }
+    }
+
+    /// Import the chats in `data`, exported in `format`.
+    /// Returns the amount of imported chats and of the chats skipped, since already subscribed
+    pub async fn import_chats(format: ChatsFormat, data: &str) -> anyhow::Result<(usize, usize)> {
+        let records: Vec<ChatRecord> = match format {
+            ChatsFormat::Json => serde_json::from_str(data)
+                .map_err(|e| anyhow::anyhow!("invalid JSON chats: {}", e))?,
+            ChatsFormat::Csv => from_csv(data)?,
+        };
+        let repository = Repository::connect().await?;
+        let creators = Creator::all()?;
+        let (mut imported, mut skipped) = (0, 0);
+        for record in records.iter() {
+            if repository.import_chat_record(record, creators).await? {
+                imported += 1;
+            } else {
+                debug!("chat {} is already subscribed; skipping", record.chat_id);
+                skipped += 1;
+            }
+        }
+        Ok((imported, skipped))
+    }
+
+    /// Run job once. Returns the amount of delivered messages
+    pub async fn run_job(job: &str) -> anyhow::Result<usize> {
+        Automatizer::run_job(AutomatizerJob::from_str(job)?).await
+    }
+
+    /// Send a sample announcement from the main creator to `chat`
+    pub async fn send_test(chat: ChatId) -> anyhow::Result<()> {
+        let bot = Config::load()?.bot()?;
+        Automatizer::sample_announcement(&Creator::main()?)
+            .send(&bot, chat)
+            .await
+            .map_err(|e| anyhow::anyhow!("could not send test message to {}: {}", chat, e))
+    }
+}
+
+/// Write chats as CSV; creators, opt-outs and subscriptions are separated by `;`.
+/// Each subscription is written as `target|address|creator`.
+/// Fails if a value contains a separator, since it couldn't be read back
+fn to_csv(records: &[ChatRecord]) -> anyhow::Result<String> {
+    let separator = CSV_LIST_SEPARATOR.to_string();
+    let mut csv = format!("{}\n", CSV_HEADER);
+    for record in records.iter() {
+        let values = std::iter::once(record.created_at.as_str())
+            .chain(record.creators.iter().map(|x| x.as_str()))
+            .chain(record.opt_outs.iter().map(|x| x.as_str()))
+            .chain(
+                record
+                    .subscriptions
+                    .iter()
+                    .flat_map(|x| [x.target.as_str(), x.address.as_str(), x.creator.as_str()]),
+            );
+        for value in values {
+            if value.contains(CSV_RESERVED) {
+                anyhow::bail!(
+                    "cannot export chat {} as CSV: {} contains a separator; export it as JSON instead",
+                    record.chat_id,
+                    value
+                );
+            }
+        }
+        let subscriptions: Vec<String> = record
+            .subscriptions
+            .iter()
+            .map(|x| {
+                [x.target.as_str(), x.address.as_str(), x.creator.as_str()]
+                    .join(&CSV_SUBSCRIPTION_SEPARATOR.to_string())
+            })
+            .collect();
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            record.chat_id,
+            record.created_at,
+            record.creators.join(&separator),
+            record.opt_outs.join(&separator),
+            subscriptions.join(&separator)
+        ));
+    }
+    Ok(csv)
+}
+
+/// Read chats from CSV, as written by `to_csv`
+fn from_csv(data: &str) -> anyhow::Result<Vec<ChatRecord>> {
+    let mut lines = data
+        .lines()
+        .enumerate()
+        .filter(|(_, x)| !x.trim().is_empty());
+    match lines.next() {
+        Some((_, header)) if header.trim() == CSV_HEADER => {}
+        _ => anyhow::bail!("invalid CSV chats: the header must be {}", CSV_HEADER),
+    }
+    lines
+        .map(|(i, line)| {
+            let fields: Vec<&str> = line.trim().split(',').collect();
+            if fields.len() != 5 {
+                anyhow::bail!("invalid CSV chats at line {}: expected 5 fields", i + 1);
+            }
+            let subscriptions = csv_list(fields[4])
+                .iter()
+                .map(|x| {
+                    let parts: Vec<&str> = x.split(CSV_SUBSCRIPTION_SEPARATOR).collect();
+                    match parts.as_slice() {
+                        [target, address, creator] => Ok(SubscriptionRecord {
+                            target: target.to_string(),
+                            address: address.to_string(),
+                            creator: creator.to_string(),
+                        }),
+                        _ => anyhow::bail!("invalid subscription {} at line {}", x, i + 1),
+                    }
+                })
+                .collect::<anyhow::Result<Vec<SubscriptionRecord>>>()?;
+            Ok(ChatRecord {
+                chat_id: fields[0]
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("invalid chat id at line {}: {}", i + 1, e))?,
+                created_at: fields[1].to_string(),
+                creators: csv_list(fields[2]),
+                opt_outs: csv_list(fields[3]),
+                subscriptions,
+            })
+        })
+        .collect()
+}
+
+/// Split a CSV list field
+fn csv_list(field: &str) -> Vec<String> {
+    field
+        .split(CSV_LIST_SEPARATOR)
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_convert_chats_to_and_from_csv() {
+        let records = records();
+        let csv = to_csv(&records).unwrap();
+        assert_eq!(
+            csv.as_str(),
+            "chat_id,created_at,creators,opt_outs,subscriptions\n-1001234567890,2022-09-01T08:00:00+00:00,spaziogrigio;mario,short,email|irina@spaziogrigio.com|spaziogrigio;discord|https://discord.com/api/webhooks/1234/token|mario\n1234,2022-09-02T08:00:00+00:00,spaziogrigio,,\n"
+        );
+        assert_eq!(from_csv(&csv).unwrap(), records);
+    }
+
+    #[test]
+    fn should_not_write_separators_in_csv() {
+        for address in [
+            "https://discord.com/api/webhooks/1234/token,1",
+            "irina;mario@spaziogrigio.com",
+            "https://discord.com/api/webhooks/1234/token|1",
+        ] {
+            let mut records = records();
+            records[0].subscriptions[1].address = address.to_string();
+            assert!(to_csv(&records).is_err());
+        }
+        let mut records = records();
+        records[1].creators.push(String::from("spazio\ngrigio"));
+        assert!(to_csv(&records).is_err());
+    }
+
+    #[test]
+    fn should_convert_chats_to_and_from_json() {
+        let records = records();
+        let json = serde_json::to_string_pretty(&records).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Vec<ChatRecord>>(&json).unwrap(),
+            records
+        );
+        // exports without subscriptions are still accepted
+        let records: Vec<ChatRecord> = serde_json::from_str(
+            r#"[{"chat_id":1234,"created_at":"2022-09-02T08:00:00+00:00","creators":["spaziogrigio"],"opt_outs":[]}]"#,
+        )
+        .unwrap();
+        assert!(records[0].subscriptions.is_empty());
+    }
+
+    #[test]
+    fn should_not_read_invalid_csv() {
+        assert!(from_csv("").is_err());
+        assert!(from_csv("id,date\n1,2022-09-01T08:00:00+00:00").is_err());
+        assert!(from_csv(
+            "chat_id,created_at,creators,opt_outs,subscriptions\n1,2022-09-01T08:00:00+00:00"
+        )
+        .is_err());
+        assert!(from_csv(
+            "chat_id,created_at,creators,opt_outs,subscriptions\nirina,2022-09-01T08:00:00+00:00,,,"
+        )
+        .is_err());
+        assert!(from_csv(
+            "chat_id,created_at,creators,opt_outs,subscriptions\n1,2022-09-01T08:00:00+00:00,,,email|irina"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn should_parse_chats_format() {
+        for format in [ChatsFormat::Json, ChatsFormat::Csv] {
+            assert_eq!(ChatsFormat::from_str(&format.to_string()).unwrap(), format);
+        }
+        assert!(ChatsFormat::from_str("xml").is_err());
+    }
+
+    fn records() -> Vec<ChatRecord> {
+        vec![
+            ChatRecord {
+                chat_id: -1001234567890,
+                created_at: String::from("2022-09-01T08:00:00+00:00"),
+                creators: vec![String::from("spaziogrigio"), String::from("mario")],
+                opt_outs: vec![String::from("short")],
+                subscriptions: vec![
+                    SubscriptionRecord {
+                        target: String::from("email"),
+                        address: String::from("irina@spaziogrigio.com"),
+                        creator: String::from("spaziogrigio"),
+                    },
+                    SubscriptionRecord {
+                        target: String::from("discord"),
+                        address: String::from("https://discord.com/api/webhooks/1234/token"),
+                        creator: String::from("mario"),
+                    },
+                ],
+            },
+            ChatRecord {
+                chat_id: 1234,
+                created_at: String::from("2022-09-02T08:00:00+00:00"),
+                creators: vec![String::from("spaziogrigio")],
+                opt_outs: Vec::new(),
+                subscriptions: Vec::new(),
+            },
+        ]
+    }
+}
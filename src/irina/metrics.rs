@@ -0,0 +1,214 @@
+//! # Metrics
+//!
+//! Counters collected while the bot runs, exposed in the Prometheus text format
+
+use super::job::AutomatizerJob;
+
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Metrics of the running bot
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::default);
+
+/// Bot metrics
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Commands handled, by command name
+    commands: Mutex<BTreeMap<String, u64>>,
+    messages_sent: AtomicU64,
+    messages_failed: AtomicU64,
+    jobs: Mutex<HashMap<AutomatizerJob, JobMetrics>>,
+}
+
+/// Runs of a job
+#[derive(Debug, Default, Copy, Clone)]
+struct JobMetrics {
+    runs: u64,
+    failures: u64,
+    /// Total duration of the runs
+    duration_seconds: f64,
+}
+
+impl Metrics {
+    /// Count a handled command
+    pub fn command(&self, command: &str) {
+        *self
+            .commands
+            .lock()
+            .unwrap()
+            .entry(command.to_string())
+            .or_default() += 1;
+    }
+
+    /// Count a message sent to telegram, either delivered or failed
+    pub fn message(&self, delivered: bool) {
+        let counter = if delivered {
+            &self.messages_sent
+        } else {
+            &self.messages_failed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a run of job
+    pub fn job_run(&self, job: AutomatizerJob, duration_seconds: f64, failed: bool) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let metrics = jobs.entry(job).or_default();
+        metrics.runs += 1;
+        metrics.duration_seconds += duration_seconds;
+        if failed {
+            metrics.failures += 1;
+        }
+    }
+
+    /// Render metrics in the Prometheus text format.
+    /// The subscribers gauge is omitted if the subscribers couldn't be counted
+    pub fn render(&self, subscribers: Option<usize>) -> String {
+        let mut out = String::new();
+        header(
+            &mut out,
+            "irina_commands_total",
+            "counter",
+            "Commands handled, by command",
+        );
+        for (command, count) in self.commands.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "irina_commands_total{{command=\"{}\"}} {}",
+                escape_label(command),
+                count
+            );
+        }
+        header(
+            &mut out,
+            "irina_messages_sent_total",
+            "counter",
+            "Messages sent to telegram",
+        );
+        let _ = writeln!(
+            out,
+            "irina_messages_sent_total {}",
+            self.messages_sent.load(Ordering::Relaxed)
+        );
+        header(
+            &mut out,
+            "irina_messages_failed_total",
+            "counter",
+            "Messages which could not be sent to telegram",
+        );
+        let _ = writeln!(
+            out,
+            "irina_messages_failed_total {}",
+            self.messages_failed.load(Ordering::Relaxed)
+        );
+        let jobs = self.jobs.lock().unwrap();
+        header(
+            &mut out,
+            "irina_job_duration_seconds",
+            "summary",
+            "Duration of the automatizer job runs",
+        );
+        for job in AutomatizerJob::ALL {
+            let metrics = jobs.get(&job).copied().unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "irina_job_duration_seconds_sum{{job=\"{}\"}} {}",
+                job, metrics.duration_seconds
+            );
+            let _ = writeln!(
+                out,
+                "irina_job_duration_seconds_count{{job=\"{}\"}} {}",
+                job, metrics.runs
+            );
+        }
+        header(
+            &mut out,
+            "irina_job_failures_total",
+            "counter",
+            "Failed runs of the automatizer jobs",
+        );
+        for job in AutomatizerJob::ALL {
+            let _ = writeln!(
+                out,
+                "irina_job_failures_total{{job=\"{}\"}} {}",
+                job,
+                jobs.get(&job).map(|x| x.failures).unwrap_or_default()
+            );
+        }
+        if let Some(subscribers) = subscribers {
+            header(
+                &mut out,
+                "irina_subscribers",
+                "gauge",
+                "Chats subscribed to the bot",
+            );
+            let _ = writeln!(out, "irina_subscribers {}", subscribers);
+        }
+        out
+    }
+}
+
+/// Write the help and type lines of a metric
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_render_metrics() {
+        let metrics = Metrics::default();
+        metrics.command("segui");
+        metrics.command("segui");
+        metrics.command("help");
+        metrics.message(true);
+        metrics.message(true);
+        metrics.message(false);
+        metrics.job_run(AutomatizerJob::Video, 1.5, false);
+        metrics.job_run(AutomatizerJob::Video, 0.5, true);
+        let rendered = metrics.render(Some(42));
+        for line in [
+            "# TYPE irina_commands_total counter",
+            "irina_commands_total{command=\"help\"} 1",
+            "irina_commands_total{command=\"segui\"} 2",
+            "irina_messages_sent_total 2",
+            "irina_messages_failed_total 1",
+            "# TYPE irina_job_duration_seconds summary",
+            "irina_job_duration_seconds_sum{job=\"video\"} 2",
+            "irina_job_duration_seconds_count{job=\"video\"} 2",
+            "irina_job_duration_seconds_count{job=\"feed\"} 0",
+            "irina_job_failures_total{job=\"video\"} 1",
+            "irina_subscribers 42",
+        ] {
+            assert!(
+                rendered.lines().any(|x| x == line),
+                "missing {} in\n{}",
+                line,
+                rendered
+            );
+        }
+        assert!(!metrics.render(None).contains("irina_subscribers"));
+    }
+
+    #[test]
+    fn should_escape_label() {
+        assert_eq!(escape_label("a\"b\\c\n").as_str(), "a\\\"b\\\\c\\n");
+    }
+}
@@ -2,74 +2,180 @@
 //!
 //! This module implements the spazio grigio bot
 
+mod admin;
 mod answer;
 mod automatize;
+mod broadcast;
 mod commands;
 mod config;
+mod creator;
+mod delivery;
+mod feeds;
+#[cfg(test)]
+mod harness;
+mod inline;
 mod instagram;
+mod job;
+mod keyboard;
+mod maintenance;
+mod metrics;
 mod morning_routine;
 mod newsletter;
 mod redis;
 mod repository;
+mod rich_text;
+mod template;
+mod topic;
+mod web;
 mod youtube;
 
-use teloxide::{dispatching::update_listeners::webhooks, prelude::*, utils::command::BotCommands};
-use url::Url;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use teloxide::{
+    dispatching::{update_listeners::webhooks, DefaultKey, UpdateHandler},
+    prelude::*,
+    types::{Chat, ChatMemberUpdated, InputFile, UserId},
+    utils::command::BotCommands,
+};
 
-use answer::{Answer, AnswerBuilder};
-use automatize::Automatizer;
-use commands::Command;
-use config::Config;
+use admin::Admin;
+use answer::{Answer, AnswerBuilder, AudioMetadata};
+pub use automatize::Automatizer;
+use broadcast::{Broadcasts, DraftItem, DraftUpdate};
+use commands::{AdminCommand, Command};
+pub use config::Config;
+use config::WebhookConfig;
+use creator::Creator;
+use delivery::TargetKind;
+use inline::Inline;
+use keyboard::Callback;
+pub use maintenance::{ChatsFormat, Maintenance};
+use metrics::METRICS;
 use morning_routine::MorningRoutine;
 use once_cell::sync::OnceCell;
+use repository::Repository;
+use rich_text::RichText;
+pub use template::Template;
+use topic::Topic;
 
 pub static AUTOMATIZER: OnceCell<Automatizer> = OnceCell::new();
 
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
 /// Irina bot application
 pub struct Irina {
     bot: AutoSend<Bot>,
+    state: State,
+}
+
+/// Shared state, injected into the update handlers
+#[derive(Clone)]
+struct State {
+    automatizer: &'static Automatizer,
+    /// Users allowed to use the admin commands
+    admins: Vec<UserId>,
+    /// Broadcasts being composed by the admins
+    broadcasts: Broadcasts,
+}
+
+impl State {
+    /// Check whether message has been sent by an admin
+    fn is_admin(&self, message: &Message) -> bool {
+        message
+            .from()
+            .map(|user| self.admins.contains(&user.id))
+            .unwrap_or(false)
+    }
+
+    /// Check whether message has been sent by an admin who is composing a broadcast
+    fn is_composing_broadcast(&self, message: &Message) -> bool {
+        self.is_admin(message)
+            && message
+                .from()
+                .map(|user| self.broadcasts.is_composing(message.chat.id, user.id))
+                .unwrap_or(false)
+    }
 }
 
 impl Irina {
     /// Initialize irina
     pub async fn init() -> anyhow::Result<Self> {
-        if let Err(err) = Config::try_from_env() {
-            anyhow::bail!("Failed to load configuraiton from env: {}", err);
-        }
+        let config = match Config::load() {
+            Ok(config) => config,
+            Err(err) => anyhow::bail!("Failed to load configuration: {}", err),
+        };
+        Repository::connect()
+            .await?
+            .follow_if_orphan(&Creator::main()?)
+            .await?;
         let automatizer = Automatizer::start()
             .await
             .map_err(|e| anyhow::anyhow!("failed to start automatizer: {}", e))?;
         if AUTOMATIZER.set(automatizer).is_err() {
             anyhow::bail!("failed to set automatizer");
         };
-        let bot = Bot::from_env().auto_send();
-        Ok(Self { bot })
+        let bot = config.bot()?;
+        let state = State {
+            automatizer: AUTOMATIZER.get().unwrap(),
+            admins: config.admin_ids()?,
+            broadcasts: Broadcasts::default(),
+        };
+        Ok(Self { bot, state })
     }
 
     /// Run irina
     pub async fn run(self) -> anyhow::Result<()> {
-        // setup hooks
-        let port = Self::get_heroku_port()?;
-        if let Some(port) = port {
-            Self::run_on_heroku(self, port).await
-        } else {
-            Self::run_simple(self).await
-        }
-    }
-
-    /// run bot with heroku webhooks
-    async fn run_on_heroku(self, port: u16) -> anyhow::Result<()> {
-        info!("running bot with heroku listener (PORT: {})", port);
-        let addr = ([0, 0, 0, 0], port).into();
-        let token = self.bot.inner().token();
-        let host = std::env::var("HOST").map_err(|_| anyhow::anyhow!("HOST is not SET"))?;
-        let url = Url::parse(&format!("https://{host}/webhooks/{token}")).unwrap();
-        debug!("configuring listener {}...", url);
-        let listener = webhooks::axum(self.bot.clone(), webhooks::Options::new(addr, url))
+        match Config::load()?.webhook()? {
+            Some(webhook) => Self::run_with_webhooks(self, webhook).await,
+            None => Self::run_simple(self).await,
+        }
+    }
+
+    /// run bot with webhooks. The public http endpoints are served along with the webhooks,
+    /// while the probes and metrics are served on `HTTP_ADDRESS`, if set
+    async fn run_with_webhooks(self, webhook: WebhookConfig) -> anyhow::Result<()> {
+        let http_address = Self::http_address()?;
+        if http_address == Some(webhook.address) {
+            anyhow::bail!("HTTP_ADDRESS must differ from the webhooks address");
+        }
+        info!(
+            "running bot with webhooks on {} (address: {})",
+            // the path may contain the token
+            webhook.url.host_str().unwrap_or_default(),
+            webhook.address
+        );
+        let mut options = webhooks::Options::new(webhook.address, webhook.url);
+        if let Some(secret_token) = webhook.secret_token {
+            options = options.secret_token(secret_token);
+        }
+        if let Some(certificate) = webhook.certificate {
+            debug!("uploading certificate {}", certificate.display());
+            options = options.certificate(InputFile::file(certificate));
+        }
+        let (listener, stop_flag, router) = webhooks::axum_to_router(self.bot.clone(), options)
             .await
             .map_err(|e| anyhow::anyhow!("could not configure listener: {}", e))?;
+        // serve webhooks along with the public http endpoints
+        let router = router.merge(web::public_router());
+        let address = webhook.address;
+        tokio::spawn(async move {
+            if let Err(err) = axum::Server::bind(&address)
+                .serve(router.into_make_service())
+                .with_graceful_shutdown(stop_flag)
+                .await
+            {
+                error!("http server error: {}", err);
+            }
+        });
+        if let Some(address) = http_address {
+            Self::serve_http(address, web::internal_router());
+        }
         // start bot
-        teloxide::commands_repl_with_listener(self.bot, Self::answer, listener, Command::ty())
+        Self::dispatcher(self.bot, self.state)
+            .dispatch_with_listener(
+                listener,
+                LoggingErrorHandler::with_custom_text("An error from the update listener"),
+            )
             .await;
         Ok(())
     }
@@ -77,89 +183,444 @@ impl Irina {
     /// run bot without webhooks
     async fn run_simple(self) -> anyhow::Result<()> {
         info!("running bot without webhooks");
-        teloxide::commands_repl(self.bot, Self::answer, Command::ty()).await;
+        if let Some(address) = Self::http_address()? {
+            Self::serve_http(address, web::router());
+        }
+        Self::dispatcher(self.bot, self.state).dispatch().await;
         Ok(())
     }
 
+    /// Get the address to serve the http endpoints on, if configured
+    fn http_address() -> anyhow::Result<Option<SocketAddr>> {
+        Config::load()?
+            .http_address
+            .as_deref()
+            .map(|address| {
+                address
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("invalid HTTP_ADDRESS {}: {}", address, e))
+            })
+            .transpose()
+    }
+
+    /// Serve router on address in background
+    fn serve_http(address: SocketAddr, router: axum::Router) {
+        tokio::spawn(async move {
+            if let Err(err) = web::serve(address, router).await {
+                error!("{}", err);
+            }
+        });
+    }
+
+    /// Build the dispatcher with the handler tree. Updates which are not handled are ignored
+    fn dispatcher(
+        bot: AutoSend<Bot>,
+        state: State,
+    ) -> Dispatcher<AutoSend<Bot>, Box<dyn std::error::Error + Send + Sync>, DefaultKey> {
+        Dispatcher::builder(bot, Self::handler())
+            .dependencies(dptree::deps![state])
+            .default_handler(|_| async {})
+            .enable_ctrlc_handler()
+            .build()
+    }
+
+    /// Build the handler tree
+    fn handler() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+        dptree::entry()
+            .branch(
+                Update::filter_message()
+                    .branch(
+                        dptree::filter(|message: Message, state: State| state.is_admin(&message))
+                            .filter_command::<AdminCommand>()
+                            .endpoint(Self::admin),
+                    )
+                    .branch(
+                        dptree::entry()
+                            .filter_command::<Command>()
+                            .endpoint(Self::answer),
+                    )
+                    .branch(
+                        dptree::filter(|message: Message, state: State| {
+                            state.is_composing_broadcast(&message)
+                        })
+                        .endpoint(Self::broadcast_draft),
+                    )
+                    .branch(dptree::endpoint(Self::message)),
+            )
+            .branch(Update::filter_callback_query().endpoint(Self::callback))
+            .branch(Update::filter_inline_query().endpoint(Self::inline_query))
+            .branch(Update::filter_my_chat_member().endpoint(Self::chat_member))
+    }
+
     /// Answer handler for bot
     async fn answer(
         bot: AutoSend<Bot>,
         message: Message,
         command: Command,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        state: State,
+    ) -> HandlerResult {
         debug!("got command {:?}", command);
+        Self::count_command(&message);
         let answer = match command {
             Command::Start => Self::start(),
             Command::Help => Answer::simple_text(Command::descriptions()),
-            Command::CiaoIrina => Self::subscribe_to_automatizer(&message.chat.id).await,
+            Command::CiaoIrina => Self::subscribe_to_automatizer(&state, &message.chat.id).await,
             Command::BuongiornoIrina => Self::good_morning(),
-            Command::SiAlConsumismo => Self::unsubscribe_from_automatizer(&message.chat.id).await,
-            Command::SerataSenzaTv => Self::get_latest_videos().await,
-            Command::VideoMinimalista => Self::get_latest_video().await,
-            Command::PostMinimalista => Self::get_latest_post().await,
+            Command::SiAlConsumismo => {
+                Self::unsubscribe_from_automatizer(&state, &message.chat.id).await
+            }
+            Command::Creatori => Self::get_creators(&message.chat.id).await,
+            Command::Segui(creator) => Self::follow(&state, &message.chat.id, &creator).await,
+            Command::NonSeguire(creator) => {
+                Self::unfollow(&state, &message.chat.id, &creator).await
+            }
+            Command::Inoltra(args) => Self::forward(&state, &message.chat.id, &args).await,
+            Command::NonInoltrare(args) => Self::unforward(&state, &message.chat.id, &args).await,
+            Command::NienteShorts => Self::opt_out(&state, &message.chat.id, Topic::Short).await,
+            Command::VoglioShorts => Self::opt_in(&state, &message.chat.id, Topic::Short).await,
+            Command::SerataSenzaTv(creator) => Self::get_latest_videos(&creator).await,
+            Command::VideoMinimalista(creator) => Self::get_latest_video(&creator).await,
+            Command::PostMinimalista(creator) => Self::get_latest_post(&creator).await,
         };
         answer.send(&bot, message.chat.id).await
     }
 
-    /// Get latest videos from papi
-    async fn get_latest_videos() -> Answer {
-        match youtube::Youtube::get_latest_videos().await {
+    /// Admin commands handler
+    async fn admin(
+        bot: AutoSend<Bot>,
+        message: Message,
+        command: AdminCommand,
+        state: State,
+    ) -> HandlerResult {
+        // admins are recognized by the message sender
+        let user = match message.from() {
+            Some(user) => user.id,
+            None => return Ok(()),
+        };
+        info!("got admin command {:?} from {}", command, user);
+        Self::count_command(&message);
+        Admin::answer(
+            state.automatizer,
+            &state.broadcasts,
+            message.chat.id,
+            user,
+            command,
+        )
+        .await
+        .send(&bot, message.chat.id)
+        .await
+    }
+
+    /// Count the command sent with message in the metrics
+    fn count_command(message: &Message) {
+        if let Some(name) = message.text().and_then(commands::command_name) {
+            METRICS.command(name);
+        }
+    }
+
+    /// Handler for the messages sent by an admin composing a broadcast.
+    /// The preview of an album is sent once all its photos have been received
+    async fn broadcast_draft(bot: AutoSend<Bot>, message: Message, state: State) -> HandlerResult {
+        let chat_id = message.chat.id;
+        let user = match message.from() {
+            Some(user) => user.id,
+            None => return Ok(()),
+        };
+        let item = match DraftItem::from_message(&message) {
+            Some(item) => item,
+            None => {
+                return Answer::simple_text("Posso inviare solo testo, foto o album")
+                    .send(&bot, chat_id)
+                    .await
+            }
+        };
+        match state.broadcasts.add(chat_id, user, item) {
+            Some(DraftUpdate::Preview) => {
+                if let Err(err) = state.broadcasts.send_preview(&bot, chat_id, user).await {
+                    error!("{}", err);
+                    return Self::error(err).send(&bot, chat_id).await;
+                }
+            }
+            Some(DraftUpdate::AlbumStarted) => {
+                state.broadcasts.send_album_preview(bot, chat_id, user)
+            }
+            Some(DraftUpdate::AlbumContinued) | None => {}
+        }
+        Ok(())
+    }
+
+    /// Handler for the messages which are not commands.
+    /// Private chats are told how to get help, while messages in groups are ignored
+    async fn message(bot: AutoSend<Bot>, message: Message) -> HandlerResult {
+        if !message.chat.is_private() || message.text().is_none() {
+            return Ok(());
+        }
+        debug!("got message from {}", message.chat.id);
+        Answer::simple_text("Non ho capito. Digita /help per vedere cosa posso fare per te")
+            .send(&bot, message.chat.id)
+            .await
+    }
+
+    /// Callback query handler for the inline keyboard buttons
+    async fn callback(bot: AutoSend<Bot>, query: CallbackQuery, state: State) -> HandlerResult {
+        let is_admin = state.admins.contains(&query.from.id);
+        let callback = match (query.message.as_ref(), query.data.as_deref()) {
+            (Some(message), Some(data)) => Callback::from_str(data).map(|x| (message, x)),
+            _ => Err(anyhow::anyhow!("callback query without message or data")),
+        };
+        let (message, callback) = match callback {
+            Ok(callback) => callback,
+            Err(err) => {
+                warn!("{}", err);
+                bot.answer_callback_query(query.id).await?;
+                return Ok(());
+            }
+        };
+        debug!("got callback {:?}", callback);
+        let chat_id = message.chat.id;
+        if matches!(callback, Callback::OptOut(_))
+            && !Self::can_manage(&bot, &message.chat, query.from.id).await
+        {
+            warn!(
+                "opt-out callback from {} who can't manage {}",
+                query.from.id, chat_id
+            );
+            bot.answer_callback_query(query.id)
+                .text("Solo gli amministratori del gruppo possono disattivare le notifiche")
+                .show_alert(true)
+                .await?;
+            return Ok(());
+        }
+        bot.answer_callback_query(query.id).await?;
+        let answer = match callback {
+            Callback::OptOut(topic) => Self::opt_out(&state, &chat_id, topic).await,
+            Callback::LatestVideos(creator) => Self::get_latest_videos(&creator).await,
+            Callback::ConfirmBroadcast | Callback::CancelBroadcast if !is_admin => {
+                warn!("broadcast callback from non admin user {}", query.from.id);
+                return Ok(());
+            }
+            Callback::ConfirmBroadcast => {
+                Self::confirm_broadcast(&bot, &state, chat_id, query.from.id).await
+            }
+            Callback::CancelBroadcast => {
+                state.broadcasts.take(chat_id, query.from.id);
+                Answer::simple_text("Broadcast annullato")
+            }
+        };
+        answer.send(&bot, chat_id).await
+    }
+
+    /// Returns whether `user` can change the settings of `chat`:
+    /// anybody in private chats, only the administrators in groups and channels
+    async fn can_manage(bot: &AutoSend<Bot>, chat: &Chat, user: UserId) -> bool {
+        if chat.is_private() {
+            return true;
+        }
+        match bot.get_chat_member(chat.id, user).await {
+            Ok(member) => member.is_privileged(),
+            Err(err) => {
+                error!("could not get member {} of {}: {}", user, chat.id, err);
+                false
+            }
+        }
+    }
+
+    /// Send the broadcast composed by user in chat to every subscribed chat and report the outcome
+    async fn confirm_broadcast(
+        bot: &AutoSend<Bot>,
+        state: &State,
+        chat_id: ChatId,
+        user: UserId,
+    ) -> Answer {
+        let answer = match state.broadcasts.take(chat_id, user) {
+            Some(answer) => answer,
+            None => return Answer::simple_text("Non c'è nessun broadcast da inviare"),
+        };
+        info!("sending broadcast composed by {} in {}", user, chat_id);
+        match broadcast::send(bot, answer).await {
+            Ok((delivered, failed)) => Answer::simple_text(format!(
+                "Broadcast inviato: {} consegnati, {} falliti",
+                delivered, failed
+            )),
+            Err(err) => Self::error(err),
+        }
+    }
+
+    /// Inline query handler; answers with the recent and archived contents matching the query
+    async fn inline_query(bot: AutoSend<Bot>, query: InlineQuery) -> HandlerResult {
+        debug!("got inline query {:?}", query.query);
+        Inline::answer(&bot, query).await.map_err(|e| e.into())
+    }
+
+    /// Handler for the changes of the bot membership in a chat.
+    /// Chats which block or remove the bot are unsubscribed; groups which add it are welcomed
+    async fn chat_member(
+        bot: AutoSend<Bot>,
+        update: ChatMemberUpdated,
+        state: State,
+    ) -> HandlerResult {
+        let chat_id = update.chat.id;
+        if update.chat.is_channel() {
+            return Ok(());
+        }
+        if update.old_chat_member.is_present() && !update.new_chat_member.is_present() {
+            info!("bot has been removed from {}", chat_id);
+            if let Err(err) = state.automatizer.unsubscribe(&chat_id).await {
+                debug!("could not unsubscribe {}: {}", chat_id, err);
+            }
+        } else if !update.old_chat_member.is_present() && update.new_chat_member.is_present() {
+            info!("bot has been added to {}", chat_id);
+            if !update.chat.is_private() {
+                Self::start().send(&bot, chat_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get latest videos from creator
+    async fn get_latest_videos(creator: &str) -> Answer {
+        let creator = match Creator::find(creator) {
+            Ok(creator) => creator,
+            Err(err) => return Self::error(err),
+        };
+        match youtube::Youtube::get_latest_videos(&creator).await {
             Ok(feed) => {
-                let mut message =
-                    String::from("Ciao sono Irina. Ecco cosa puoi guardare questa sera:\n\n");
+                let mut message = RichText::default().plain(format!(
+                    "Ciao sono {}. Ecco cosa puoi guardare questa sera:\n\n",
+                    creator.persona
+                ));
                 for video in feed.entries() {
-                    message.push_str(
-                        format!(
-                            "• {} 👉 {}\n",
-                            video.title.as_deref().unwrap_or_default(),
-                            video.url
-                        )
-                        .as_str(),
-                    );
+                    message = message
+                        .plain("• ")
+                        .link(video.title.as_deref().unwrap_or(&video.url), &video.url)
+                        .plain("\n");
                 }
-                Answer::simple_text(message)
+                AnswerBuilder::default().text(message).finalize()
             }
             Err(err) => Self::error(err),
         }
     }
 
-    /// Get latest video from papi
-    async fn get_latest_video() -> Answer {
-        match youtube::Youtube::get_latest_video().await {
-            Ok(video) => Answer::simple_text(format!(
-                "Ciao sono Irina. Guarda il mio ultimo video \"{}\" 👉 {}",
-                video.title.unwrap_or_default(),
-                video.url
-            )),
+    /// Get latest video from creator
+    async fn get_latest_video(creator: &str) -> Answer {
+        let creator = match Creator::find(creator) {
+            Ok(creator) => creator,
+            Err(err) => return Self::error(err),
+        };
+        match youtube::Youtube::get_latest_video(&creator).await {
+            Ok(video) => {
+                let mut answer = AnswerBuilder::default();
+                if let Some(thumbnail) = crate::youtube::thumbnail(&video) {
+                    answer = answer.image(thumbnail);
+                }
+                answer
+                    .text(
+                        RichText::default()
+                            .plain(format!(
+                                "Ciao sono {}. Guarda il mio ultimo video: ",
+                                creator.persona
+                            ))
+                            .link(video.title.as_deref().unwrap_or(&video.url), &video.url),
+                    )
+                    .keyboard(keyboard::video(&video.url, &creator.id, None))
+                    .finalize()
+            }
             Err(err) => Self::error(err),
         }
     }
 
-    async fn get_latest_post() -> Answer {
-        match instagram::InstagramService::get_latest_post().await {
+    async fn get_latest_post(creator: &str) -> Answer {
+        let creator = match Creator::find(creator) {
+            Ok(creator) => creator,
+            Err(err) => return Self::error(err),
+        };
+        match instagram::InstagramService::get_latest_post(&creator).await {
             Ok(post) => AnswerBuilder::default()
                 .text(format!(
-                    "Ciao sono Irina. Guarda il mio ultimo post su instagram:\n{}",
-                    post.caption.unwrap_or_default()
+                    "Ciao sono {}. Guarda il mio ultimo post su instagram:\n{}",
+                    creator.persona,
+                    post.caption.as_deref().unwrap_or_default()
+                ))
+                .image(&post.display_url)
+                .keyboard(keyboard::instagram_post(
+                    &instagram::InstagramService::post_url(&post),
+                    None,
                 ))
-                .image(post.display_url)
                 .finalize(),
             Err(err) => Self::error(err),
         }
     }
 
+    /// List creators, marking those followed by chat
+    async fn get_creators(chat_id: &ChatId) -> Answer {
+        let creators = match Creator::all() {
+            Ok(creators) => creators,
+            Err(err) => return Self::error(err),
+        };
+        let followed = match Repository::connect().await {
+            Ok(repository) => repository
+                .get_followed_creators(*chat_id)
+                .await
+                .unwrap_or_default(),
+            Err(err) => return Self::error(err),
+        };
+        let mut message = RichText::from("Ecco chi puoi seguire:\n\n");
+        for creator in creators.iter() {
+            let mark = if followed.contains(&creator.id) {
+                "✅"
+            } else {
+                "•"
+            };
+            message = message
+                .plain(format!("{} {} 👉 ", mark, creator.persona))
+                .code(format!("/segui {}", creator.id))
+                .plain("\n");
+        }
+        AnswerBuilder::default().text(message).finalize()
+    }
+
+    /// Follow creator
+    async fn follow(state: &State, chat_id: &ChatId, creator: &str) -> Answer {
+        let creator = match Creator::find(creator) {
+            Ok(creator) => creator,
+            Err(err) => return Self::error(err),
+        };
+        match state.automatizer.follow(chat_id, &creator).await {
+            Ok(()) => Answer::simple_text(format!(
+                "Ciao sono {}. Da ora riceverai anche i miei aggiornamenti.",
+                creator.persona
+            )),
+            Err(err) => Self::error(err),
+        }
+    }
+
+    /// Unfollow creator
+    async fn unfollow(state: &State, chat_id: &ChatId, creator: &str) -> Answer {
+        let creator = match Creator::find(creator) {
+            Ok(creator) => creator,
+            Err(err) => return Self::error(err),
+        };
+        match state.automatizer.unfollow(chat_id, &creator).await {
+            Ok(()) => Answer::simple_text(format!(
+                "Ciao sono {}. Non riceverai più i miei aggiornamenti.",
+                creator.persona
+            )),
+            Err(err) => Self::error(err),
+        }
+    }
+
     /// Subscribe chat to the automatizer
-    async fn subscribe_to_automatizer(chat_id: &ChatId) -> Answer {
-        match AUTOMATIZER.get().unwrap().subscribe(chat_id).await {
+    async fn subscribe_to_automatizer(state: &State, chat_id: &ChatId) -> Answer {
+        match state.automatizer.subscribe(chat_id).await {
             Ok(_) => AnswerBuilder::default()
-            .text("Ciao sono Irina e ti do il benvenuto in Spazio Grigio. Da ora riceverai tutti gli aggiornamenti per proseguire nel tuo percorso verso il Minimalismo.")
-            .finalize(),
+                .text(Template::Welcome.render(&[]))
+                .finalize(),
             Err(err) => Self::error(err),
         }
     }
 
-    async fn unsubscribe_from_automatizer(chat_id: &ChatId) -> Answer {
-        match AUTOMATIZER.get().unwrap().unsubscribe(chat_id).await {
+    async fn unsubscribe_from_automatizer(state: &State, chat_id: &ChatId) -> Answer {
+        match state.automatizer.unsubscribe(chat_id).await {
             Ok(()) => AnswerBuilder::default()
                 .text("Hai deciso di abbandonare il tuo percorso verso il Minimalismo. Mi dispiace tanto, se vuoi cambiare idea, ricomincia da qui /ciaoirina")
                 .finalize(),
@@ -167,11 +628,74 @@ impl Irina {
         }
     }
 
+    /// Opt chat out from topic
+    async fn opt_out(state: &State, chat_id: &ChatId, topic: Topic) -> Answer {
+        match state.automatizer.opt_out(chat_id, topic).await {
+            Ok(()) => Answer::simple_text(format!("Non riceverai più le notifiche per: {}", topic)),
+            Err(err) => Self::error(err),
+        }
+    }
+
+    /// Opt chat in to topic
+    async fn opt_in(state: &State, chat_id: &ChatId, topic: Topic) -> Answer {
+        match state.automatizer.opt_in(chat_id, topic).await {
+            Ok(()) => Answer::simple_text(format!(
+                "Da ora riceverai di nuovo le notifiche per: {}",
+                topic
+            )),
+            Err(err) => Self::error(err),
+        }
+    }
+
+    /// Forward the announcements received by chat to a delivery target
+    async fn forward(state: &State, chat_id: &ChatId, args: &str) -> Answer {
+        let (target, address, code) = match TargetKind::parse_forward(args) {
+            Ok(target) => target,
+            Err(err) => return Self::error(err),
+        };
+        let result = match code {
+            Some(code) => state
+                .automatizer
+                .confirm_forward(chat_id, target, &address, &code)
+                .await
+                .map(|_| false),
+            None => state.automatizer.forward(chat_id, target, &address).await,
+        };
+        match result {
+            Ok(true) => Answer::simple_text(format!(
+                "Ho inviato un codice di conferma a {}. Per confermare l'inoltro invia /inoltra {} <indirizzo> <codice>",
+                target.redact(&address),
+                target
+            )),
+            Ok(false) if target == TargetKind::Email => Answer::simple_text(format!(
+                "Da ora {} riceverà ogni sera il riepilogo degli aggiornamenti dei creator che segui",
+                target.redact(&address)
+            )),
+            Ok(false) => Answer::simple_text(format!(
+                "Da ora inoltrerò a {} gli aggiornamenti dei creator che segui",
+                target.redact(&address)
+            )),
+            Err(err) => Self::error(err),
+        }
+    }
+
+    /// Stop forwarding announcements to a delivery target
+    async fn unforward(state: &State, chat_id: &ChatId, args: &str) -> Answer {
+        let (target, address) = match TargetKind::parse_address(args) {
+            Ok(target) => target,
+            Err(err) => return Self::error(err),
+        };
+        match state.automatizer.unforward(chat_id, target, &address).await {
+            Ok(()) => Answer::simple_text(format!(
+                "Non inoltrerò più aggiornamenti a {}",
+                target.redact(&address)
+            )),
+            Err(err) => Self::error(err),
+        }
+    }
+
     pub fn good_morning() -> Answer {
-        Answer::simple_text(format!(
-            "Buongiorno sono Irina. Segui la mia morning routine per cominciare la tua giornata 👉 {}",
-            MorningRoutine::get_random()
-        ))
+        Answer::simple_text(Template::GoodMorning.render(&[("url", MorningRoutine::get_random())]))
     }
 
     fn start() -> Answer {
@@ -182,15 +706,445 @@ impl Irina {
 
     /// The answer to return in case of an error
     fn error(err: impl ToString) -> Answer {
-        AnswerBuilder::default().text(err).finalize()
+        AnswerBuilder::default().text(err.to_string()).finalize()
     }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::harness::{environment, lock_jobs, ApiCall, Harness, ADMIN, GROUP_OWNER};
+    use super::job::AutomatizerJob;
+    use super::*;
 
-    // get heroku port
-    fn get_heroku_port() -> anyhow::Result<Option<u16>> {
-        match std::env::var("PORT").map(|x| x.parse()) {
-            Err(_) => Ok(None),
-            Ok(Ok(p)) => Ok(Some(p)),
-            Ok(Err(e)) => anyhow::bail!("could not parse PORT environment variable: {}", e),
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn should_answer_start_and_help() {
+        let harness = Harness::new().await;
+        let chat = ChatId(2001);
+        let calls = harness.ask(chat, "/start").await;
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].method.as_str(), "sendMessage");
+        assert!(calls[0].text().contains("benvenuto in Spazio Grigio"));
+        let calls = harness.ask(chat, "/help").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].text().contains("/ciaoirina"));
+        assert!(calls[0].text().contains("/videominimalista"));
+    }
+
+    #[tokio::test]
+    async fn should_subscribe_and_unsubscribe() {
+        let harness = Harness::new().await;
+        let chat = ChatId(2002);
+        let calls = harness.ask(chat, "/ciaoirina").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].text().contains("ti do il benvenuto"));
+        let calls = harness.ask(chat, "/ciaoirina").await;
+        assert!(calls[0].text().contains("già iscritto"));
+        let calls = harness.ask(chat, "/sialconsumismo").await;
+        assert!(calls[0].text().contains("abbandonare il tuo percorso"));
+    }
+
+    #[tokio::test]
+    async fn should_say_good_morning() {
+        let harness = Harness::new().await;
+        let calls = harness.ask(ChatId(2003), "/buongiornoirina").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].text().starts_with("Buongiorno sono Irina"));
+    }
+
+    #[tokio::test]
+    async fn should_follow_and_unfollow_creators() {
+        let harness = Harness::new().await;
+        let chat = ChatId(2004);
+        harness.send_text(chat, "/ciaoirina").await;
+        let calls = harness.ask(chat, "/creatori").await;
+        assert!(calls[0].text().contains("✅ Irina"));
+        assert!(calls[0].text().contains("✅ Mario"));
+        let calls = harness.ask(chat, "/nonseguire minimalista").await;
+        assert!(calls[0]
+            .text()
+            .contains("Ciao sono Mario. Non riceverai più i miei aggiornamenti."));
+        let calls = harness.ask(chat, "/creatori").await;
+        assert!(calls[0].text().contains("• Mario"));
+        let calls = harness.ask(chat, "/segui minimalista").await;
+        assert!(calls[0]
+            .text()
+            .contains("Ciao sono Mario. Da ora riceverai anche i miei aggiornamenti."));
+        let calls = harness.ask(chat, "/segui nessuno").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].text().contains("nessuno"));
+    }
+
+    #[tokio::test]
+    async fn should_forward_and_unforward() {
+        let harness = Harness::new().await;
+        let chat = ChatId(2005);
+        harness.send_text(chat, "/ciaoirina").await;
+        let room = "!room2005:spaziogrigio.com";
+        let calls = harness
+            .ask(chat, &format!("/inoltra matrix {}", room))
+            .await;
+        assert!(calls[0]
+            .text()
+            .starts_with("Ho inviato un codice di conferma a !room2005:spaziogrigio.com"));
+        let messages = environment().await.matrix_messages(room).await;
+        assert_eq!(messages.len(), 1);
+        let code = messages[0].rsplit(' ').next().unwrap();
+        // nothing is forwarded until the code is confirmed
+        let calls = harness
+            .ask(chat, &format!("/inoltra matrix {} 000000", room))
+            .await;
+        assert_eq!(calls[0].text(), "Il codice di conferma non è valido");
+        let calls = harness
+            .ask(chat, &format!("/inoltra matrix {} {}", room, code))
+            .await;
+        assert!(calls[0].text().starts_with("Da ora inoltrerò a"));
+        // another chat can't stop forwarding to the room
+        let other = ChatId(2014);
+        harness.send_text(other, "/ciaoirina").await;
+        let calls = harness
+            .ask(other, &format!("/noninoltrare matrix {}", room))
+            .await;
+        assert!(calls[0].text().starts_with("Non inoltro niente a"));
+        let calls = harness
+            .ask(chat, &format!("/noninoltrare matrix {}", room))
+            .await;
+        assert!(calls[0]
+            .text()
+            .starts_with("Non inoltrerò più aggiornamenti a"));
+    }
+
+    #[tokio::test]
+    async fn should_redact_discord_webhook_token() {
+        let harness = Harness::new().await;
+        let chat = ChatId(2015);
+        let webhook = "https://discord.com/api/webhooks/1/token";
+        let calls = harness
+            .ask(chat, &format!("/noninoltrare discord {}", webhook))
+            .await;
+        assert_eq!(
+            calls[0].text(),
+            "Non inoltro niente a discord.com/api/webhooks/1/…"
+        );
+    }
+
+    #[tokio::test]
+    async fn should_let_only_group_admins_opt_out() {
+        let harness = Harness::new().await;
+        let group = ChatId(-2016);
+        Repository::connect()
+            .await
+            .unwrap()
+            .insert_chat(group, Creator::all().unwrap())
+            .await
+            .unwrap();
+        let calls = harness.press(group, UserId(2016), "optout:short").await;
+        assert_eq!(
+            calls
+                .iter()
+                .map(|x| x.method.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["getChatMember", "answerCallbackQuery"]
+        );
+        assert_eq!(calls[1].param("show_alert"), Some("true"));
+        assert!(calls[1]
+            .param("text")
+            .unwrap()
+            .starts_with("Solo gli amministratori"));
+        let calls = harness.press(group, GROUP_OWNER, "optout:short").await;
+        assert_eq!(
+            calls
+                .iter()
+                .map(|x| x.method.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["getChatMember", "answerCallbackQuery", "sendMessage"]
+        );
+        assert!(calls[2]
+            .text()
+            .starts_with("Non riceverai più le notifiche per"));
+        // in private chats the user is the owner of the chat
+        let chat = ChatId(2016);
+        harness.send_text(chat, "/ciaoirina").await;
+        let calls = harness.press(chat, UserId(2016), "optout:short").await;
+        assert_eq!(
+            calls
+                .iter()
+                .map(|x| x.method.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["answerCallbackQuery", "sendMessage"]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_answer_callbacks() {
+        let harness = Harness::new().await;
+        let (chat, user) = (ChatId(2017), UserId(2017));
+        let methods =
+            |calls: Vec<ApiCall>| -> Vec<String> { calls.into_iter().map(|x| x.method).collect() };
+        let calls = harness.press(chat, user, "videos:spaziogrigio").await;
+        assert_eq!(calls[0].method.as_str(), "answerCallbackQuery");
+        assert!(calls[1]
+            .text()
+            .starts_with("Ciao sono Irina. Ecco cosa puoi guardare questa sera"));
+        // broadcast buttons are ignored unless pressed by an admin
+        let calls = harness.press(chat, user, "broadcast:confirm").await;
+        assert_eq!(methods(calls), vec!["answerCallbackQuery"]);
+        let calls = harness
+            .press(ChatId(ADMIN.0 as i64), ADMIN, "broadcast:cancel")
+            .await;
+        assert_eq!(calls[1].text(), "Broadcast annullato");
+        let calls = harness.press(chat, user, "pippo:pluto").await;
+        assert_eq!(methods(calls), vec!["answerCallbackQuery"]);
+    }
+
+    #[tokio::test]
+    async fn should_handle_bot_membership_changes() {
+        let harness = Harness::new().await;
+        let group = ChatId(-2018);
+        harness.change_membership(group, "left", "member").await;
+        let calls = harness.api.calls_to(group).await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].text().contains("benvenuto in Spazio Grigio"));
+        let repository = Repository::connect().await.unwrap();
+        repository
+            .insert_chat(group, Creator::all().unwrap())
+            .await
+            .unwrap();
+        harness.change_membership(group, "member", "left").await;
+        assert!(!repository
+            .get_subscribed_chats()
+            .await
+            .unwrap()
+            .contains(&group));
+        assert_eq!(harness.api.calls_to(group).await.len(), 1);
+        // private chats are welcomed by /start instead
+        let chat = ChatId(2018);
+        harness.change_membership(chat, "left", "member").await;
+        assert!(harness.api.calls_to(chat).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_opt_out_and_in_shorts() {
+        let harness = Harness::new().await;
+        let chat = ChatId(2006);
+        harness.send_text(chat, "/ciaoirina").await;
+        let calls = harness.ask(chat, "/nienteshorts").await;
+        assert!(calls[0]
+            .text()
+            .starts_with("Non riceverai più le notifiche per"));
+        let calls = harness.ask(chat, "/voglioshorts").await;
+        assert!(calls[0]
+            .text()
+            .starts_with("Da ora riceverai di nuovo le notifiche per"));
+    }
+
+    #[tokio::test]
+    async fn should_list_latest_videos() {
+        let harness = Harness::new().await;
+        let calls = harness.ask(ChatId(2007), "/seratasenzatv").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0]
+            .text()
+            .starts_with("Ciao sono Irina. Ecco cosa puoi guardare questa sera"));
+        assert!(calls[0].text().contains("Il mio armadio minimalista"));
+        let calls = harness
+            .ask(ChatId(2007), "/seratasenzatv minimalista")
+            .await;
+        assert_eq!(calls.len(), 1);
+        assert!(!calls[0].text().contains("Il mio armadio minimalista"));
+    }
+
+    #[tokio::test]
+    async fn should_send_latest_video() {
+        let harness = Harness::new().await;
+        let calls = harness.ask(ChatId(2008), "/videominimalista").await;
+        assert_eq!(
+            calls
+                .iter()
+                .map(|x| x.method.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["sendPhoto", "sendMessage"]
+        );
+        assert!(calls[1]
+            .text()
+            .starts_with("Ciao sono Irina. Guarda il mio ultimo video"));
+        assert!(calls[1].param("reply_markup").is_some());
+    }
+
+    #[tokio::test]
+    async fn should_tell_creator_has_no_instagram() {
+        let harness = Harness::new().await;
+        let calls = harness
+            .ask(ChatId(2009), "/postminimalista minimalista")
+            .await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0]
+            .text()
+            .contains("Non ho un profilo instagram da mostrarti"));
+    }
+
+    #[tokio::test]
+    async fn should_tell_how_to_get_help() {
+        let harness = Harness::new().await;
+        let calls = harness.ask(ChatId(2010), "ciao").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].text().starts_with("Non ho capito"));
+    }
+
+    #[tokio::test]
+    async fn should_answer_admin_commands_to_admins_only() {
+        let harness = Harness::new().await;
+        let calls = harness.ask(ChatId(2011), "/admin").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].text().starts_with("Non ho capito"));
+        let admin = ChatId(ADMIN.0 as i64);
+        let calls = harness.ask(admin, "/admin").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].text().contains("/runjob"));
+    }
+
+    #[tokio::test]
+    async fn should_send_good_morning_to_subscribers() {
+        let harness = Harness::new().await;
+        let chats = [ChatId(2012), ChatId(2013)];
+        for chat in chats {
+            harness.send_text(chat, "/ciaoirina").await;
+        }
+        let _jobs = lock_jobs().await;
+        let api = &environment().await.api;
+        let before = [api.calls_to(chats[0]).await, api.calls_to(chats[1]).await];
+        assert!(Automatizer::run_job(AutomatizerJob::GoodMorning)
+            .await
+            .is_ok());
+        for (chat, before) in chats.into_iter().zip(before) {
+            let calls = api.calls_to(chat).await.split_off(before.len());
+            assert_eq!(calls.len(), 1);
+            assert!(calls[0].text().starts_with("Buongiorno sono Irina"));
+        }
+    }
+
+    #[tokio::test]
+    async fn should_announce_videos_to_followers_not_opted_out() {
+        let harness = Harness::new().await;
+        let (all, no_shorts, unfollowed) = (ChatId(2019), ChatId(2020), ChatId(2021));
+        for chat in [all, no_shorts, unfollowed] {
+            harness.send_text(chat, "/ciaoirina").await;
         }
+        harness.send_text(no_shorts, "/nienteshorts").await;
+        harness
+            .send_text(unfollowed, "/nonseguire spaziogrigio")
+            .await;
+        let _jobs = lock_jobs().await;
+        let api = &environment().await.api;
+        let announced = |chat: ChatId| async move {
+            api.calls_to(chat)
+                .await
+                .into_iter()
+                .filter(|x| x.method == "sendMessage")
+                .map(|x| x.text().to_string())
+                .collect::<Vec<String>>()
+        };
+        // the oldest unseen video is announced first, then the short
+        for _ in 0..2 {
+            assert!(Automatizer::run_job(AutomatizerJob::Video).await.is_ok());
+        }
+        let messages = announced(all).await;
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("La mia morning routine minimalista"));
+        assert!(messages[1].contains("Declutter in 30 secondi"));
+        let messages = announced(no_shorts).await;
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("La mia morning routine minimalista"));
+        assert!(announced(unfollowed).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_show_stats() {
+        let harness = Harness::new().await;
+        let admin = ChatId(ADMIN.0 as i64);
+        let calls = harness.ask(admin, "/stats").await;
+        assert_eq!(calls.len(), 1);
+        let text = calls[0].text();
+        assert!(text.starts_with("<b>📊 Statistiche</b>"));
+        assert!(text.contains("Chat iscritte: "));
+        assert!(text.contains("• spaziogrigio: "));
+        assert!(text.contains("• minimalista: "));
+        assert!(text.contains("• short: "));
+        assert!(text.contains("• discord: "));
+    }
+
+    #[tokio::test]
+    async fn should_run_job_and_show_its_health() {
+        let harness = Harness::new().await;
+        let admin = ChatId(ADMIN.0 as i64);
+        let calls = harness.ask(admin, "/runjob pippo").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].text().contains("pippo"));
+        let _jobs = lock_jobs().await;
+        let calls = harness.ask(admin, "/runjob good_morning").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].text().starts_with("Job good_morning completato: "));
+        let calls = harness.ask(admin, "/jobs").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].text().starts_with("<b>⚙️ Job</b>"));
+        assert!(calls[0].text().contains("✅ <code>good_morning</code>: "));
+    }
+
+    #[tokio::test]
+    async fn should_reset_watermark() {
+        let harness = Harness::new().await;
+        let admin = ChatId(ADMIN.0 as i64);
+        let calls = harness.ask(admin, "/resetwatermark digest").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].text().starts_with("Watermark azzerato."));
+        assert!(calls[0].text().contains("senza inviare il riepilogo"));
+        let calls = harness.ask(admin, "/resetwatermark video").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].text().contains("Uso: "));
+    }
+
+    #[tokio::test]
+    async fn should_broadcast_to_subscribed_chats() {
+        let harness = Harness::new().await;
+        let chat = ChatId(2022);
+        harness.send_text(chat, "/ciaoirina").await;
+        let admin = ChatId(ADMIN.0 as i64);
+        let calls = harness.ask(admin, "/broadcast").await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0]
+            .text()
+            .starts_with("Inviami il messaggio da inviare"));
+        let before = harness.api.calls_to(admin).await.len();
+        harness
+            .send_formatted_text(
+                admin,
+                "Ciao a tutti <3",
+                serde_json::json!([{ "type": "bold", "offset": 0, "length": 4 }]),
+            )
+            .await;
+        let calls = harness.api.calls_to(admin).await.split_off(before);
+        assert_eq!(calls.len(), 2);
+        // the formatting is kept
+        assert_eq!(calls[0].text(), "<b>Ciao</b> a tutti &lt;3");
+        assert!(calls[1]
+            .text()
+            .starts_with("Questa è l'anteprima. Confermi l'invio a"));
+        assert!(calls[1].param("reply_markup").is_some());
+        // nothing is sent before the confirmation
+        assert_eq!(harness.api.calls_to(chat).await.len(), 1);
+        let calls = harness.press(admin, ADMIN, "broadcast:confirm").await;
+        assert!(calls
+            .iter()
+            .any(|x| x.chat_id() == Some(chat) && x.text() == "<b>Ciao</b> a tutti &lt;3"));
+        assert!(calls
+            .last()
+            .unwrap()
+            .text()
+            .starts_with("Broadcast inviato: "));
+        // the draft is gone once sent
+        let calls = harness.press(admin, ADMIN, "broadcast:confirm").await;
+        assert_eq!(calls[1].text(), "Non c'è nessun broadcast da inviare");
     }
 }
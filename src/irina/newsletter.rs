@@ -10,18 +10,22 @@ pub struct Newsletter {
 impl Newsletter {
     /// Connect to the database
     pub async fn connect() -> anyhow::Result<Self> {
-        let config =
-            Config::try_from_env().map_err(|_| anyhow::anyhow!("failed to load configuration"))?;
-        Ok(Self {
-            client: EmailClient::connect(
-                &config.imap_server,
-                config.imap_port,
-                &config.email_address,
-                &config.email_password,
-            )
-            .await
-            .map_err(|e| anyhow::anyhow!("could not connect to email server: {}", e))?,
-        })
+        let config = Config::load()?;
+        EmailClient::connect(
+            &config.imap_server,
+            config.imap_port,
+            config.imap_security()?,
+            &config.email_address,
+            &config.email_password,
+        )
+        .await
+        .map(Self::new)
+        .map_err(|e| anyhow::anyhow!("could not connect to email server: {}", e))
+    }
+
+    /// Read the newsletter with an already connected client
+    pub fn new(client: EmailClient) -> Self {
+        Self { client }
     }
 
     /// Get latest message
@@ -32,7 +36,53 @@ impl Newsletter {
             .await
             .map_err(|e| anyhow::anyhow!("failed to get inbox messages: {}", e))?
             .into_iter()
-            .filter(|x| x.sender_address.as_str() == from)
-            .last())
+            .rev()
+            .find(|x| x.sender_address.as_str() == from))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mail::test_server::{
+        ImapServer, BAD_FROM, HTML_ONLY, LATIN1, MULTIPART, PASSWORD, USERNAME,
+    };
+
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn should_get_latest_message_from_sender() {
+        let server = ImapServer::start(&[MULTIPART, LATIN1, HTML_ONLY, BAD_FROM]).await;
+        let mut newsletter = Newsletter::new(
+            EmailClient::login(server.connect().await, USERNAME, PASSWORD)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(
+            newsletter
+                .get_latest_message("info@spaziogrigio.com")
+                .await
+                .unwrap()
+                .unwrap()
+                .subject
+                .as_str(),
+            "Decluttering di settembre"
+        );
+        assert_eq!(
+            newsletter
+                .get_latest_message("newsletter@minimalista.it")
+                .await
+                .unwrap()
+                .unwrap()
+                .subject
+                .as_str(),
+            "Perché possedere meno"
+        );
+        assert!(newsletter
+            .get_latest_message("spam@example.com")
+            .await
+            .unwrap()
+            .is_none());
     }
 }
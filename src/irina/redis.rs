@@ -1,96 +1,345 @@
 //! # Redis repository client
 //!
-//! This module exposes the big luca redis repository client
+//! This module exposes the spazio grigio redis repository client
 
 use chrono::{DateTime, Utc};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::redis::RedisClient;
 
+use super::creator::DEFAULT_CREATOR_ID;
 use super::Config;
 
-const LAST_NEWSLETTER_UPDATE: &str = "spaziogrigio-bot:last_newsletter_update";
-const LAST_VIDEO_PUBDATE: &str = "spaziogrigio-bot:last_video_pubdate";
-const LAST_INSTAGRAM_UPDATE: &str = "spaziogrigio-bot:last_instagram_update_v2";
+const LAST_NEWSLETTER_UPDATE: &str = "last_newsletter_update";
+const LAST_VIDEO_PUBDATE: &str = "last_video_pubdate";
+const LAST_INSTAGRAM_UPDATE: &str = "last_instagram_update_v2";
+const LAST_FEED_PUBDATE: &str = "last_feed_pubdate";
+const LAST_EMAIL_DIGEST: &str = "last_email_digest";
+
+/// The watermarks the automatizer jobs store to announce each content once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Watermark {
+    /// Last video of creator
+    Video(String),
+    /// Last instagram post of creator
+    Instagram(String),
+    /// Last newsletter of creator
+    Newsletter(String),
+    /// Last entry of feed source
+    Feed(String),
+    /// Last email digest
+    EmailDigest,
+}
+
+impl Watermark {
+    /// Parse `<video|instagram|newsletter|feed|digest> [creator|source]` arguments
+    pub fn parse(args: &str) -> anyhow::Result<Self> {
+        let mut args = args.split_whitespace();
+        match (args.next(), args.next(), args.next()) {
+            (Some("video"), Some(creator), None) => Ok(Self::Video(creator.to_string())),
+            (Some("instagram"), Some(creator), None) => Ok(Self::Instagram(creator.to_string())),
+            (Some("newsletter"), Some(creator), None) => Ok(Self::Newsletter(creator.to_string())),
+            (Some("feed"), Some(source), None) => Ok(Self::Feed(source.to_string())),
+            (Some("digest"), None, None) => Ok(Self::EmailDigest),
+            _ => anyhow::bail!(
+                "Uso: <video|instagram|newsletter> <creator>, feed <sorgente> oppure digest"
+            ),
+        }
+    }
+
+    /// Get the keys storing the watermark
+    fn keys(&self) -> Vec<String> {
+        let (name, creator) = match self {
+            Self::Video(creator) => (LAST_VIDEO_PUBDATE, creator),
+            Self::Instagram(creator) => (LAST_INSTAGRAM_UPDATE, creator),
+            Self::Newsletter(creator) => (LAST_NEWSLETTER_UPDATE, creator),
+            Self::Feed(source) => return vec![key(LAST_FEED_PUBDATE, source)],
+            Self::EmailDigest => return vec![global_key(LAST_EMAIL_DIGEST)],
+        };
+        if creator == DEFAULT_CREATOR_ID {
+            vec![key(name, creator), legacy_key(name)]
+        } else {
+            vec![key(name, creator)]
+        }
+    }
+}
 
 pub struct RedisRepository {
     redis: RedisClient,
+    /// When set, the watermarks are read but never written
+    dry_run: bool,
 }
 
 impl RedisRepository {
     /// Connect to the database
     pub fn connect() -> anyhow::Result<Self> {
-        let config = Config::try_from_env()
+        let config = Config::load()
             .map_err(|_| anyhow::anyhow!("REDIS_URL is not SET; repository is not available"))?;
         Ok(Self {
             redis: RedisClient::connect(&config.redis_url)
                 .map_err(|e| anyhow::anyhow!("failed to connect to redis: {}", e))?,
+            dry_run: false,
         })
     }
 
-    /// get last video publication date
-    pub async fn get_last_video_pubdate(&mut self) -> anyhow::Result<Option<DateTime<Utc>>> {
+    /// Set dry run; in dry run the watermarks are not advanced
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Check whether redis is reachable
+    pub async fn ping(&mut self) -> anyhow::Result<()> {
         self.redis
-            .get::<String>(LAST_VIDEO_PUBDATE)
+            .ping()
+            .await
+            .map_err(|e| anyhow::anyhow!("redis is not reachable: {}", e))
+    }
+
+    /// get last video publication date for creator
+    pub async fn get_last_video_pubdate(
+        &mut self,
+        creator: &str,
+    ) -> anyhow::Result<Option<DateTime<Utc>>> {
+        self.get::<String>(LAST_VIDEO_PUBDATE, creator)
             .await
             .map_err(|e| anyhow::anyhow!("failed to get last video pubdate: {}", e))
-            .map(|x| {
-                x.and_then(|x| {
-                    DateTime::parse_from_rfc3339(&x)
-                        .ok()
-                        .map(|x| DateTime::from_utc(x.naive_utc(), Utc))
-                })
-            })
+            .map(|x| x.and_then(|x| Self::parse_datetime(&x)))
     }
 
-    /// Set last video pubdate
-    pub async fn set_last_video_pubdate(&mut self, date: DateTime<Utc>) -> anyhow::Result<()> {
-        self.redis
-            .set(LAST_VIDEO_PUBDATE, date.to_rfc3339().as_str())
+    /// Set last video pubdate for creator
+    pub async fn set_last_video_pubdate(
+        &mut self,
+        creator: &str,
+        date: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        self.set(
+            &key(LAST_VIDEO_PUBDATE, creator),
+            date.to_rfc3339().as_str(),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to set last video pubdate: {}", e))
+    }
+
+    /// get last publication date of the entries announced for feed source
+    pub async fn get_last_feed_pubdate(
+        &mut self,
+        source: &str,
+    ) -> anyhow::Result<Option<DateTime<Utc>>> {
+        self.get::<String>(LAST_FEED_PUBDATE, source)
             .await
-            .map_err(|e| anyhow::anyhow!("failed to set last video pubdate: {}", e))
+            .map_err(|e| anyhow::anyhow!("failed to get last feed pubdate: {}", e))
+            .map(|x| x.and_then(|x| Self::parse_datetime(&x)))
     }
 
-    /// get last video publication date
-    pub async fn get_last_instagram_update(&mut self) -> anyhow::Result<Option<SystemTime>> {
-        self.redis
-            .get::<u64>(LAST_INSTAGRAM_UPDATE)
+    /// Set last feed pubdate for feed source
+    pub async fn set_last_feed_pubdate(
+        &mut self,
+        source: &str,
+        date: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        self.set(&key(LAST_FEED_PUBDATE, source), date.to_rfc3339().as_str())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to set last feed pubdate: {}", e))
+    }
+
+    /// get last instagram post date for creator
+    pub async fn get_last_instagram_update(
+        &mut self,
+        creator: &str,
+    ) -> anyhow::Result<Option<SystemTime>> {
+        self.get::<u64>(LAST_INSTAGRAM_UPDATE, creator)
             .await
             .map_err(|e| anyhow::anyhow!("failed to get last instagram update: {}", e))
             .map(|x| x.map(|x| UNIX_EPOCH.checked_add(Duration::from_secs(x)).unwrap()))
     }
 
-    /// Set last video pubdate
-    pub async fn set_last_instagram_update(&mut self, time: SystemTime) -> anyhow::Result<()> {
-        self.redis
-            .set(
-                LAST_INSTAGRAM_UPDATE,
-                time.duration_since(UNIX_EPOCH).unwrap().as_secs(),
-            )
-            .await
-            .map_err(|e| anyhow::anyhow!("failed to set last instagram update: {}", e))
+    /// Set last instagram post date for creator
+    pub async fn set_last_instagram_update(
+        &mut self,
+        creator: &str,
+        time: SystemTime,
+    ) -> anyhow::Result<()> {
+        self.set(
+            &key(LAST_INSTAGRAM_UPDATE, creator),
+            time.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to set last instagram update: {}", e))
     }
 
-    /// get last video publication date
-    pub async fn get_last_newsletter_update(&mut self) -> anyhow::Result<Option<DateTime<Utc>>> {
-        self.redis
-            .get::<String>(LAST_NEWSLETTER_UPDATE)
+    /// get last newsletter date for creator
+    pub async fn get_last_newsletter_update(
+        &mut self,
+        creator: &str,
+    ) -> anyhow::Result<Option<DateTime<Utc>>> {
+        self.get::<String>(LAST_NEWSLETTER_UPDATE, creator)
             .await
             .map_err(|e| anyhow::anyhow!("failed to get last newsletter update: {}", e))
-            .map(|x| {
-                x.and_then(|x| {
-                    DateTime::parse_from_rfc3339(&x)
-                        .ok()
-                        .map(|x| DateTime::from_utc(x.naive_utc(), Utc))
-                })
-            })
+            .map(|x| x.and_then(|x| Self::parse_datetime(&x)))
+    }
+
+    /// Set last newsletter date for creator
+    pub async fn set_last_newsletter_update(
+        &mut self,
+        creator: &str,
+        date: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        self.set(
+            &key(LAST_NEWSLETTER_UPDATE, creator),
+            date.to_rfc3339().as_str(),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to set last newsletter update: {}", e))
     }
 
-    /// Set last video pubdate
-    pub async fn set_last_newsletter_update(&mut self, date: DateTime<Utc>) -> anyhow::Result<()> {
+    /// get the date of the last email digest
+    pub async fn get_last_email_digest(&mut self) -> anyhow::Result<Option<DateTime<Utc>>> {
         self.redis
-            .set(LAST_NEWSLETTER_UPDATE, date.to_rfc3339().as_str())
+            .get::<String>(&global_key(LAST_EMAIL_DIGEST))
             .await
-            .map_err(|e| anyhow::anyhow!("failed to set last newsletter update: {}", e))
+            .map_err(|e| anyhow::anyhow!("failed to get last email digest: {}", e))
+            .map(|x| x.and_then(|x| Self::parse_datetime(&x)))
+    }
+
+    /// Set the date of the last email digest
+    pub async fn set_last_email_digest(&mut self, date: DateTime<Utc>) -> anyhow::Result<()> {
+        self.set(&global_key(LAST_EMAIL_DIGEST), date.to_rfc3339().as_str())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to set last email digest: {}", e))
+    }
+
+    /// Reset watermark, so the job considers every content as new
+    pub async fn reset_watermark(&mut self, watermark: &Watermark) -> anyhow::Result<()> {
+        for key in watermark.keys() {
+            self.redis
+                .del(&key)
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to reset watermark {:?}: {}", watermark, e))?;
+        }
+        Ok(())
+    }
+
+    /// Set `key` to `value`, unless in dry run
+    async fn set<V>(&mut self, key: &str, value: V) -> crate::redis::RedisResult<()>
+    where
+        V: redis::ToRedisArgs + Send + Sync + std::fmt::Debug,
+    {
+        if self.dry_run {
+            info!("dry run: not setting {} to {:?}", key, value);
+            return Ok(());
+        }
+        self.redis.set(key, value).await
+    }
+
+    /// Get value for `name` of `creator`.
+    /// For the default creator, if the key is not set, the key used before creators were introduced is read
+    async fn get<V>(&mut self, name: &str, creator: &str) -> crate::redis::RedisResult<Option<V>>
+    where
+        V: redis::FromRedisValue,
+    {
+        let value = self.redis.get(&key(name, creator)).await?;
+        if value.is_none() && creator == DEFAULT_CREATOR_ID {
+            self.redis.get(&legacy_key(name)).await
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn parse_datetime(s: &str) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|x| DateTime::from_utc(x.naive_utc(), Utc))
+    }
+}
+
+/// Make key for `name` of `creator`
+fn key(name: &str, creator: &str) -> String {
+    format!("spaziogrigio-bot:{}:{}", creator, name)
+}
+
+/// Make key for `name`, which is not related to any creator
+fn global_key(name: &str) -> String {
+    format!("spaziogrigio-bot:{}", name)
+}
+
+/// Make key for `name` as it was before creators were introduced
+fn legacy_key(name: &str) -> String {
+    global_key(name)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_parse_watermark() {
+        assert_eq!(
+            Watermark::parse("video spaziogrigio").unwrap(),
+            Watermark::Video(String::from("spaziogrigio"))
+        );
+        assert_eq!(
+            Watermark::parse(" feed  blog ").unwrap(),
+            Watermark::Feed(String::from("blog"))
+        );
+        assert_eq!(Watermark::parse("digest").unwrap(), Watermark::EmailDigest);
+        assert!(Watermark::parse("").is_err());
+        assert!(Watermark::parse("video").is_err());
+        assert!(Watermark::parse("digest spaziogrigio").is_err());
+        assert!(Watermark::parse("tv spaziogrigio").is_err());
+    }
+
+    #[tokio::test]
+    async fn should_not_advance_watermarks_in_dry_run() {
+        // nothing listens on this port: any write would fail
+        let mut repository = RedisRepository {
+            redis: RedisClient::connect("redis://127.0.0.1:1/").unwrap(),
+            dry_run: false,
+        }
+        .dry_run(true);
+        assert!(repository
+            .set_last_video_pubdate(DEFAULT_CREATOR_ID, Utc::now())
+            .await
+            .is_ok());
+        assert!(repository.set_last_email_digest(Utc::now()).await.is_ok());
+        assert!(repository
+            .dry_run(false)
+            .set_last_email_digest(Utc::now())
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn should_make_watermark_keys() {
+        assert_eq!(
+            Watermark::Video(String::from(DEFAULT_CREATOR_ID)).keys(),
+            vec![
+                key(LAST_VIDEO_PUBDATE, DEFAULT_CREATOR_ID),
+                legacy_key(LAST_VIDEO_PUBDATE)
+            ]
+        );
+        assert_eq!(
+            Watermark::Instagram(String::from("minimalista")).keys(),
+            vec![key(LAST_INSTAGRAM_UPDATE, "minimalista")]
+        );
+        assert_eq!(
+            Watermark::EmailDigest.keys(),
+            vec![String::from("spaziogrigio-bot:last_email_digest")]
+        );
+    }
+
+    #[test]
+    fn should_make_keys() {
+        assert_eq!(
+            key(LAST_VIDEO_PUBDATE, "spaziogrigio").as_str(),
+            "spaziogrigio-bot:spaziogrigio:last_video_pubdate"
+        );
+        assert_eq!(
+            legacy_key(LAST_INSTAGRAM_UPDATE).as_str(),
+            "spaziogrigio-bot:last_instagram_update_v2"
+        );
     }
 }
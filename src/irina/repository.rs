@@ -2,21 +2,71 @@
 //!
 //! This module contains the interface to the bot repository
 
-use crate::repository::{chat::Chat, SqliteDb};
+use crate::repository::{
+    chat::Chat, content::Content, follow::Follow, job_run::JobRun, optout::OptOut,
+    subscription::Subscription, SqliteDb,
+};
 
+use chrono::{DateTime, Duration, Utc};
+use std::str::FromStr;
 use teloxide::types::ChatId;
 
 use super::config::Config;
+use super::creator::Creator;
+use super::delivery::TargetKind;
+use super::job::{AutomatizerJob, JobHealth, JobStatus};
+use super::topic::Topic;
+
+/// Minimum time between two confirmation codes requested by the same chat or sent to the same address
+const CONFIRMATION_COOLDOWN_MINUTES: i64 = 5;
+/// Maximum amount of unconfirmed delivery targets per chat
+const MAX_PENDING_TARGETS: usize = 3;
 
 pub struct Repository {
     db: SqliteDb,
 }
 
+/// A subscribed chat, along with the creators it follows, the topics it opted out from
+/// and the delivery targets it forwards the announcements to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatRecord {
+    pub chat_id: i64,
+    /// Subscription date, in RFC3339 format
+    pub created_at: String,
+    pub creators: Vec<String>,
+    pub opt_outs: Vec<String>,
+    #[serde(default)]
+    pub subscriptions: Vec<SubscriptionRecord>,
+}
+
+/// A confirmed subscription of a delivery target to a creator
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubscriptionRecord {
+    pub target: String,
+    pub address: String,
+    pub creator: String,
+}
+
+/// A creator followed by a delivery target, along with the topics opted out by the chat owning the target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetFollow {
+    pub creator: String,
+    pub opt_outs: Vec<Topic>,
+}
+
+impl TargetFollow {
+    /// Returns whether contents about `topic` from `creator` should be delivered to the target.
+    /// Contents without a creator are delivered to every target which hasn't opted out from their topic
+    pub fn wants(&self, creator: Option<&str>, topic: &str) -> bool {
+        creator.map(|x| x == self.creator).unwrap_or(true)
+            && self.opt_outs.iter().all(|x| x.as_str() != topic)
+    }
+}
+
 impl Repository {
     /// Connect to the database
     pub async fn connect() -> anyhow::Result<Self> {
-        let config =
-            Config::try_from_env().map_err(|_| anyhow::anyhow!("failed to load configuration"))?;
+        let config = Config::load()?;
         Ok(Self {
             db: SqliteDb::connect(&config.database_url)
                 .await
@@ -24,23 +74,415 @@ impl Repository {
         })
     }
 
-    /// Insert a chat to database
-    pub async fn insert_chat(&self, chat: ChatId) -> anyhow::Result<()> {
+    /// Insert a chat to database, following all the provided creators
+    pub async fn insert_chat(&self, chat: ChatId, creators: &[Creator]) -> anyhow::Result<()> {
         if self.is_subscribed(&chat).await? {
             anyhow::bail!("Ciao sono Irina. Sei già iscritto alla mia newsletter")
         }
         Chat::new(chat)
             .insert(self.db.pool())
             .await
-            .map_err(|e| anyhow::anyhow!("failed to insert chat into the database: {}", e))
+            .map_err(|e| anyhow::anyhow!("failed to insert chat into the database: {}", e))?;
+        for creator in creators.iter() {
+            Follow::new(chat, &creator.id)
+                .insert(self.db.pool())
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to insert follow into the database: {}", e))?;
+        }
+        Ok(())
     }
 
-    /// Delete chat from database
+    /// Delete chat from database, along with its follows, opt-outs and delivery target subscriptions
     pub async fn delete_chat(&self, chat: ChatId) -> anyhow::Result<()> {
+        let mut tx = self
+            .db
+            .pool()
+            .begin()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to begin transaction: {}", e))?;
+        Subscription::delete_by_chat(&mut tx, chat)
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!("failed to delete subscriptions from the database: {}", e)
+            })?;
+        OptOut::delete_by_chat(&mut tx, chat)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to delete opt-outs from the database: {}", e))?;
+        Follow::delete_by_chat(&mut tx, chat)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to delete follows from the database: {}", e))?;
         Chat::new(chat)
+            .delete(&mut tx)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to delete chat from the database: {}", e))?;
+        tx.commit()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to commit transaction: {}", e))
+    }
+
+    /// Make `chat` follow `creator`
+    pub async fn follow(&self, chat: ChatId, creator: &Creator) -> anyhow::Result<()> {
+        if !self.is_subscribed(&chat).await? {
+            anyhow::bail!(
+                "Per seguire {} devi prima iscriverti con /ciaoirina",
+                creator.persona
+            )
+        }
+        if self
+            .get_followed_creators(chat)
+            .await?
+            .contains(&creator.id)
+        {
+            anyhow::bail!("Ciao sono {}. Mi stai già seguendo", creator.persona)
+        }
+        Follow::new(chat, &creator.id)
+            .insert(self.db.pool())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to insert follow into the database: {}", e))
+    }
+
+    /// Make `chat` unfollow `creator`. The last followed creator can't be unfollowed
+    pub async fn unfollow(&self, chat: ChatId, creator: &Creator) -> anyhow::Result<()> {
+        let followed = self.get_followed_creators(chat).await?;
+        if !followed.contains(&creator.id) {
+            anyhow::bail!("Ciao sono {}. Non mi stai seguendo", creator.persona)
+        }
+        if followed.len() == 1 {
+            anyhow::bail!(
+                "Non puoi smettere di seguire tutti. Se vuoi disiscriverti usa /sialconsumismo"
+            )
+        }
+        Follow::new(chat, &creator.id)
+            .delete(self.db.pool())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to delete follow from the database: {}", e))
+    }
+
+    /// Get the ids of the creators followed by `chat`
+    pub async fn get_followed_creators(&self, chat: ChatId) -> anyhow::Result<Vec<String>> {
+        Follow::get_by_chat(self.db.pool(), chat)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect followed creators: {}", e))
+            .map(|x| x.into_iter().map(|x| x.creator().to_string()).collect())
+    }
+
+    /// Get the chats following `creator` which haven't opted out from `topic`
+    pub async fn get_followers(&self, creator: &str, topic: Topic) -> anyhow::Result<Vec<ChatId>> {
+        let opted_out: Vec<ChatId> = OptOut::get_by_topic(self.db.pool(), topic.as_str())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect opt-outs: {}", e))?
+            .into_iter()
+            .map(|x| x.chat_id())
+            .collect();
+        Follow::get_by_creator(self.db.pool(), creator)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect followers: {}", e))
+            .map(|x| {
+                x.into_iter()
+                    .map(|x| x.chat_id())
+                    .filter(|x| !opted_out.contains(x))
+                    .collect()
+            })
+    }
+
+    /// Count the chats following `creator`
+    pub async fn count_followers(&self, creator: &str) -> anyhow::Result<usize> {
+        Follow::get_by_creator(self.db.pool(), creator)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect followers: {}", e))
+            .map(|x| x.len())
+    }
+
+    /// Count the chats which opted out from `topic`
+    pub async fn count_opt_outs(&self, topic: Topic) -> anyhow::Result<usize> {
+        OptOut::get_by_topic(self.db.pool(), topic.as_str())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect opt-outs: {}", e))
+            .map(|x| x.len())
+    }
+
+    /// Get all the subscribed chats which haven't opted out from `topic`
+    pub async fn get_subscribers(&self, topic: Topic) -> anyhow::Result<Vec<ChatId>> {
+        let opted_out: Vec<ChatId> = OptOut::get_by_topic(self.db.pool(), topic.as_str())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect opt-outs: {}", e))?
+            .into_iter()
+            .map(|x| x.chat_id())
+            .collect();
+        self.get_subscribed_chats()
+            .await
+            .map(|x| x.into_iter().filter(|x| !opted_out.contains(x)).collect())
+    }
+
+    /// Archive announced content
+    pub async fn archive(&self, content: Content) -> anyhow::Result<()> {
+        content
+            .insert(self.db.pool())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to archive content: {}", e))
+    }
+
+    /// Get the latest `limit` archived contents about `topics` (any topic if empty)
+    pub async fn get_archived(&self, topics: &[Topic], limit: u32) -> anyhow::Result<Vec<Content>> {
+        let topics: Vec<String> = topics.iter().map(|x| x.to_string()).collect();
+        Content::get_latest(self.db.pool(), &topics, limit)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect archived contents: {}", e))
+    }
+
+    /// Get the contents archived after `date`, sorted by publication date
+    pub async fn get_archived_since(&self, date: DateTime<Utc>) -> anyhow::Result<Vec<Content>> {
+        Content::get_archived_since(self.db.pool(), date)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect archived contents: {}", e))
+    }
+
+    /// Record the run of job, deleting the runs started before `retain_since`
+    pub async fn record_job_run(
+        &self,
+        job: AutomatizerJob,
+        status: &JobStatus,
+        retain_since: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        JobRun::new(
+            job,
+            status.started_at,
+            status.finished_at,
+            status.error.clone(),
+            status.delivered,
+        )
+        .insert(self.db.pool())
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to record job run: {}", e))?;
+        JobRun::delete_before(self.db.pool(), retain_since)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to delete old job runs: {}", e))
+    }
+
+    /// Get the health of job from its run history
+    pub async fn get_job_health(&self, job: AutomatizerJob) -> anyhow::Result<JobHealth> {
+        let last_run = JobRun::get_latest(self.db.pool(), job.as_str(), 1)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect job runs: {}", e))?
+            .into_iter()
+            .next()
+            .map(|x| Self::job_status(&x))
+            .transpose()?;
+        let last_success = JobRun::get_last_success(self.db.pool(), job.as_str())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect job runs: {}", e))?
+            .map(|x| x.started_at().map(|x| x.with_timezone(&Utc)))
+            .transpose()?;
+        let consecutive_failures = JobRun::count_consecutive_failures(self.db.pool(), job.as_str())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to count job failures: {}", e))?;
+        Ok(JobHealth {
+            last_run,
+            last_success,
+            consecutive_failures,
+        })
+    }
+
+    /// Subscribe the delivery target at `address` to the creators followed by `chat`.
+    /// If `chat` hasn't already confirmed the target, the subscriptions are pending until confirmed with `code`.
+    /// Returns whether the subscriptions must be confirmed
+    pub async fn subscribe_target(
+        &self,
+        chat: ChatId,
+        target: TargetKind,
+        address: &str,
+        code: &str,
+    ) -> anyhow::Result<bool> {
+        if !self.is_subscribed(&chat).await? {
+            anyhow::bail!("Non sei iscritto. Iscriviti con /ciaoirina")
+        }
+        let subscriptions = Subscription::get_by_chat(self.db.pool(), chat)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect subscriptions: {}", e))?;
+        let (owned, others): (Vec<Subscription>, Vec<Subscription>) = subscriptions
+            .into_iter()
+            .partition(|x| x.target() == target.as_str() && x.address() == address);
+        let confirmed = owned.iter().any(|x| x.is_confirmed());
+        if !confirmed {
+            self.check_confirmation_requests(target, address, &owned, &others)
+                .await?;
+        }
+        if !confirmed && !owned.is_empty() {
+            // a new code replaces the one of the previous request
+            Subscription::delete_by_address(self.db.pool(), chat, target.as_str(), address)
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!("failed to delete subscriptions from the database: {}", e)
+                })?;
+        }
+        for creator in self.get_followed_creators(chat).await?.into_iter() {
+            if confirmed && owned.iter().any(|x| x.creator() == creator) {
+                continue;
+            }
+            let subscription = Subscription::new(chat, target, address, creator);
+            let subscription = if confirmed {
+                subscription
+            } else {
+                subscription.pending(code)
+            };
+            subscription.insert(self.db.pool()).await.map_err(|e| {
+                anyhow::anyhow!("failed to insert subscription into the database: {}", e)
+            })?;
+        }
+        Ok(!confirmed)
+    }
+
+    /// Confirm the pending subscriptions of `chat` for the delivery target at `address` with `code`
+    pub async fn confirm_target(
+        &self,
+        chat: ChatId,
+        target: TargetKind,
+        address: &str,
+        code: &str,
+    ) -> anyhow::Result<()> {
+        let confirmed = Subscription::confirm(self.db.pool(), chat, target.as_str(), address, code)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to confirm subscriptions: {}", e))?;
+        if confirmed == 0 {
+            anyhow::bail!("Il codice di conferma non è valido")
+        }
+        Ok(())
+    }
+
+    /// Unsubscribe the delivery target at `address` owned by `chat` from all the creators
+    pub async fn unsubscribe_target(
+        &self,
+        chat: ChatId,
+        target: TargetKind,
+        address: &str,
+    ) -> anyhow::Result<()> {
+        if Subscription::get_by_chat(self.db.pool(), chat)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect subscriptions: {}", e))?
+            .iter()
+            .all(|x| x.target() != target.as_str() || x.address() != address)
+        {
+            anyhow::bail!("Non inoltro niente a {}", target.redact(address))
+        }
+        Subscription::delete_by_address(self.db.pool(), chat, target.as_str(), address)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to delete subscriptions from the database: {}", e))
+    }
+
+    /// Get the delivery targets subscribed to `creator`, or to any creator if `None`,
+    /// by the chats which haven't opted out from `topic`, if set. Each target is returned once
+    pub async fn get_subscribed_targets(
+        &self,
+        creator: Option<&str>,
+        topic: Option<Topic>,
+    ) -> anyhow::Result<Vec<(TargetKind, String)>> {
+        let opted_out: Vec<ChatId> = match topic {
+            Some(topic) => OptOut::get_by_topic(self.db.pool(), topic.as_str())
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to collect opt-outs: {}", e))?
+                .into_iter()
+                .map(|x| x.chat_id())
+                .collect(),
+            None => Vec::new(),
+        };
+        let subscriptions = match creator {
+            Some(creator) => Subscription::get_by_creator(self.db.pool(), creator).await,
+            None => Subscription::get_all(self.db.pool()).await,
+        }
+        .map_err(|e| anyhow::anyhow!("failed to collect subscriptions: {}", e))?;
+        let mut targets: Vec<(TargetKind, String)> = Vec::new();
+        for subscription in subscriptions
+            .into_iter()
+            .filter(|x| !opted_out.contains(&x.chat_id()))
+        {
+            let target = (
+                TargetKind::from_str(subscription.target())?,
+                subscription.address().to_string(),
+            );
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        Ok(targets)
+    }
+
+    /// Get the creators followed by each address of `target` kind,
+    /// along with the topics the chat owning the subscription opted out from
+    pub async fn get_target_subscriptions(
+        &self,
+        target: TargetKind,
+    ) -> anyhow::Result<Vec<(String, Vec<TargetFollow>)>> {
+        let subscriptions = Subscription::get_by_target(self.db.pool(), target.as_str())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect subscriptions: {}", e))?;
+        let mut addresses: Vec<(String, Vec<TargetFollow>)> = Vec::new();
+        for subscription in subscriptions.into_iter() {
+            let follow = TargetFollow {
+                creator: subscription.creator().to_string(),
+                opt_outs: OptOut::get_by_chat(self.db.pool(), subscription.chat_id())
+                    .await
+                    .map_err(|e| anyhow::anyhow!("failed to collect opt-outs: {}", e))?
+                    .iter()
+                    .filter_map(|x| Topic::from_str(x.topic()).ok())
+                    .collect(),
+            };
+            match addresses
+                .iter_mut()
+                .find(|(address, _)| address == subscription.address())
+            {
+                Some((_, follows)) => follows.push(follow),
+                None => addresses.push((subscription.address().to_string(), vec![follow])),
+            }
+        }
+        Ok(addresses)
+    }
+
+    /// Opt `chat` out from `topic`
+    pub async fn opt_out(&self, chat: ChatId, topic: Topic) -> anyhow::Result<()> {
+        if !self.is_subscribed(&chat).await? {
+            anyhow::bail!("Non sei iscritto. Iscriviti con /ciaoirina")
+        }
+        if self.is_opted_out(chat, topic).await? {
+            anyhow::bail!("Non ricevi già le notifiche per: {}", topic)
+        }
+        OptOut::new(chat, topic)
+            .insert(self.db.pool())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to insert opt-out into the database: {}", e))
+    }
+
+    /// Opt `chat` in to `topic`
+    pub async fn opt_in(&self, chat: ChatId, topic: Topic) -> anyhow::Result<()> {
+        if !self.is_subscribed(&chat).await? {
+            anyhow::bail!("Non sei iscritto. Iscriviti con /ciaoirina")
+        }
+        if !self.is_opted_out(chat, topic).await? {
+            anyhow::bail!("Ricevi già le notifiche per: {}", topic)
+        }
+        OptOut::new(chat, topic)
             .delete(self.db.pool())
             .await
-            .map_err(|e| anyhow::anyhow!("failed to delete chat from the database: {}", e))
+            .map_err(|e| anyhow::anyhow!("failed to delete opt-out from the database: {}", e))
+    }
+
+    /// Make all the chats which don't follow anybody, follow `creator`.
+    /// This is used to migrate chats subscribed before creators were introduced
+    pub async fn follow_if_orphan(&self, creator: &Creator) -> anyhow::Result<()> {
+        for chat in self.get_subscribed_chats().await? {
+            if self.get_followed_creators(chat).await?.is_empty() {
+                info!(
+                    "chat {} doesn't follow anybody; following {}",
+                    chat, creator.id
+                );
+                Follow::new(chat, &creator.id)
+                    .insert(self.db.pool())
+                    .await
+                    .map_err(|e| {
+                        anyhow::anyhow!("failed to insert follow into the database: {}", e)
+                    })?;
+            }
+        }
+        Ok(())
     }
 
     /// Get subscribed chats
@@ -64,9 +506,543 @@ impl Repository {
             })
     }
 
+    /// Get the subscribed chats, along with their follows, opt-outs and confirmed subscriptions
+    pub async fn get_chat_records(&self) -> anyhow::Result<Vec<ChatRecord>> {
+        let chats = Chat::get_all(self.db.pool())
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect subscribed chats: {}", e))?;
+        let mut records = Vec::with_capacity(chats.len());
+        for chat in chats.into_iter() {
+            let creators = Follow::get_by_chat(self.db.pool(), chat.id())
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to collect follows: {}", e))?
+                .iter()
+                .map(|x| x.creator().to_string())
+                .collect();
+            let opt_outs = OptOut::get_by_chat(self.db.pool(), chat.id())
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to collect opt-outs: {}", e))?
+                .iter()
+                .map(|x| x.topic().to_string())
+                .collect();
+            let subscriptions = Subscription::get_by_chat(self.db.pool(), chat.id())
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to collect subscriptions: {}", e))?
+                .iter()
+                .filter(|x| x.is_confirmed())
+                .map(|x| SubscriptionRecord {
+                    target: x.target().to_string(),
+                    address: x.address().to_string(),
+                    creator: x.creator().to_string(),
+                })
+                .collect();
+            records.push(ChatRecord {
+                chat_id: chat.id().0,
+                created_at: chat.created_at()?.to_rfc3339(),
+                creators,
+                opt_outs,
+                subscriptions,
+            });
+        }
+        Ok(records)
+    }
+
+    /// Insert an exported chat, with its follows, opt-outs and subscriptions, in a single transaction.
+    /// The followed creators must be among `creators`.
+    /// Returns false if the chat is already subscribed, in which case nothing is inserted
+    pub async fn import_chat_record(
+        &self,
+        record: &ChatRecord,
+        creators: &[Creator],
+    ) -> anyhow::Result<bool> {
+        let chat = ChatId(record.chat_id);
+        if self.is_subscribed(&chat).await? {
+            return Ok(false);
+        }
+        let created_at = DateTime::parse_from_rfc3339(&record.created_at).map_err(|e| {
+            anyhow::anyhow!(
+                "invalid created_at {} of chat {}: {}",
+                record.created_at,
+                chat,
+                e
+            )
+        })?;
+        if let Some(creator) = record
+            .creators
+            .iter()
+            .chain(record.subscriptions.iter().map(|x| &x.creator))
+            .find(|x| creators.iter().all(|creator| &creator.id != *x))
+        {
+            anyhow::bail!("unknown creator {} followed by chat {}", creator, chat);
+        }
+        let topics = record
+            .opt_outs
+            .iter()
+            .map(|x| Topic::from_str(x))
+            .collect::<anyhow::Result<Vec<Topic>>>()?;
+        let targets = record
+            .subscriptions
+            .iter()
+            .map(|x| TargetKind::from_str(&x.target))
+            .collect::<anyhow::Result<Vec<TargetKind>>>()?;
+        let mut tx = self
+            .db
+            .pool()
+            .begin()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to begin transaction: {}", e))?;
+        Chat::new(chat)
+            .with_created_at(created_at)
+            .insert(&mut tx)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to insert chat into the database: {}", e))?;
+        for creator in record.creators.iter() {
+            Follow::new(chat, creator)
+                .insert(&mut tx)
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to insert follow into the database: {}", e))?;
+        }
+        for topic in topics.into_iter() {
+            OptOut::new(chat, topic)
+                .insert(&mut tx)
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!("failed to insert opt-out into the database: {}", e)
+                })?;
+        }
+        for (target, subscription) in targets.into_iter().zip(record.subscriptions.iter()) {
+            Subscription::new(chat, target, &subscription.address, &subscription.creator)
+                .insert(&mut tx)
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!("failed to insert subscription into the database: {}", e)
+                })?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to commit transaction: {}", e))?;
+        Ok(true)
+    }
+
+    /// Check whether a new confirmation code can be sent to the target at `address`.
+    /// `owned` are the subscriptions of the chat for the target, `others` the ones for its other targets
+    async fn check_confirmation_requests(
+        &self,
+        target: TargetKind,
+        address: &str,
+        owned: &[Subscription],
+        others: &[Subscription],
+    ) -> anyhow::Result<()> {
+        let pending_by_address =
+            Subscription::get_pending_by_address(self.db.pool(), target.as_str(), address)
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to collect subscriptions: {}", e))?;
+        let cooldown_start = Utc::now() - Duration::minutes(CONFIRMATION_COOLDOWN_MINUTES);
+        for subscription in owned
+            .iter()
+            .chain(others.iter())
+            .chain(pending_by_address.iter())
+            .filter(|x| !x.is_confirmed())
+        {
+            if subscription.created_at()? > cooldown_start {
+                anyhow::bail!(
+                    "Ho già inviato un codice di conferma da poco. Riprova tra {} minuti",
+                    CONFIRMATION_COOLDOWN_MINUTES
+                )
+            }
+        }
+        let mut pending_targets: Vec<(&str, &str)> = others
+            .iter()
+            .filter(|x| !x.is_confirmed())
+            .map(|x| (x.target(), x.address()))
+            .collect();
+        pending_targets.sort_unstable();
+        pending_targets.dedup();
+        if pending_targets.len() >= MAX_PENDING_TARGETS {
+            anyhow::bail!(
+                "Hai già {} inoltri da confermare. Confermali o annullali con /noninoltrare prima di aggiungerne altri",
+                pending_targets.len()
+            )
+        }
+        Ok(())
+    }
+
+    /// Convert a recorded job run into a `JobStatus`
+    fn job_status(run: &JobRun) -> anyhow::Result<JobStatus> {
+        Ok(JobStatus {
+            started_at: run.started_at()?.with_timezone(&Utc),
+            finished_at: run.finished_at()?.with_timezone(&Utc),
+            error: run.error().map(|x| x.to_string()),
+            delivered: run.delivered(),
+        })
+    }
+
+    /// Returns whether `chat` has opted out from `topic`
+    async fn is_opted_out(&self, chat: ChatId, topic: Topic) -> anyhow::Result<bool> {
+        OptOut::get_by_chat(self.db.pool(), chat)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to collect opt-outs: {}", e))
+            .map(|x| x.iter().any(|x| x.topic() == topic.as_str()))
+    }
+
     /// Returns whether `chat_id` is already subscribed
     async fn is_subscribed(&self, chat_id: &ChatId) -> anyhow::Result<bool> {
         let subs = self.get_subscribed_chats().await?;
         Ok(subs.iter().any(|x| x == chat_id))
     }
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::repository::test::init_database;
+
+    use pretty_assertions::assert_eq;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn should_get_followers_not_opted_out() {
+        let (repository, temp) = repository().await;
+        let creator = Creator::default();
+        for chat in [ChatId(1), ChatId(2), ChatId(3)] {
+            repository
+                .insert_chat(chat, std::slice::from_ref(&creator))
+                .await
+                .unwrap();
+        }
+        repository.opt_out(ChatId(2), Topic::Short).await.unwrap();
+        assert_eq!(
+            repository
+                .get_followers(&creator.id, Topic::Short)
+                .await
+                .unwrap(),
+            vec![ChatId(1), ChatId(3)]
+        );
+        assert_eq!(
+            repository
+                .get_followers(&creator.id, Topic::Video)
+                .await
+                .unwrap(),
+            vec![ChatId(1), ChatId(2), ChatId(3)]
+        );
+        assert!(repository
+            .get_followers("minimalista", Topic::Video)
+            .await
+            .unwrap()
+            .is_empty());
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_scope_target_subscriptions_to_owning_chat() {
+        let (repository, temp) = repository().await;
+        let creator = Creator::default();
+        for chat in [ChatId(1), ChatId(2)] {
+            repository
+                .insert_chat(chat, std::slice::from_ref(&creator))
+                .await
+                .unwrap();
+        }
+        assert!(repository
+            .subscribe_target(ChatId(1), TargetKind::Discord, WEBHOOK, "111111")
+            .await
+            .unwrap());
+        // pending until confirmed by the owning chat
+        assert!(repository
+            .get_subscribed_targets(None, None)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(repository
+            .confirm_target(ChatId(2), TargetKind::Discord, WEBHOOK, "111111")
+            .await
+            .is_err());
+        assert!(repository
+            .confirm_target(ChatId(1), TargetKind::Discord, WEBHOOK, "222222")
+            .await
+            .is_err());
+        assert!(repository
+            .confirm_target(ChatId(1), TargetKind::Discord, WEBHOOK, "111111")
+            .await
+            .is_ok());
+        assert_eq!(
+            repository
+                .get_subscribed_targets(Some(&creator.id), None)
+                .await
+                .unwrap(),
+            vec![(TargetKind::Discord, String::from(WEBHOOK))]
+        );
+        // a confirmed target doesn't need to be confirmed again
+        assert!(!repository
+            .subscribe_target(ChatId(1), TargetKind::Discord, WEBHOOK, "333333")
+            .await
+            .unwrap());
+        // another chat can't remove the target
+        assert!(repository
+            .unsubscribe_target(ChatId(2), TargetKind::Discord, WEBHOOK)
+            .await
+            .is_err());
+        assert_eq!(
+            repository
+                .get_subscribed_targets(None, None)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(repository
+            .unsubscribe_target(ChatId(1), TargetKind::Discord, WEBHOOK)
+            .await
+            .is_ok());
+        assert!(repository
+            .get_subscribed_targets(None, None)
+            .await
+            .unwrap()
+            .is_empty());
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_limit_confirmation_requests() {
+        let (repository, temp) = repository().await;
+        let creator = Creator::default();
+        for chat in [ChatId(1), ChatId(2)] {
+            repository
+                .insert_chat(chat, std::slice::from_ref(&creator))
+                .await
+                .unwrap();
+        }
+        assert!(repository
+            .subscribe_target(ChatId(1), TargetKind::Discord, WEBHOOK, "111111")
+            .await
+            .unwrap());
+        // neither the same chat nor the same address get another code during the cooldown
+        assert!(repository
+            .subscribe_target(ChatId(1), TargetKind::Discord, WEBHOOK, "222222")
+            .await
+            .is_err());
+        assert!(repository
+            .subscribe_target(
+                ChatId(1),
+                TargetKind::Email,
+                "irina@spaziogrigio.com",
+                "222222"
+            )
+            .await
+            .is_err());
+        assert!(repository
+            .subscribe_target(ChatId(2), TargetKind::Discord, WEBHOOK, "222222")
+            .await
+            .is_err());
+        expire_confirmation_cooldown(&repository).await;
+        for address in ["irina@spaziogrigio.com", "grigio@spaziogrigio.com"] {
+            assert!(repository
+                .subscribe_target(ChatId(1), TargetKind::Email, address, "333333")
+                .await
+                .unwrap());
+            expire_confirmation_cooldown(&repository).await;
+        }
+        // too many unconfirmed targets
+        assert!(repository
+            .subscribe_target(
+                ChatId(1),
+                TargetKind::Email,
+                "minimal@spaziogrigio.com",
+                "444444"
+            )
+            .await
+            .is_err());
+        // requesting a new code for a pending target is still allowed
+        assert!(repository
+            .subscribe_target(ChatId(1), TargetKind::Discord, WEBHOOK, "555555")
+            .await
+            .unwrap());
+        expire_confirmation_cooldown(&repository).await;
+        repository
+            .confirm_target(ChatId(1), TargetKind::Discord, WEBHOOK, "555555")
+            .await
+            .unwrap();
+        assert!(repository
+            .subscribe_target(
+                ChatId(1),
+                TargetKind::Email,
+                "minimal@spaziogrigio.com",
+                "444444"
+            )
+            .await
+            .unwrap());
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_apply_opt_outs_to_subscribed_targets() {
+        let (repository, temp) = repository().await;
+        let creator = Creator::default();
+        repository
+            .insert_chat(ChatId(1), std::slice::from_ref(&creator))
+            .await
+            .unwrap();
+        for (target, address) in [
+            (TargetKind::Discord, WEBHOOK),
+            (TargetKind::Email, "irina@spaziogrigio.com"),
+        ] {
+            repository
+                .subscribe_target(ChatId(1), target, address, "111111")
+                .await
+                .unwrap();
+            repository
+                .confirm_target(ChatId(1), target, address, "111111")
+                .await
+                .unwrap();
+        }
+        repository.opt_out(ChatId(1), Topic::Short).await.unwrap();
+        assert!(repository
+            .get_subscribed_targets(Some(&creator.id), Some(Topic::Short))
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            repository
+                .get_subscribed_targets(Some(&creator.id), Some(Topic::Video))
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        let subscriptions = repository
+            .get_target_subscriptions(TargetKind::Email)
+            .await
+            .unwrap();
+        assert_eq!(subscriptions.len(), 1);
+        let (address, follows) = &subscriptions[0];
+        assert_eq!(address, "irina@spaziogrigio.com");
+        assert!(follows.iter().any(|x| x.wants(Some(&creator.id), "video")));
+        assert!(follows.iter().any(|x| x.wants(None, "video")));
+        assert!(!follows.iter().any(|x| x.wants(Some(&creator.id), "short")));
+        assert!(!follows
+            .iter()
+            .any(|x| x.wants(Some("minimalista"), "video")));
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_delete_chat_with_its_subscriptions() {
+        let (repository, temp) = repository().await;
+        let creator = Creator::default();
+        repository
+            .insert_chat(ChatId(1), std::slice::from_ref(&creator))
+            .await
+            .unwrap();
+        repository.opt_out(ChatId(1), Topic::Short).await.unwrap();
+        repository
+            .subscribe_target(ChatId(1), TargetKind::Discord, WEBHOOK, "111111")
+            .await
+            .unwrap();
+        repository
+            .confirm_target(ChatId(1), TargetKind::Discord, WEBHOOK, "111111")
+            .await
+            .unwrap();
+        assert!(repository.delete_chat(ChatId(1)).await.is_ok());
+        assert!(repository.get_subscribed_chats().await.unwrap().is_empty());
+        assert!(repository
+            .get_followed_creators(ChatId(1))
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(!repository
+            .is_opted_out(ChatId(1), Topic::Short)
+            .await
+            .unwrap());
+        assert!(repository
+            .get_subscribed_targets(None, None)
+            .await
+            .unwrap()
+            .is_empty());
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_export_and_import_chat_records() {
+        let (repository, temp) = repository().await;
+        let creator = Creator::default();
+        repository
+            .insert_chat(ChatId(1), std::slice::from_ref(&creator))
+            .await
+            .unwrap();
+        repository.opt_out(ChatId(1), Topic::Short).await.unwrap();
+        repository
+            .subscribe_target(ChatId(1), TargetKind::Discord, WEBHOOK, "111111")
+            .await
+            .unwrap();
+        repository
+            .confirm_target(ChatId(1), TargetKind::Discord, WEBHOOK, "111111")
+            .await
+            .unwrap();
+        let records = repository.get_chat_records().await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0].subscriptions,
+            vec![SubscriptionRecord {
+                target: TargetKind::Discord.to_string(),
+                address: WEBHOOK.to_string(),
+                creator: creator.id.clone(),
+            }]
+        );
+        let (imported, imported_temp) = self::repository().await;
+        assert!(imported
+            .import_chat_record(&records[0], std::slice::from_ref(&creator))
+            .await
+            .unwrap());
+        assert!(!imported
+            .import_chat_record(&records[0], std::slice::from_ref(&creator))
+            .await
+            .unwrap());
+        assert_eq!(imported.get_chat_records().await.unwrap(), records);
+        drop(temp);
+        drop(imported_temp)
+    }
+
+    #[tokio::test]
+    async fn should_not_import_chat_records_partially() {
+        let (repository, temp) = repository().await;
+        let creator = Creator::default();
+        let mut record = ChatRecord {
+            chat_id: 1,
+            created_at: String::from("2022-09-01T08:00:00+00:00"),
+            creators: vec![String::from("mario")],
+            opt_outs: Vec::new(),
+            subscriptions: Vec::new(),
+        };
+        // unknown creator
+        assert!(repository
+            .import_chat_record(&record, std::slice::from_ref(&creator))
+            .await
+            .is_err());
+        // duplicated follow fails after the chat has been inserted
+        record.creators = vec![creator.id.clone(), creator.id.clone()];
+        assert!(repository
+            .import_chat_record(&record, std::slice::from_ref(&creator))
+            .await
+            .is_err());
+        assert!(repository.get_subscribed_chats().await.unwrap().is_empty());
+        drop(temp)
+    }
+
+    const WEBHOOK: &str = "https://discord.com/api/webhooks/1234/token";
+
+    /// Move the pending subscriptions back in time, past the confirmation cooldown
+    async fn expire_confirmation_cooldown(repository: &Repository) {
+        let created_at = Utc::now() - Duration::minutes(CONFIRMATION_COOLDOWN_MINUTES + 1);
+        sqlx::query("UPDATE subscription SET created_at = $1 WHERE confirmation IS NOT NULL")
+            .bind(created_at.to_rfc3339())
+            .execute(repository.db.pool())
+            .await
+            .unwrap();
+    }
+
+    async fn repository() -> (Repository, NamedTempFile) {
+        let (db, temp) = init_database().await;
+        (Repository { db }, temp)
+    }
+}
@@ -0,0 +1,295 @@
+//! # Rich text
+//!
+//! Formatted text, rendered as Telegram HTML
+
+use teloxide::types::{MessageEntity, MessageEntityKind};
+
+/// A formatted text
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RichText {
+    spans: Vec<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Span {
+    Plain(String),
+    Bold(String),
+    Italic(String),
+    Link {
+        text: String,
+        url: String,
+    },
+    Code(String),
+    /// Text already rendered as Telegram HTML
+    Html {
+        text: String,
+        html: String,
+    },
+}
+
+impl RichText {
+    /// Append plain text
+    pub fn plain(mut self, text: impl ToString) -> Self {
+        self.spans.push(Span::Plain(text.to_string()));
+        self
+    }
+
+    /// Append bold text
+    pub fn bold(mut self, text: impl ToString) -> Self {
+        self.spans.push(Span::Bold(text.to_string()));
+        self
+    }
+
+    /// Append italic text
+    pub fn italic(mut self, text: impl ToString) -> Self {
+        self.spans.push(Span::Italic(text.to_string()));
+        self
+    }
+
+    /// Append a link to `url` with `text`
+    pub fn link(mut self, text: impl ToString, url: impl ToString) -> Self {
+        self.spans.push(Span::Link {
+            text: text.to_string(),
+            url: url.to_string(),
+        });
+        self
+    }
+
+    /// Append monospace text
+    pub fn code(mut self, text: impl ToString) -> Self {
+        self.spans.push(Span::Code(text.to_string()));
+        self
+    }
+
+    /// Get the rich text of a Telegram message `text`, formatted with its `entities`.
+    /// Entities which Telegram detects by itself (mentions, hashtags, urls, ...) are left as plain text
+    pub fn from_entities(text: &str, entities: &[MessageEntity]) -> Self {
+        let mut entities: Vec<(&MessageEntity, String, &'static str)> = entities
+            .iter()
+            .filter_map(|entity| {
+                Self::tags(&entity.kind).map(|(open, close)| (entity, open, close))
+            })
+            .collect();
+        // outer entities first
+        entities.sort_by_key(|(entity, _, _)| (entity.offset, std::cmp::Reverse(entity.length)));
+        let mut entities = entities.into_iter().peekable();
+        let mut open: Vec<(usize, &'static str)> = Vec::new();
+        let mut html = String::with_capacity(text.len());
+        let mut offset = 0;
+        let mut buffer = [0; 4];
+        for ch in text.chars() {
+            // close the entities ending before this char
+            while let Some((_, close)) = open.last().filter(|(end, _)| *end <= offset).copied() {
+                open.pop();
+                html.push_str(close);
+            }
+            while let Some((entity, start, close)) =
+                entities.next_if(|(entity, _, _)| entity.offset <= offset)
+            {
+                html.push_str(&start);
+                open.push((entity.offset + entity.length, close));
+            }
+            html.push_str(&escape(ch.encode_utf8(&mut buffer)));
+            offset += ch.len_utf16();
+        }
+        while let Some((_, close)) = open.pop() {
+            html.push_str(close);
+        }
+        Self {
+            spans: vec![Span::Html {
+                text: text.to_string(),
+                html,
+            }],
+        }
+    }
+
+    /// Get the opening and closing HTML tags for an entity kind; `None` if it has no tags
+    fn tags(kind: &MessageEntityKind) -> Option<(String, &'static str)> {
+        match kind {
+            MessageEntityKind::Bold => Some((String::from("<b>"), "</b>")),
+            MessageEntityKind::Italic => Some((String::from("<i>"), "</i>")),
+            MessageEntityKind::Underline => Some((String::from("<u>"), "</u>")),
+            MessageEntityKind::Strikethrough => Some((String::from("<s>"), "</s>")),
+            MessageEntityKind::Spoiler => {
+                Some((String::from("<span class=\"tg-spoiler\">"), "</span>"))
+            }
+            MessageEntityKind::Code => Some((String::from("<code>"), "</code>")),
+            MessageEntityKind::Pre { language: None } => Some((String::from("<pre>"), "</pre>")),
+            MessageEntityKind::Pre {
+                language: Some(language),
+            } => Some((
+                format!("<pre><code class=\"language-{}\">", escape(language)),
+                "</code></pre>",
+            )),
+            MessageEntityKind::TextLink { url } => {
+                Some((format!("<a href=\"{}\">", escape(url.as_str())), "</a>"))
+            }
+            MessageEntityKind::TextMention { user } => {
+                Some((format!("<a href=\"tg://user?id={}\">", user.id.0), "</a>"))
+            }
+            _ => None,
+        }
+    }
+
+    /// Render text as Telegram HTML
+    pub fn to_html(&self) -> String {
+        self.spans
+            .iter()
+            .map(|span| match span {
+                Span::Plain(text) => escape(text),
+                Span::Bold(text) => format!("<b>{}</b>", escape(text)),
+                Span::Italic(text) => format!("<i>{}</i>", escape(text)),
+                Span::Link { text, url } => {
+                    format!("<a href=\"{}\">{}</a>", escape(url), escape(text))
+                }
+                Span::Code(text) => format!("<code>{}</code>", escape(text)),
+                Span::Html { html, .. } => html.clone(),
+            })
+            .collect()
+    }
+
+    /// Render text without formatting. Links are rendered as `text (url)`
+    pub fn to_plain(&self) -> String {
+        self.spans
+            .iter()
+            .map(|span| match span {
+                Span::Plain(text)
+                | Span::Bold(text)
+                | Span::Italic(text)
+                | Span::Code(text)
+                | Span::Html { text, .. } => text.clone(),
+                Span::Link { text, url } if text == url => url.clone(),
+                Span::Link { text, url } => format!("{} ({})", text, url),
+            })
+            .collect()
+    }
+
+    /// Get the length of the text in UTF-16 code units, which is how Telegram counts its limits
+    pub fn len(&self) -> usize {
+        self.spans
+            .iter()
+            .map(|span| match span {
+                Span::Plain(text)
+                | Span::Bold(text)
+                | Span::Italic(text)
+                | Span::Code(text)
+                | Span::Link { text, .. }
+                | Span::Html { text, .. } => text.encode_utf16().count(),
+            })
+            .sum()
+    }
+}
+
+impl From<String> for RichText {
+    fn from(text: String) -> Self {
+        Self::default().plain(text)
+    }
+}
+
+impl From<&str> for RichText {
+    fn from(text: &str) -> Self {
+        Self::default().plain(text)
+    }
+}
+
+/// Escape text for Telegram HTML
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_render_rich_text_as_html() {
+        let text = RichText::default()
+            .plain("Ciao sono Irina. ")
+            .bold("Nuovo video")
+            .plain(": ")
+            .link(
+                "Il mio armadio <minimalista>",
+                "https://www.youtube.com/watch?v=rRQP8PNEouo&t=1",
+            )
+            .plain(" ")
+            .italic("5 minuti")
+            .plain(" ")
+            .code("/segui spaziogrigio");
+        assert_eq!(
+            text.to_html().as_str(),
+            "Ciao sono Irina. <b>Nuovo video</b>: <a href=\"https://www.youtube.com/watch?v=rRQP8PNEouo&amp;t=1\">Il mio armadio &lt;minimalista&gt;</a> <i>5 minuti</i> <code>/segui spaziogrigio</code>"
+        );
+    }
+
+    #[test]
+    fn should_render_rich_text_as_plain_text() {
+        let text = RichText::default()
+            .bold("Nuovo video")
+            .plain(": ")
+            .link(
+                "Il mio armadio",
+                "https://www.youtube.com/watch?v=rRQP8PNEouo",
+            )
+            .plain(" ")
+            .link(
+                "https://www.spaziogrigio.com",
+                "https://www.spaziogrigio.com",
+            );
+        assert_eq!(
+            text.to_plain().as_str(),
+            "Nuovo video: Il mio armadio (https://www.youtube.com/watch?v=rRQP8PNEouo) https://www.spaziogrigio.com"
+        );
+        assert_eq!(text.len(), 56);
+    }
+
+    #[test]
+    fn should_count_utf16_code_units() {
+        assert_eq!(RichText::from("città").len(), 5);
+        // emojis outside of the basic multilingual plane take two code units
+        assert_eq!(RichText::default().plain("ciao ").bold("👋").len(), 7);
+    }
+
+    #[test]
+    fn should_render_message_entities_as_html() {
+        let entities: Vec<MessageEntity> = serde_json::from_value(serde_json::json!([
+            { "type": "bold", "offset": 0, "length": 14 },
+            { "type": "italic", "offset": 5, "length": 9 },
+            { "type": "text_link", "offset": 19, "length": 4, "url": "https://www.spaziogrigio.com/" },
+            { "type": "hashtag", "offset": 25, "length": 12 },
+            { "type": "code", "offset": 38, "length": 2 },
+        ]))
+        .unwrap();
+        // the emoji takes two UTF-16 code units
+        let text =
+            RichText::from_entities("👋 Q&A stasera sul blog: #minimalismo <3 ok", &entities);
+        assert_eq!(
+            text.to_html().as_str(),
+            "<b>👋 Q&amp;<i>A stasera</i></b> sul <a href=\"https://www.spaziogrigio.com/\">blog</a>: #minimalismo <code>&lt;3</code> ok"
+        );
+        assert_eq!(
+            text.to_plain().as_str(),
+            "👋 Q&A stasera sul blog: #minimalismo <3 ok"
+        );
+        assert_eq!(text.len(), 43);
+        assert_eq!(
+            RichText::from_entities("Tom & Jerry", &[])
+                .to_html()
+                .as_str(),
+            "Tom &amp; Jerry"
+        );
+    }
+
+    #[test]
+    fn should_escape_plain_text() {
+        assert_eq!(
+            RichText::from("Tom & Jerry <3").to_html().as_str(),
+            "Tom &amp; Jerry &lt;3"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! # Template
+//!
+//! Message templates, which can be customized in the `[templates]` table of the configuration
+
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Templates configured for this instance, set at startup
+static TEMPLATES: OnceCell<HashMap<String, String>> = OnceCell::new();
+
+/// A message sent by the bot, with its default text
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Template {
+    /// Sent to the chats which subscribe
+    Welcome,
+    /// Sent every morning
+    GoodMorning,
+    /// Prefix of a new video announcement
+    Video,
+    /// Prefix of a new short announcement
+    Short,
+    /// Prefix of a premiere announcement
+    Premiere,
+    /// Prefix of a live stream announcement
+    Live,
+    /// Prefix of a new instagram post announcement
+    Instagram,
+    /// Prefix of a newsletter announcement
+    Newsletter,
+    /// Prefix of a new podcast episode announcement
+    Podcast,
+    /// Prefix of a new article announcement
+    Article,
+    /// Greeting of the creator, prefixed to the podcast and article announcements
+    Greeting,
+}
+
+impl Template {
+    /// All the templates
+    pub const ALL: [Template; 11] = [
+        Self::Welcome,
+        Self::GoodMorning,
+        Self::Video,
+        Self::Short,
+        Self::Premiere,
+        Self::Live,
+        Self::Instagram,
+        Self::Newsletter,
+        Self::Podcast,
+        Self::Article,
+        Self::Greeting,
+    ];
+
+    /// Get template name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Welcome => "welcome",
+            Self::GoodMorning => "good_morning",
+            Self::Video => "video",
+            Self::Short => "short",
+            Self::Premiere => "premiere",
+            Self::Live => "live",
+            Self::Instagram => "instagram",
+            Self::Newsletter => "newsletter",
+            Self::Podcast => "podcast",
+            Self::Article => "article",
+            Self::Greeting => "greeting",
+        }
+    }
+
+    /// Get the default text of the template
+    fn default_text(&self) -> &'static str {
+        match self {
+            Self::Welcome => "Ciao sono Irina e ti do il benvenuto in Spazio Grigio. Da ora riceverai tutti gli aggiornamenti per proseguire nel tuo percorso verso il Minimalismo.",
+            Self::GoodMorning => "Buongiorno sono Irina. Segui la mia morning routine per cominciare la tua giornata 👉 {url}",
+            Self::Video => "Ciao sono {persona}. Ho appena pubblicato questo nuovo mio video: ",
+            Self::Short => "Ciao sono {persona}. Ho appena pubblicato un nuovo short: ",
+            Self::Premiere => "Ciao sono {persona}. Sta per uscire un nuovo mio video in anteprima: ",
+            Self::Live => "Ciao sono {persona}. Sono in diretta su Youtube: ",
+            Self::Instagram => "Ciao sono {persona}. Ho appena pubblicato questo nuovo mio post su Instagram: ",
+            Self::Newsletter => "Ciao sono {persona}.\n",
+            Self::Podcast => "È uscito un nuovo episodio di ",
+            Self::Article => "È uscito un nuovo articolo su ",
+            Self::Greeting => "Ciao sono {persona}. ",
+        }
+    }
+
+    /// Get the placeholders which can be used in the template
+    fn placeholders(&self) -> &'static [&'static str] {
+        match self {
+            Self::Welcome | Self::Podcast | Self::Article => &[],
+            Self::GoodMorning => &["url"],
+            Self::Video
+            | Self::Short
+            | Self::Premiere
+            | Self::Live
+            | Self::Instagram
+            | Self::Newsletter
+            | Self::Greeting => &["persona"],
+        }
+    }
+
+    /// Set the templates configured for this instance. Must be called once, at startup
+    pub fn init(templates: HashMap<String, String>) -> anyhow::Result<()> {
+        TEMPLATES
+            .set(templates)
+            .map_err(|_| anyhow::anyhow!("templates are already set"))
+    }
+
+    /// Render the template configured for this instance, replacing the `{placeholder}`s with `vars`.
+    /// If the templates haven't been set, the default text is used
+    pub fn render(&self, vars: &[(&str, &str)]) -> String {
+        match TEMPLATES.get() {
+            Some(templates) => self.render_with(templates, vars),
+            None => self.render_with(&HashMap::new(), vars),
+        }
+    }
+
+    /// Render the template from `templates` or its default text, replacing the `{placeholder}`s with `vars`
+    fn render_with(&self, templates: &HashMap<String, String>, vars: &[(&str, &str)]) -> String {
+        let text = templates
+            .get(self.as_str())
+            .map(|x| x.as_str())
+            .unwrap_or_else(|| self.default_text());
+        vars.iter().fold(text.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+    }
+
+    /// Check that `text` is a valid template for this kind, using only the allowed placeholders
+    pub fn validate(&self, text: &str) -> anyhow::Result<()> {
+        let unknown: Vec<&str> = regex!(r"\{(\w+)\}")
+            .captures_iter(text)
+            .filter_map(|x| x.get(1))
+            .map(|x| x.as_str())
+            .filter(|x| !self.placeholders().contains(x))
+            .collect();
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            anyhow::bail!(
+                "template {} uses unknown placeholders: {}",
+                self,
+                unknown.join(", ")
+            )
+        }
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|x| x.as_str() == s)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "unknown template {}; templates are: {}",
+                    s,
+                    Self::ALL.map(|x| x.as_str()).join(", ")
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_render_template() {
+        let mut templates = HashMap::new();
+        assert_eq!(
+            Template::Video
+                .render_with(&templates, &[("persona", "Mario")])
+                .as_str(),
+            "Ciao sono Mario. Ho appena pubblicato questo nuovo mio video: "
+        );
+        templates.insert(
+            String::from("video"),
+            String::from("{persona} ha un nuovo video: "),
+        );
+        assert_eq!(
+            Template::Video
+                .render_with(&templates, &[("persona", "Mario")])
+                .as_str(),
+            "Mario ha un nuovo video: "
+        );
+    }
+
+    #[test]
+    fn should_validate_template() {
+        for template in Template::ALL {
+            assert!(template.validate(template.default_text()).is_ok());
+            assert_eq!(Template::from_str(template.as_str()).unwrap(), template);
+        }
+        assert!(Template::Video.validate("{persona} è in diretta").is_ok());
+        assert!(Template::Video.validate("{url} è in diretta").is_err());
+        assert!(Template::from_str("tv").is_err());
+    }
+}
@@ -0,0 +1,86 @@
+//! # Topic
+//!
+//! The topics of the notifications sent by the automatizer
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A topic chats can opt out from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Topic {
+    /// New videos, premieres and live streams
+    Video,
+    /// New youtube shorts
+    Short,
+    /// New instagram posts
+    Instagram,
+    /// New newsletters
+    Newsletter,
+    /// New articles from feed sources
+    Blog,
+    /// New podcast episodes from feed sources
+    Podcast,
+}
+
+impl Topic {
+    /// All the topics
+    pub const ALL: [Topic; 6] = [
+        Self::Video,
+        Self::Short,
+        Self::Instagram,
+        Self::Newsletter,
+        Self::Blog,
+        Self::Podcast,
+    ];
+
+    /// Get topic as str
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Video => "video",
+            Self::Short => "short",
+            Self::Instagram => "instagram",
+            Self::Newsletter => "newsletter",
+            Self::Blog => "blog",
+            Self::Podcast => "podcast",
+        }
+    }
+}
+
+impl fmt::Display for Topic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Topic {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "video" => Ok(Self::Video),
+            "short" => Ok(Self::Short),
+            "instagram" => Ok(Self::Instagram),
+            "newsletter" => Ok(Self::Newsletter),
+            "blog" => Ok(Self::Blog),
+            "podcast" => Ok(Self::Podcast),
+            _ => anyhow::bail!("unknown topic: {}", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_convert_topic_to_and_from_str() {
+        for topic in Topic::ALL {
+            assert_eq!(Topic::from_str(topic.as_str()).unwrap(), topic);
+            assert_eq!(topic.to_string().as_str(), topic.as_str());
+        }
+        assert!(Topic::from_str("tv").is_err());
+    }
+}
@@ -0,0 +1,343 @@
+//! # Feed
+//!
+//! Atom and JSON feeds of the contents announced by the bot
+
+use crate::irina::creator::Creator;
+use crate::irina::repository::Repository;
+use crate::irina::topic::Topic;
+use crate::repository::content::Content;
+
+use axum::extract::Query;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// Default amount of entries in the feed
+const DEFAULT_LIMIT: u32 = 20;
+/// Max amount of entries in the feed
+const MAX_LIMIT: u32 = 100;
+const FEED_ID: &str = "urn:spazio-grigio-bot:feed";
+const FEED_TITLE: &str = "Spazio Grigio";
+/// Feed author, for the entries without a creator
+const FEED_AUTHOR: &str = "Spazio Grigio";
+const JSON_FEED_VERSION: &str = "https://jsonfeed.org/version/1.1";
+
+type FeedResult<T> = Result<T, (StatusCode, String)>;
+
+/// Feed query parameters
+#[derive(Debug, Default, Deserialize)]
+pub struct FeedQuery {
+    /// Comma separated list of topics to include. All topics if unset
+    topics: Option<String>,
+    /// Amount of entries
+    limit: Option<u32>,
+}
+
+impl FeedQuery {
+    /// Get the topics to filter
+    fn topics(&self) -> anyhow::Result<Vec<Topic>> {
+        self.topics
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(Topic::from_str)
+            .collect()
+    }
+
+    /// Get the amount of entries to serve
+    fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+    }
+}
+
+/// Serve the Atom feed
+pub async fn atom(Query(query): Query<FeedQuery>) -> Response {
+    match get_contents(&query).await {
+        Ok(contents) => (
+            [(CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+            render_atom(&contents, &authors()),
+        )
+            .into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Serve the JSON feed
+pub async fn json(Query(query): Query<FeedQuery>) -> Response {
+    match get_contents(&query).await {
+        Ok(contents) => (
+            [(CONTENT_TYPE, "application/feed+json; charset=utf-8")],
+            render_json(&contents, &authors()),
+        )
+            .into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Get the archived contents matching `query`
+async fn get_contents(query: &FeedQuery) -> FeedResult<Vec<Content>> {
+    let topics = query
+        .topics()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let repository = Repository::connect().await.map_err(internal_error)?;
+    repository
+        .get_archived(&topics, query.limit())
+        .await
+        .map_err(internal_error)
+}
+
+fn internal_error(err: anyhow::Error) -> (StatusCode, String) {
+    error!("failed to collect archived contents: {}", err);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        String::from("internal server error"),
+    )
+}
+
+/// Get the creators persona by creator id
+fn authors() -> HashMap<String, String> {
+    Creator::all()
+        .unwrap_or_default()
+        .iter()
+        .map(|x| (x.id.clone(), x.persona.clone()))
+        .collect()
+}
+
+/// Get the publication date of content as a rfc3339 string
+fn published_at(content: &Content) -> String {
+    content
+        .published_at()
+        .map(|x| x.to_rfc3339())
+        .unwrap_or_default()
+}
+
+/// Get the author name of content
+fn author<'a>(content: &'a Content, authors: &'a HashMap<String, String>) -> Option<&'a str> {
+    content
+        .creator()
+        .map(|x| authors.get(x).map(|x| x.as_str()).unwrap_or(x))
+}
+
+/// Escape text for XML, removing the characters XML 1.0 doesn't allow
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(ch),
+            ch if ch < '\u{20}' || ch == '\u{fffe}' || ch == '\u{ffff}' => {}
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Render contents as an Atom feed
+fn render_atom(contents: &[Content], authors: &HashMap<String, String>) -> String {
+    let updated = contents
+        .first()
+        .map(published_at)
+        .unwrap_or_else(|| Utc::now().to_rfc3339());
+    let mut feed = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    feed.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+    let _ = write!(
+        feed,
+        "<id>{}</id><title>{}</title><updated>{}</updated><author><name>{}</name></author>",
+        FEED_ID, FEED_TITLE, updated, FEED_AUTHOR
+    );
+    for content in contents.iter() {
+        feed.push_str("<entry>");
+        let _ = write!(
+            feed,
+            "<id>{}</id><title>{}</title><updated>{}</updated><category term=\"{}\"/>",
+            escape(content.id()),
+            escape(content.title()),
+            published_at(content),
+            escape(content.topic())
+        );
+        if let Some(url) = content.url() {
+            let _ = write!(feed, "<link href=\"{}\"/>", escape(url));
+        }
+        if let Some(author) = author(content, authors) {
+            let _ = write!(feed, "<author><name>{}</name></author>", escape(author));
+        }
+        if !content.summary().is_empty() {
+            let _ = write!(feed, "<summary>{}</summary>", escape(content.summary()));
+        }
+        feed.push_str("</entry>");
+    }
+    feed.push_str("</feed>");
+    feed
+}
+
+/// JSON feed
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'static str,
+    items: Vec<JsonFeedItem<'a>>,
+}
+
+/// JSON feed item
+#[derive(Serialize)]
+struct JsonFeedItem<'a> {
+    id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<&'a str>,
+    title: &'a str,
+    content_text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<&'a str>,
+    date_published: String,
+    tags: [&'a str; 1],
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonFeedAuthor<'a>>,
+}
+
+/// JSON feed author
+#[derive(Serialize)]
+struct JsonFeedAuthor<'a> {
+    name: &'a str,
+}
+
+/// Render contents as a JSON feed
+fn render_json(contents: &[Content], authors: &HashMap<String, String>) -> String {
+    let feed = JsonFeed {
+        version: JSON_FEED_VERSION,
+        title: FEED_TITLE,
+        items: contents
+            .iter()
+            .map(|content| JsonFeedItem {
+                id: content.id(),
+                url: content.url(),
+                title: content.title(),
+                content_text: content.summary(),
+                image: content.image(),
+                date_published: published_at(content),
+                tags: [content.topic()],
+                authors: author(content, authors)
+                    .map(|name| JsonFeedAuthor { name })
+                    .into_iter()
+                    .collect(),
+            })
+            .collect(),
+    };
+    serde_json::to_string(&feed).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_parse_feed_query() {
+        let query = FeedQuery::default();
+        assert!(query.topics().unwrap().is_empty());
+        assert_eq!(query.limit(), DEFAULT_LIMIT);
+        let query = FeedQuery {
+            topics: Some(String::from("video, short,")),
+            limit: Some(1000),
+        };
+        assert_eq!(query.topics().unwrap(), vec![Topic::Video, Topic::Short]);
+        assert_eq!(query.limit(), MAX_LIMIT);
+        let query = FeedQuery {
+            topics: Some(String::from("video,tv")),
+            limit: None,
+        };
+        assert!(query.topics().is_err());
+    }
+
+    #[test]
+    fn should_render_atom_feed() {
+        let feed = render_atom(&contents(), &authors());
+        assert!(feed.starts_with(r#"<?xml version="1.0" encoding="utf-8"?><feed"#));
+        assert!(feed.contains("<updated>2022-09-10T08:00:00+00:00</updated>"));
+        assert!(feed.contains(
+            "<id>yt:video:rRQP8PNEouo</id><title>Il mio armadio &lt;minimalista&gt;</title>"
+        ));
+        assert!(
+            feed.contains(r#"<link href="https://www.youtube.com/watch?v=rRQP8PNEouo&amp;t=1"/>"#)
+        );
+        assert!(feed.contains("<author><name>Irina</name></author>"));
+        // entries without a creator inherit the feed author
+        assert!(feed.contains("</updated><author><name>Spazio Grigio</name></author><entry>"));
+        assert_eq!(feed.matches("<author>").count(), 2);
+        assert!(feed.contains(r#"<category term="newsletter"/>"#));
+        assert_eq!(feed.matches("<entry>").count(), 2);
+    }
+
+    #[test]
+    fn should_escape_xml() {
+        assert_eq!(
+            escape("<a href=\"x\">Tom & Jerry's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;"
+        );
+        assert_eq!(
+            escape("riga\u{0}\u{8}\tuno\r\ndue\u{1b}[0m\u{ffff}"),
+            "riga\tuno\r\ndue[0m"
+        );
+        assert_eq!(escape("ciao 👋"), "ciao 👋");
+    }
+
+    #[test]
+    fn should_render_json_feed() {
+        let feed: serde_json::Value =
+            serde_json::from_str(&render_json(&contents(), &authors())).unwrap();
+        assert_eq!(feed["version"], JSON_FEED_VERSION);
+        assert_eq!(feed["items"].as_array().unwrap().len(), 2);
+        assert_eq!(feed["items"][0]["id"], "yt:video:rRQP8PNEouo");
+        assert_eq!(feed["items"][0]["tags"][0], "video");
+        assert_eq!(feed["items"][0]["authors"][0]["name"], "Irina");
+        assert_eq!(
+            feed["items"][0]["image"],
+            "https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg"
+        );
+        assert!(feed["items"][1].get("url").is_none());
+        assert!(feed["items"][1].get("authors").is_none());
+    }
+
+    fn authors() -> HashMap<String, String> {
+        HashMap::from([(String::from("spaziogrigio"), String::from("Irina"))])
+    }
+
+    fn contents() -> Vec<Content> {
+        vec![
+            Content::new(
+                "yt:video:rRQP8PNEouo",
+                Topic::Video,
+                Some("spaziogrigio"),
+                "Il mio armadio <minimalista>",
+                "Ecco il mio armadio minimalista",
+                Utc.ymd(2022, 9, 10).and_hms(8, 0, 0),
+            )
+            .with_url(Some(String::from(
+                "https://www.youtube.com/watch?v=rRQP8PNEouo&t=1",
+            )))
+            .with_image(Some(String::from(
+                "https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg",
+            ))),
+            Content::new(
+                "newsletter:spaziogrigio:2022-09-08T19:30:00+00:00",
+                Topic::Newsletter,
+                None,
+                "Una settimana minimalista",
+                "Ciao a tutti",
+                Utc.ymd(2022, 9, 8).and_hms(19, 30, 0),
+            ),
+        ]
+    }
+}
@@ -0,0 +1,77 @@
+//! # Health
+//!
+//! Liveness, readiness and metrics endpoints, used by the container platforms and by Prometheus
+
+use crate::irina::metrics::METRICS;
+use crate::irina::redis::RedisRepository;
+use crate::irina::repository::Repository;
+use crate::irina::AUTOMATIZER;
+
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Liveness probe: the bot is up as long as it can answer
+pub async fn healthz() -> Response {
+    (StatusCode::OK, "ok").into_response()
+}
+
+/// Readiness probe: checks the database, redis and the automatizer scheduler
+pub async fn readyz() -> Response {
+    let checks = [
+        ("database", check_database().await),
+        ("redis", check_redis().await),
+        ("scheduler", check_scheduler().await),
+    ];
+    let ready = checks.iter().all(|(_, result)| result.is_ok());
+    let body: String = checks
+        .iter()
+        .map(|(name, result)| match result {
+            Ok(()) => format!("{}: ok\n", name),
+            Err(err) => format!("{}: {}\n", name, err),
+        })
+        .collect();
+    if !ready {
+        warn!("readiness check failed: {}", body.trim());
+    }
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, body).into_response()
+}
+
+/// Serve the metrics in the Prometheus text format
+pub async fn metrics() -> Response {
+    let subscribers = match Repository::connect().await {
+        Ok(repository) => repository.get_subscribed_chats().await.map(|x| x.len()),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = subscribers.as_ref() {
+        error!("could not count subscribers: {}", err);
+    }
+    (
+        [(CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)],
+        METRICS.render(subscribers.ok()),
+    )
+        .into_response()
+}
+
+async fn check_database() -> anyhow::Result<()> {
+    Repository::connect().await.map(|_| ())
+}
+
+async fn check_redis() -> anyhow::Result<()> {
+    RedisRepository::connect()?.ping().await
+}
+
+async fn check_scheduler() -> anyhow::Result<()> {
+    match AUTOMATIZER.get() {
+        Some(automatizer) if automatizer.is_running().await => Ok(()),
+        Some(_) => anyhow::bail!("scheduler is not running"),
+        None => anyhow::bail!("automatizer is not started"),
+    }
+}
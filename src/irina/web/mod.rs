@@ -0,0 +1,76 @@
+//! # Web
+//!
+//! This module exposes the http endpoints served by the bot, next to the telegram webhooks
+
+mod feed;
+mod health;
+
+use axum::routing::get;
+use axum::Router;
+use std::net::SocketAddr;
+
+/// Build the router with the public endpoints, which are served along with the webhooks
+pub fn public_router() -> Router {
+    Router::new()
+        .route("/feed.atom", get(feed::atom))
+        .route("/feed.json", get(feed::json))
+}
+
+/// Build the router with the probes and metrics, which are served only on `HTTP_ADDRESS`
+pub fn internal_router() -> Router {
+    Router::new()
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/metrics", get(health::metrics))
+}
+
+/// Build the router with all the endpoints served by the bot
+pub fn router() -> Router {
+    public_router().merge(internal_router())
+}
+
+/// Serve `router` on `address`
+pub async fn serve(address: SocketAddr, router: Router) -> anyhow::Result<()> {
+    info!("serving http endpoints on {}", address);
+    axum::Server::bind(&address)
+        .serve(router.into_make_service())
+        .await
+        .map_err(|e| anyhow::anyhow!("http server error: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use reqwest::StatusCode;
+
+    #[tokio::test]
+    async fn should_serve_probes_and_metrics_only_on_internal_router() {
+        let public = spawn(public_router());
+        let internal = spawn(internal_router());
+        for path in ["/healthz", "/readyz", "/metrics"] {
+            assert_eq!(status(public, path).await, StatusCode::NOT_FOUND);
+        }
+        assert_eq!(status(internal, "/healthz").await, StatusCode::OK);
+        assert_eq!(status(internal, "/feed.atom").await, StatusCode::NOT_FOUND);
+    }
+
+    /// Serve router on a random local port, returning its address
+    fn spawn(router: Router) -> SocketAddr {
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+        let address = server.local_addr();
+        tokio::spawn(server);
+        address
+    }
+
+    /// Get the status of the response to a GET request for path
+    async fn status(address: SocketAddr, path: &str) -> StatusCode {
+        reqwest::get(format!("http://{}{}", address, path))
+            .await
+            .unwrap()
+            .status()
+    }
+}
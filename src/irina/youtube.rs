@@ -1,32 +1,34 @@
 //! # Youtube
 //!
-//! This module exposes the function to fetch the youtube latest videos from spazio grigio
+//! This module exposes the function to fetch the youtube latest videos from the creators
 
-use crate::feed::Entry;
-use crate::youtube::{Feed, YoutubeClient};
+use super::config::Config;
+use super::creator::Creator;
+use crate::youtube::{Entry, Feed, YoutubeClient};
 
 use chrono::{DateTime, Utc};
 
-// <https://www.youtube.com/feeds/videos.xml?channel_id=UCK3cMi97Kf_WENLvRFdztoQ>
-const CHANNEL_ID: &str = "UCK3cMi97Kf_WENLvRFdztoQ";
-
 pub struct Youtube;
 
 impl Youtube {
-    /// Get latest video from spazio grigio
-    pub async fn get_latest_video() -> anyhow::Result<Entry> {
-        if let Some(video) = Self::get_latest_videos().await?.entries().next() {
+    /// Get latest video from creator
+    pub async fn get_latest_video(creator: &Creator) -> anyhow::Result<Entry> {
+        if let Some(video) = Self::get_latest_videos(creator).await?.entries().next() {
             Ok(video.clone())
         } else {
-            anyhow::bail!("Ciao sono Irina. Non ho nessun video da mostrarti.")
+            anyhow::bail!(
+                "Ciao sono {}. Non ho nessun video da mostrarti.",
+                creator.persona
+            )
         }
     }
 
     /// Get oldest unseen video from youtube
     pub async fn get_oldest_unseen_video(
+        creator: &Creator,
         last_video_pubdate: DateTime<Utc>,
     ) -> anyhow::Result<Option<Entry>> {
-        let feed = Self::get_latest_videos().await?;
+        let feed = Self::get_latest_videos(creator).await?;
         // sort by date
         let mut entries: Vec<Entry> = feed.entries().cloned().collect();
         entries.sort_by_key(|x| x.date);
@@ -38,14 +40,32 @@ impl Youtube {
         Ok(None)
     }
 
-    /// Get latest videos from spazio grigio
-    pub async fn get_latest_videos() -> anyhow::Result<Feed> {
-        let client = YoutubeClient::new(CHANNEL_ID);
+    /// Get latest videos from creator
+    pub async fn get_latest_videos(creator: &Creator) -> anyhow::Result<Feed> {
+        let channel_id = match creator.youtube_channel_id.as_deref() {
+            Some(id) => id,
+            None => anyhow::bail!(
+                "Ciao sono {}. Non ho un canale youtube da mostrarti.",
+                creator.persona
+            ),
+        };
+        let client = Self::client(channel_id)?;
         client.fetch().await.map_err(|e| {
             anyhow::anyhow!(
-                "Ciao sono Irina. Non riesco ad ottenere gli ultimi video di Spazio Grigio: {}",
+                "Ciao sono {}. Non riesco ad ottenere i miei ultimi video: {}",
+                creator.persona,
                 e
             )
         })
     }
+
+    /// Get the client for `channel_id`. The client shares the cached feed with the previous ones
+    fn client(channel_id: &str) -> anyhow::Result<YoutubeClient> {
+        let client = YoutubeClient::new(channel_id);
+        Ok(match Config::load()?.youtube_url.as_deref() {
+            Some(url) => client.base_url(url),
+            None => client,
+        }
+        .shared())
+    }
 }
@@ -11,6 +11,12 @@ pub enum EmailError {
     LoginFailed(ImapError),
     #[error("failed to parse email body: {0}")]
     ParseError(String),
+    #[error("invalid imap server name: {0}")]
+    InvalidServerName(String),
+    #[error("STARTTLS failed: {0}")]
+    StartTls(String),
+    #[error("unknown imap security {0}; use tls, starttls or none")]
+    UnknownSecurity(String),
 }
 
 impl From<ImapError> for EmailError {
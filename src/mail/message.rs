@@ -64,3 +64,56 @@ impl TryFrom<&[u8]> for Message {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::super::test_server::{BAD_FROM, HTML_ONLY, LATIN1, MULTIPART};
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_parse_multipart_message() {
+        let message = Message::try_from(MULTIPART).unwrap();
+        assert_eq!(message.sender_address.as_str(), "info@spaziogrigio.com");
+        assert_eq!(message.sender_name.as_deref(), Some("Spazio Grigio"));
+        assert_eq!(message.subject.as_str(), "Il minimalismo in cucina");
+        assert_eq!(message.date, Utc.ymd(2022, 9, 1).and_hms(10, 0, 0));
+        assert_eq!(
+            message.body.trim_end(),
+            "Ciao, oggi parliamo di come liberare la cucina dal superfluo."
+        );
+    }
+
+    #[test]
+    fn should_parse_html_only_message() {
+        let message = Message::try_from(HTML_ONLY).unwrap();
+        assert_eq!(message.subject.as_str(), "Decluttering di settembre");
+        assert_eq!(message.date, Utc.ymd(2022, 9, 3).and_hms(10, 0, 0));
+        assert_eq!(
+            message.body.trim(),
+            "Settembre è il mese perfetto per il decluttering."
+        );
+    }
+
+    #[test]
+    fn should_parse_message_with_non_utf8_charset() {
+        let message = Message::try_from(LATIN1).unwrap();
+        assert_eq!(message.sender_address.as_str(), "newsletter@minimalista.it");
+        assert_eq!(message.sender_name.as_deref(), Some("Minimalismo Più"));
+        assert_eq!(message.subject.as_str(), "Perché possedere meno");
+        assert_eq!(
+            message.body.trim_end(),
+            "Possedere meno è più facile di quanto sembri: ecco perché."
+        );
+    }
+
+    #[test]
+    fn should_not_parse_message_without_sender_address() {
+        assert!(matches!(
+            Message::try_from(BAD_FROM),
+            Err(EmailError::ParseError(_))
+        ));
+    }
+}
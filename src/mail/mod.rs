@@ -2,32 +2,115 @@
 //!
 //! this module exposes the email client
 
-use async_native_tls::TlsStream;
+use async_imap::error::Error as ImapError;
+use async_std::io::{Read, ReadExt, Write, WriteExt};
 use async_std::net::TcpStream;
 use futures::TryStreamExt;
+use futures_rustls::rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+use futures_rustls::TlsConnector;
+use std::fmt;
+use std::sync::Arc;
 
 mod errors;
 mod message;
+mod security;
+#[cfg(test)]
+pub mod test_server;
 
 pub use errors::{EmailError, EmailResult};
 pub use message::Message;
+pub use security::ImapSecurity;
+
+/// Tag of the STARTTLS command, sent before the imap client takes over the connection
+const STARTTLS_TAG: &str = "S0";
+
+/// A connection to the imap server
+pub trait ImapStream: Read + Write + Unpin + fmt::Debug + Send {}
+
+impl<T: Read + Write + Unpin + fmt::Debug + Send> ImapStream for T {}
 
 pub struct EmailClient {
-    session: async_imap::Session<TlsStream<TcpStream>>,
+    session: async_imap::Session<Box<dyn ImapStream>>,
 }
 
 impl EmailClient {
-    /// Connect to email client
+    /// Connect to email client, securing the connection as requested by `security`
     pub async fn connect(
         server: &str,
         port: u16,
+        security: ImapSecurity,
         username: &str,
         password: &str,
     ) -> EmailResult<Self> {
-        let tls = async_native_tls::TlsConnector::new();
-        let client = async_imap::connect((server, port), server, tls)
+        Self::connect_with_roots(
+            server,
+            port,
+            security,
+            username,
+            password,
+            Self::webpki_roots(),
+        )
+        .await
+    }
+
+    /// Connect to email client, trusting the certificates issued by `roots`
+    async fn connect_with_roots(
+        server: &str,
+        port: u16,
+        security: ImapSecurity,
+        username: &str,
+        password: &str,
+        roots: RootCertStore,
+    ) -> EmailResult<Self> {
+        debug!("connecting to {}:{} ({})", server, port, security);
+        let stream = TcpStream::connect((server, port))
             .await
-            .map_err(EmailError::from)?;
+            .map_err(ImapError::from)?;
+        match security {
+            ImapSecurity::Tls => {
+                Self::login(
+                    Box::new(Self::tls(server, stream, roots).await?),
+                    username,
+                    password,
+                )
+                .await
+            }
+            ImapSecurity::StartTls => {
+                let stream = Self::starttls(stream).await?;
+                let client = async_imap::Client::new(Box::new(
+                    Self::tls(server, stream, roots).await?,
+                ) as Box<dyn ImapStream>);
+                Self::authenticate(client, username, password).await
+            }
+            ImapSecurity::None => Self::login(Box::new(stream), username, password).await,
+        }
+    }
+
+    /// Login on an established connection to the imap server, which has not sent its greeting yet
+    pub async fn login(
+        stream: Box<dyn ImapStream>,
+        username: &str,
+        password: &str,
+    ) -> EmailResult<Self> {
+        let mut client = async_imap::Client::new(stream);
+        match client.read_response().await {
+            Some(Ok(_)) => {}
+            Some(Err(err)) => return Err(ImapError::from(err).into()),
+            None => {
+                return Err(EmailError::Imap(ImapError::Bad(String::from(
+                    "could not read server greeting",
+                ))))
+            }
+        }
+        Self::authenticate(client, username, password).await
+    }
+
+    /// Login with the client
+    async fn authenticate(
+        client: async_imap::Client<Box<dyn ImapStream>>,
+        username: &str,
+        password: &str,
+    ) -> EmailResult<Self> {
         let session = client
             .login(username, password)
             .await
@@ -35,6 +118,72 @@ impl EmailClient {
         Ok(Self { session })
     }
 
+    /// Get the webpki root certificates
+    fn webpki_roots() -> RootCertStore {
+        let mut roots = RootCertStore::empty();
+        roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|x| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                x.subject,
+                x.spki,
+                x.name_constraints,
+            )
+        }));
+        roots
+    }
+
+    /// Secure the connection to server with TLS, trusting the certificates issued by `roots`
+    async fn tls<S: ImapStream>(
+        server: &str,
+        stream: S,
+        roots: RootCertStore,
+    ) -> EmailResult<futures_rustls::client::TlsStream<S>> {
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let name = ServerName::try_from(server)
+            .map_err(|_| EmailError::InvalidServerName(server.to_string()))?;
+        TlsConnector::from(Arc::new(config))
+            .connect(name, stream)
+            .await
+            .map_err(|e| ImapError::from(e).into())
+    }
+
+    /// Read the server greeting and ask the server to start the TLS negotiation.
+    /// The lines are read byte by byte, so that no byte of the TLS handshake is consumed
+    async fn starttls(mut stream: TcpStream) -> EmailResult<TcpStream> {
+        Self::read_line(&mut stream).await?;
+        stream
+            .write_all(format!("{} STARTTLS\r\n", STARTTLS_TAG).as_bytes())
+            .await
+            .map_err(ImapError::from)?;
+        loop {
+            let line = Self::read_line(&mut stream).await?;
+            if let Some(status) = line.strip_prefix(STARTTLS_TAG) {
+                return if status.trim_start().starts_with("OK") {
+                    Ok(stream)
+                } else {
+                    Err(EmailError::StartTls(status.trim().to_string()))
+                };
+            }
+        }
+    }
+
+    /// Read a line sent by the server
+    async fn read_line(stream: &mut TcpStream) -> EmailResult<String> {
+        let mut line = Vec::new();
+        let mut byte = [0u8];
+        while !line.ends_with(b"\r\n") {
+            if stream.read(&mut byte).await.map_err(ImapError::from)? == 0 {
+                return Err(EmailError::StartTls(String::from(
+                    "connection closed by server",
+                )));
+            }
+            line.push(byte[0]);
+        }
+        Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+    }
+
     /// Get all email messages
     pub async fn get_messages(&mut self) -> EmailResult<Vec<Message>> {
         self.session.select("INBOX").await?;
@@ -64,3 +213,166 @@ impl EmailClient {
         Ok(messages)
     }
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::test_server::{
+        ImapServer, BAD_FROM, HTML_ONLY, LATIN1, MULTIPART, PASSWORD, USERNAME,
+    };
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn should_get_messages() {
+        let server = ImapServer::start(&[MULTIPART, BAD_FROM, HTML_ONLY, LATIN1]).await;
+        let mut client = EmailClient::login(server.connect().await, USERNAME, PASSWORD)
+            .await
+            .unwrap();
+        let messages = client.get_messages().await.unwrap();
+        // the message without sender address is skipped
+        assert_eq!(
+            messages
+                .iter()
+                .map(|x| (x.sender_address.as_str(), x.subject.as_str()))
+                .collect::<Vec<(&str, &str)>>(),
+            vec![
+                ("info@spaziogrigio.com", "Il minimalismo in cucina"),
+                ("info@spaziogrigio.com", "Decluttering di settembre"),
+                ("newsletter@minimalista.it", "Perché possedere meno"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_get_messages_from_empty_inbox() {
+        let server = ImapServer::start(&[]).await;
+        let mut client = EmailClient::login(server.connect().await, USERNAME, PASSWORD)
+            .await
+            .unwrap();
+        assert!(client.get_messages().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_get_messages_delivered_after_login() {
+        let server = ImapServer::start(&[MULTIPART]).await;
+        let mut client = EmailClient::login(server.connect().await, USERNAME, PASSWORD)
+            .await
+            .unwrap();
+        assert_eq!(client.get_messages().await.unwrap().len(), 1);
+        server.deliver(HTML_ONLY);
+        assert_eq!(client.get_messages().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn should_connect_without_security() {
+        let server = ImapServer::start(&[MULTIPART]).await;
+        let mut client = EmailClient::connect(
+            "127.0.0.1",
+            server.port(),
+            ImapSecurity::None,
+            USERNAME,
+            PASSWORD,
+        )
+        .await
+        .unwrap();
+        assert_eq!(client.get_messages().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_get_messages_through_starttls() {
+        let server = ImapServer::start_secure(&[MULTIPART, LATIN1], ImapSecurity::StartTls).await;
+        let mut client = EmailClient::connect_with_roots(
+            "localhost",
+            server.port(),
+            ImapSecurity::StartTls,
+            USERNAME,
+            PASSWORD,
+            server.roots(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            client
+                .get_messages()
+                .await
+                .unwrap()
+                .iter()
+                .map(|x| x.subject.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["Il minimalismo in cucina", "Perché possedere meno"]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_get_messages_through_tls() {
+        let server = ImapServer::start_secure(&[MULTIPART], ImapSecurity::Tls).await;
+        let mut client = EmailClient::connect_with_roots(
+            "localhost",
+            server.port(),
+            ImapSecurity::Tls,
+            USERNAME,
+            PASSWORD,
+            server.roots(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(client.get_messages().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_not_trust_unknown_certificates() {
+        let server = ImapServer::start_secure(&[MULTIPART], ImapSecurity::StartTls).await;
+        assert!(matches!(
+            EmailClient::connect(
+                "localhost",
+                server.port(),
+                ImapSecurity::StartTls,
+                USERNAME,
+                PASSWORD,
+            )
+            .await,
+            Err(EmailError::Imap(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_fail_starttls_if_not_supported_by_server() {
+        let server = ImapServer::start(&[MULTIPART]).await;
+        assert!(matches!(
+            EmailClient::connect(
+                "localhost",
+                server.port(),
+                ImapSecurity::StartTls,
+                USERNAME,
+                PASSWORD,
+            )
+            .await,
+            Err(EmailError::StartTls(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_fail_tls_handshake_with_plain_server() {
+        let server = ImapServer::start(&[MULTIPART]).await;
+        assert!(EmailClient::connect(
+            "localhost",
+            server.port(),
+            ImapSecurity::Tls,
+            USERNAME,
+            PASSWORD,
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn should_fail_login_with_bad_credentials() {
+        let server = ImapServer::start(&[MULTIPART]).await;
+        assert!(matches!(
+            EmailClient::login(server.connect().await, USERNAME, "wrong").await,
+            Err(EmailError::LoginFailed(_))
+        ));
+    }
+}
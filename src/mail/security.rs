@@ -0,0 +1,69 @@
+//! # Security
+//!
+//! Security of the connection to the imap server
+
+use super::EmailError;
+
+use std::fmt;
+use std::str::FromStr;
+
+/// How the connection to the imap server is secured
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ImapSecurity {
+    /// TLS from the start of the connection (usually on port 993)
+    #[default]
+    Tls,
+    /// Plain connection upgraded to TLS with STARTTLS (usually on port 143)
+    StartTls,
+    /// Plain connection; credentials and messages are sent in clear, use for testing only
+    None,
+}
+
+impl fmt::Display for ImapSecurity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Tls => "tls",
+            Self::StartTls => "starttls",
+            Self::None => "none",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ImapSecurity {
+    type Err = EmailError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tls" | "ssl" => Ok(Self::Tls),
+            "starttls" => Ok(Self::StartTls),
+            "none" | "plain" => Ok(Self::None),
+            _ => Err(EmailError::UnknownSecurity(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_parse_imap_security() {
+        for security in [
+            ImapSecurity::Tls,
+            ImapSecurity::StartTls,
+            ImapSecurity::None,
+        ] {
+            assert_eq!(
+                ImapSecurity::from_str(&security.to_string()).unwrap(),
+                security
+            );
+        }
+        assert_eq!(ImapSecurity::from_str("SSL").unwrap(), ImapSecurity::Tls);
+        assert_eq!(ImapSecurity::from_str("plain").unwrap(), ImapSecurity::None);
+        assert!(ImapSecurity::from_str("ssh").is_err());
+    }
+}
@@ -0,0 +1,226 @@
+//! # Test server
+//!
+//! An in-process imap server, serving the messages of its INBOX over plain TCP, STARTTLS or TLS
+//! with a self-signed certificate for `localhost`.
+//! It implements only the commands the email client sends
+
+use super::ImapSecurity;
+
+use futures_rustls::rustls::RootCertStore;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+pub const USERNAME: &str = "irina@spaziogrigio.com";
+pub const PASSWORD: &str = "password";
+
+/// Fixture with both a text and an html part
+pub const MULTIPART: &[u8] = include_bytes!("../../tests/fixtures/mail/multipart.eml");
+/// Fixture with an html part only
+pub const HTML_ONLY: &[u8] = include_bytes!("../../tests/fixtures/mail/html_only.eml");
+/// Fixture whose `From` header has no address
+pub const BAD_FROM: &[u8] = include_bytes!("../../tests/fixtures/mail/bad_from.eml");
+/// Fixture encoded in ISO-8859-1
+pub const LATIN1: &[u8] = include_bytes!("../../tests/fixtures/mail/latin1.eml");
+
+/// A running imap server
+pub struct ImapServer {
+    port: u16,
+    inbox: Arc<Mutex<Vec<Vec<u8>>>>,
+    /// DER of the certificate of the server
+    certificate: Vec<u8>,
+}
+
+impl ImapServer {
+    /// Start the server with `messages` in its INBOX, without security
+    pub async fn start(messages: &[&[u8]]) -> Self {
+        Self::start_secure(messages, ImapSecurity::None).await
+    }
+
+    /// Start the server with `messages` in its INBOX, securing the connections as requested by `security`
+    pub async fn start_secure(messages: &[&[u8]], security: ImapSecurity) -> Self {
+        let certificate = rcgen::generate_simple_self_signed(vec![String::from("localhost")])
+            .expect("failed to generate certificate");
+        let der = certificate
+            .serialize_der()
+            .expect("failed to serialize certificate");
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![rustls::Certificate(der.clone())],
+                rustls::PrivateKey(certificate.serialize_private_key_der()),
+            )
+            .expect("invalid certificate");
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind imap server");
+        let port = listener.local_addr().unwrap().port();
+        let inbox = Arc::new(Mutex::new(
+            messages
+                .iter()
+                .map(|x| x.to_vec())
+                .collect::<Vec<Vec<u8>>>(),
+        ));
+        let server_inbox = inbox.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (inbox, acceptor) = (server_inbox.clone(), acceptor.clone());
+                tokio::spawn(async move {
+                    match security {
+                        ImapSecurity::Tls => {
+                            serve(acceptor.accept(stream).await?, inbox, None).await
+                        }
+                        ImapSecurity::StartTls => serve(stream, inbox, Some(acceptor)).await,
+                        ImapSecurity::None => serve(stream, inbox, None).await,
+                    }
+                });
+            }
+        });
+        Self {
+            port,
+            inbox,
+            certificate: der,
+        }
+    }
+
+    /// Get the port the server listens on
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Get the root certificates trusting the server
+    pub fn roots(&self) -> RootCertStore {
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(&futures_rustls::rustls::Certificate(
+                self.certificate.clone(),
+            ))
+            .expect("invalid certificate");
+        roots
+    }
+
+    /// Deliver a new message to the INBOX
+    pub fn deliver(&self, message: &[u8]) {
+        self.inbox.lock().unwrap().push(message.to_vec());
+    }
+
+    /// Connect to the server, without logging in
+    pub async fn connect(&self) -> Box<dyn super::ImapStream> {
+        Box::new(
+            async_std::net::TcpStream::connect(("127.0.0.1", self.port))
+                .await
+                .expect("failed to connect to imap server"),
+        )
+    }
+}
+
+/// Serve a client connection, which is upgraded with `starttls`, if set, when the client asks to
+async fn serve<S>(
+    stream: S,
+    inbox: Arc<Mutex<Vec<Vec<u8>>>>,
+    starttls: Option<TlsAcceptor>,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = BufReader::new(stream);
+    let greeting = format!(
+        "* OK [CAPABILITY {}] test server ready\r\n",
+        capabilities(starttls.is_some())
+    );
+    stream.write_all(greeting.as_bytes()).await?;
+    stream.flush().await?;
+    if !session(&mut stream, &inbox, starttls.is_some()).await? {
+        return Ok(());
+    }
+    if let Some(acceptor) = starttls {
+        // the client waits for the STARTTLS response, so nothing has been buffered
+        let mut stream = BufReader::new(acceptor.accept(stream.into_inner()).await?);
+        session(&mut stream, &inbox, false).await?;
+    }
+    Ok(())
+}
+
+/// Answer the commands of the client until it logs out or asks to start TLS, if `starttls` is supported.
+/// Returns whether the client asked to start TLS
+async fn session<S>(
+    stream: &mut BufReader<S>,
+    inbox: &Mutex<Vec<Vec<u8>>>,
+    starttls: bool,
+) -> std::io::Result<bool>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if stream.read_line(&mut line).await? == 0 {
+            return Ok(false);
+        }
+        let mut args = line.split_whitespace();
+        let (tag, command) = match (args.next(), args.next()) {
+            (Some(tag), Some(command)) => (tag, command.to_ascii_uppercase()),
+            _ => continue,
+        };
+        let args: Vec<&str> = args.map(|x| x.trim_matches('"')).collect();
+        let response = match command.as_str() {
+            "CAPABILITY" => format!(
+                "* CAPABILITY {}\r\nOK CAPABILITY completed",
+                capabilities(starttls)
+            ),
+            "STARTTLS" if starttls => {
+                let response = format!("{} OK begin TLS negotiation now\r\n", tag);
+                stream.write_all(response.as_bytes()).await?;
+                stream.flush().await?;
+                return Ok(true);
+            }
+            "LOGIN" if args == [USERNAME, PASSWORD] => String::from("OK LOGIN completed"),
+            "LOGIN" => String::from("NO [AUTHENTICATIONFAILED] invalid credentials"),
+            "SELECT" | "EXAMINE" => format!(
+                "* FLAGS (\\Seen)\r\n* {} EXISTS\r\n* 0 RECENT\r\nOK [READ-WRITE] SELECT completed",
+                inbox.lock().unwrap().len()
+            ),
+            "FETCH" => {
+                let seq = args.first().and_then(|x| x.parse::<usize>().ok());
+                let message = seq
+                    .and_then(|x| x.checked_sub(1))
+                    .and_then(|x| inbox.lock().unwrap().get(x).cloned());
+                if let (Some(seq), Some(message)) = (seq, message) {
+                    let header = format!("* {} FETCH (RFC822 {{{}}}\r\n", seq, message.len());
+                    stream.write_all(header.as_bytes()).await?;
+                    stream.write_all(&message).await?;
+                    stream.write_all(b")\r\n").await?;
+                }
+                String::from("OK FETCH completed")
+            }
+            "NOOP" => String::from("OK NOOP completed"),
+            "LOGOUT" => {
+                let response = format!("* BYE logging out\r\n{} OK LOGOUT completed\r\n", tag);
+                stream.write_all(response.as_bytes()).await?;
+                stream.flush().await?;
+                return Ok(false);
+            }
+            _ => String::from("BAD unknown command"),
+        };
+        // the tagged status is the last line of the response
+        let response = match response.rsplit_once("\r\n") {
+            Some((untagged, status)) => format!("{}\r\n{} {}\r\n", untagged, tag, status),
+            None => format!("{} {}\r\n", tag, response),
+        };
+        stream.write_all(response.as_bytes()).await?;
+        stream.flush().await?;
+    }
+}
+
+/// Get the capabilities advertised by the server
+fn capabilities(starttls: bool) -> &'static str {
+    if starttls {
+        "IMAP4rev1 STARTTLS"
+    } else {
+        "IMAP4rev1"
+    }
+}
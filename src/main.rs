@@ -8,6 +8,7 @@ extern crate serde;
 #[macro_use]
 extern crate tracing;
 
+mod cli;
 mod feed;
 mod irina;
 mod mail;
@@ -16,16 +17,55 @@ mod repository;
 mod utils;
 mod youtube;
 
-use irina::Irina;
+use cli::{Args, Command};
+use irina::{Config, Irina, Template};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
+    let args: Args = argh::from_env();
+    if let Some(config) = args.config {
+        Config::set_file(config)?;
+    }
+    if args.check_config {
+        check_config();
+    }
+    match args.command {
+        None | Some(Command::Run(_)) => tracing_subscriber::fmt::init(),
+        // keep stdout for the command output
+        Some(_) => tracing_subscriber::fmt()
+            .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+            .with_writer(std::io::stderr)
+            .init(),
+    }
     info!(
         "spazio-grigio-bot v{} - developed by {}",
         APP_VERSION, APP_AUTHORS
     );
+    // fail early on an invalid configuration, templates included
+    let config = Config::init()
+        .map_err(|problems| anyhow::anyhow!("invalid configuration: {}", problems.join("; ")))?;
+    Template::init(config.templates.clone())?;
+    match args.command {
+        None | Some(Command::Run(_)) => {}
+        Some(command) => return command.execute().await,
+    }
     let irina = Irina::init().await?;
     info!("application ready!");
     irina.run().await
 }
+
+/// Validate the configuration, print the problems found and exit
+fn check_config() -> ! {
+    match Config::check() {
+        Ok(_) => {
+            println!("configuration is valid");
+            std::process::exit(0)
+        }
+        Err(problems) => {
+            for problem in problems.iter() {
+                eprintln!("{}", problem);
+            }
+            std::process::exit(1)
+        }
+    }
+}
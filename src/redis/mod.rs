@@ -37,6 +37,20 @@ impl RedisClient {
         debug!("GET {}", key);
         connection.get(key).await
     }
+
+    /// Check whether redis is reachable
+    pub async fn ping(&mut self) -> RedisResult<()> {
+        let mut connection = self.client.get_async_connection().await?;
+        debug!("PING");
+        redis::cmd("PING").query_async(&mut connection).await
+    }
+
+    /// Delete key
+    pub async fn del(&mut self, key: &str) -> RedisResult<()> {
+        let mut connection = self.client.get_async_connection().await?;
+        debug!("DEL {}", key);
+        connection.del(key).await
+    }
 }
 
 #[cfg(test)]
@@ -61,6 +75,14 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn should_del_key() {
+        let mut client = RedisClient::connect("redis://localhost/").unwrap();
+        assert!(client.set("test:key4", "4").await.is_ok());
+        assert!(client.del("test:key4").await.is_ok());
+        assert_eq!(client.get::<String>("test:key4").await.unwrap(), None);
+    }
+
     #[tokio::test]
     async fn should_get_none() {
         let mut client = RedisClient::connect("redis://localhost/").unwrap();
@@ -5,7 +5,7 @@
 use super::{RepositoryError, RepositoryResult};
 
 use chrono::{DateTime, FixedOffset, Utc};
-use sqlx::{Pool, Sqlite};
+use sqlx::{Executor, Pool, Sqlite};
 use teloxide::types::ChatId;
 
 #[derive(sqlx::FromRow, Debug, Clone, Eq, PartialEq)]
@@ -22,6 +22,12 @@ impl Chat {
         }
     }
 
+    /// Set the date the chat subscribed at, e.g. when restoring an exported chat
+    pub fn with_created_at(mut self, created_at: DateTime<FixedOffset>) -> Self {
+        self.created_at = created_at.to_rfc3339();
+        self
+    }
+
     /// Return inner `ChatId`
     pub fn id(&self) -> ChatId {
         ChatId(self.id)
@@ -46,7 +52,10 @@ impl Chat {
     }
 
     /// Insert `Chat` to database
-    pub async fn insert(&self, db: &Pool<Sqlite>) -> RepositoryResult<()> {
+    pub async fn insert<'e, E>(&self, db: E) -> RepositoryResult<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
         debug!("inserting a new chat {} to repository", self.id);
         let rows = sqlx::query("INSERT INTO chat (id, created_at) VALUES ($1, $2)")
             .bind(self.id)
@@ -63,7 +72,10 @@ impl Chat {
     }
 
     /// Delete this chat from database
-    pub async fn delete(&self, db: &Pool<Sqlite>) -> RepositoryResult<()> {
+    pub async fn delete<'e, E>(&self, db: E) -> RepositoryResult<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
         debug!("deleting chat {} from repository", self.id);
         sqlx::query("DELETE FROM chat WHERE id = $1")
             .bind(self.id)
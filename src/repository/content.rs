@@ -0,0 +1,246 @@
+//! # Content
+//!
+//! this module contains the content entity repository, which archives the content announced by the bot
+
+use super::{RepositoryError, RepositoryResult};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use sqlx::{Pool, QueryBuilder, Sqlite};
+
+#[derive(sqlx::FromRow, Debug, Clone, Eq, PartialEq)]
+pub struct Content {
+    id: String,
+    url: Option<String>,
+    topic: String,
+    creator: Option<String>,
+    title: String,
+    summary: String,
+    image: Option<String>,
+    published_at: String,
+    created_at: String,
+}
+
+impl Content {
+    pub fn new(
+        id: impl ToString,
+        topic: impl ToString,
+        creator: Option<&str>,
+        title: impl ToString,
+        summary: impl ToString,
+        published_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            id: id.to_string(),
+            url: None,
+            topic: topic.to_string(),
+            creator: creator.map(|x| x.to_string()),
+            title: title.to_string(),
+            summary: summary.to_string(),
+            image: None,
+            published_at: published_at.to_rfc3339(),
+            created_at: Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Set content url
+    pub fn with_url(mut self, url: Option<String>) -> Self {
+        self.url = url;
+        self
+    }
+
+    /// Set content image
+    pub fn with_image(mut self, image: Option<String>) -> Self {
+        self.image = image;
+        self
+    }
+
+    /// Return content id
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Return content url
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// Return content topic
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Return the id of the creator who published the content
+    pub fn creator(&self) -> Option<&str> {
+        self.creator.as_deref()
+    }
+
+    /// Return content title
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Return content summary
+    pub fn summary(&self) -> &str {
+        &self.summary
+    }
+
+    /// Return content image url
+    pub fn image(&self) -> Option<&str> {
+        self.image.as_deref()
+    }
+
+    /// Return published_at as a `DateTime`
+    pub fn published_at(&self) -> RepositoryResult<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(&self.published_at)
+            .map_err(|_| RepositoryError::BadDateTimeSyntax)
+    }
+
+    /// Collect the latest `limit` contents, sorted by publication date (newest first).
+    /// If `topics` is not empty, only the contents with one of the provided topics are collected
+    pub async fn get_latest(
+        db: &Pool<Sqlite>,
+        topics: &[String],
+        limit: u32,
+    ) -> RepositoryResult<Vec<Content>> {
+        let mut query = QueryBuilder::new("SELECT * FROM content");
+        if !topics.is_empty() {
+            query.push(" WHERE topic IN (");
+            let mut separated = query.separated(", ");
+            for topic in topics.iter() {
+                separated.push_bind(topic);
+            }
+            separated.push_unseparated(")");
+        }
+        query.push(" ORDER BY published_at DESC LIMIT ");
+        query.push_bind(limit);
+        query
+            .build_query_as()
+            .fetch_all(db)
+            .await
+            .map_err(RepositoryError::from)
+    }
+
+    /// Collect the contents archived after `date`, sorted by publication date
+    pub async fn get_archived_since(
+        db: &Pool<Sqlite>,
+        date: DateTime<Utc>,
+    ) -> RepositoryResult<Vec<Content>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM content
+            WHERE created_at > $1
+            ORDER BY published_at"#,
+        )
+        .bind(date.to_rfc3339())
+        .fetch_all(db)
+        .await
+        .map_err(RepositoryError::from)
+    }
+
+    /// Insert `Content` to database. If content with the same id already exists, it is replaced
+    pub async fn insert(&self, db: &Pool<Sqlite>) -> RepositoryResult<()> {
+        debug!("archiving content {} ({})", self.id, self.topic);
+        sqlx::query(
+            r#"INSERT OR REPLACE INTO content
+            (id, url, topic, creator, title, summary, image, published_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"#,
+        )
+        .bind(&self.id)
+        .bind(&self.url)
+        .bind(&self.topic)
+        .bind(&self.creator)
+        .bind(&self.title)
+        .bind(&self.summary)
+        .bind(&self.image)
+        .bind(&self.published_at)
+        .bind(&self.created_at)
+        .execute(db)
+        .await
+        .map_err(RepositoryError::from)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::repository::test::init_database;
+
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn should_insert_content() {
+        let (db, temp) = init_database().await;
+        let content = content("video", "https://www.youtube.com/watch?v=rRQP8PNEouo", 1);
+        assert!(content.insert(db.pool()).await.is_ok());
+        assert!(content.insert(db.pool()).await.is_ok());
+        assert_eq!(
+            Content::get_latest(db.pool(), &[], 10).await.unwrap(),
+            vec![content]
+        );
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_get_latest_content() {
+        let (db, temp) = init_database().await;
+        let contents = [
+            content("video", "https://www.youtube.com/watch?v=rRQP8PNEouo", 1),
+            content("newsletter", "https://www.spaziogrigio.com", 2),
+            content("short", "https://www.youtube.com/shorts/tMZmKRk54bQ", 3),
+        ];
+        for content in contents.iter() {
+            assert!(content.insert(db.pool()).await.is_ok());
+        }
+        assert_eq!(
+            Content::get_latest(db.pool(), &[], 2).await.unwrap(),
+            vec![contents[2].clone(), contents[1].clone()]
+        );
+        assert_eq!(
+            Content::get_latest(
+                db.pool(),
+                &[String::from("video"), String::from("short")],
+                10
+            )
+            .await
+            .unwrap(),
+            vec![contents[2].clone(), contents[0].clone()]
+        );
+        assert!(
+            Content::get_latest(db.pool(), &[String::from("podcast")], 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            Content::get_archived_since(db.pool(), Utc.ymd(2022, 9, 1).and_hms(0, 0, 0))
+                .await
+                .unwrap(),
+            &contents
+        );
+        assert!(Content::get_archived_since(db.pool(), Utc::now())
+            .await
+            .unwrap()
+            .is_empty());
+        drop(temp)
+    }
+
+    fn content(topic: &str, url: &str, day: u32) -> Content {
+        Content::new(
+            url,
+            topic,
+            Some("spaziogrigio"),
+            "title",
+            "summary",
+            Utc.ymd(2022, 9, day).and_hms(8, 0, 0),
+        )
+        .with_url(Some(url.to_string()))
+        .with_image(Some(String::from(
+            "https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg",
+        )))
+    }
+}
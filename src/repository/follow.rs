@@ -0,0 +1,182 @@
+//! # Follow
+//!
+//! this module contains the follow entity repository, which relates chats to the creators they follow
+
+use super::{RepositoryError, RepositoryResult};
+
+use chrono::Utc;
+use sqlx::{Executor, Pool, Sqlite};
+use teloxide::types::ChatId;
+
+#[derive(sqlx::FromRow, Debug, Clone, Eq, PartialEq)]
+pub struct Follow {
+    chat_id: i64,
+    creator: String,
+    created_at: String,
+}
+
+impl Follow {
+    pub fn new(chat_id: ChatId, creator: impl ToString) -> Self {
+        Self {
+            chat_id: chat_id.0,
+            creator: creator.to_string(),
+            created_at: Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Return inner `ChatId`
+    pub fn chat_id(&self) -> ChatId {
+        ChatId(self.chat_id)
+    }
+
+    /// Return followed creator id
+    pub fn creator(&self) -> &str {
+        &self.creator
+    }
+
+    /// Collect all the follows of `chat_id`
+    pub async fn get_by_chat(db: &Pool<Sqlite>, chat_id: ChatId) -> RepositoryResult<Vec<Follow>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM follow
+            WHERE chat_id = $1
+            ORDER BY created_at"#,
+        )
+        .bind(chat_id.0)
+        .fetch_all(db)
+        .await
+        .map_err(RepositoryError::from)
+    }
+
+    /// Collect all the followers of `creator`
+    pub async fn get_by_creator(db: &Pool<Sqlite>, creator: &str) -> RepositoryResult<Vec<Follow>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM follow
+            WHERE creator = $1
+            ORDER BY created_at"#,
+        )
+        .bind(creator)
+        .fetch_all(db)
+        .await
+        .map_err(RepositoryError::from)
+    }
+
+    /// Insert `Follow` to database
+    pub async fn insert<'e, E>(&self, db: E) -> RepositoryResult<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
+        debug!(
+            "inserting a new follow {} -> {} to repository",
+            self.chat_id, self.creator
+        );
+        let rows =
+            sqlx::query("INSERT INTO follow (chat_id, creator, created_at) VALUES ($1, $2, $3)")
+                .bind(self.chat_id)
+                .bind(&self.creator)
+                .bind(&self.created_at)
+                .execute(db)
+                .await
+                .map_err(RepositoryError::from)?
+                .rows_affected();
+        if rows != 1 {
+            return Err(RepositoryError::TooManyInserts);
+        }
+
+        Ok(())
+    }
+
+    /// Delete this follow from database
+    pub async fn delete(&self, db: &Pool<Sqlite>) -> RepositoryResult<()> {
+        debug!(
+            "deleting follow {} -> {} from repository",
+            self.chat_id, self.creator
+        );
+        sqlx::query("DELETE FROM follow WHERE chat_id = $1 AND creator = $2")
+            .bind(self.chat_id)
+            .bind(&self.creator)
+            .execute(db)
+            .await
+            .map_err(RepositoryError::from)?;
+
+        Ok(())
+    }
+
+    /// Delete all the follows of `chat_id`
+    pub async fn delete_by_chat<'e, E>(db: E, chat_id: ChatId) -> RepositoryResult<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
+        debug!("deleting all follows of {} from repository", chat_id);
+        sqlx::query("DELETE FROM follow WHERE chat_id = $1")
+            .bind(chat_id.0)
+            .execute(db)
+            .await
+            .map_err(RepositoryError::from)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::repository::test::init_database;
+
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn should_insert_follow() {
+        let (db, temp) = init_database().await;
+        let follow = Follow::new(ChatId(32), "spaziogrigio");
+        assert!(follow.insert(db.pool()).await.is_ok());
+        assert!(follow.insert(db.pool()).await.is_err());
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_delete_follow() {
+        let (db, temp) = init_database().await;
+        let follow = Follow::new(ChatId(32), "spaziogrigio");
+        assert!(follow.insert(db.pool()).await.is_ok());
+        assert!(follow.delete(db.pool()).await.is_ok());
+        assert!(Follow::get_by_chat(db.pool(), ChatId(32))
+            .await
+            .unwrap()
+            .is_empty());
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_retrieve_follows() {
+        let (db, temp) = init_database().await;
+        let follows = [
+            Follow::new(ChatId(1), "spaziogrigio"),
+            Follow::new(ChatId(1), "minimalista"),
+            Follow::new(ChatId(2), "spaziogrigio"),
+        ];
+        for follow in follows.iter() {
+            assert!(follow.insert(db.pool()).await.is_ok());
+        }
+        assert_eq!(
+            Follow::get_by_chat(db.pool(), ChatId(1)).await.unwrap(),
+            &follows[0..2]
+        );
+        assert_eq!(
+            Follow::get_by_creator(db.pool(), "spaziogrigio")
+                .await
+                .unwrap(),
+            vec![follows[0].clone(), follows[2].clone()]
+        );
+        assert!(Follow::delete_by_chat(db.pool(), ChatId(1)).await.is_ok());
+        assert!(Follow::get_by_chat(db.pool(), ChatId(1))
+            .await
+            .unwrap()
+            .is_empty());
+        drop(temp)
+    }
+}
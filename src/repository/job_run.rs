@@ -0,0 +1,269 @@
+//! # Job run
+//!
+//! this module contains the job run entity repository, which records the outcome of each run of the automatizer jobs
+
+use super::{RepositoryError, RepositoryResult};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use sqlx::{Pool, Sqlite};
+
+#[derive(sqlx::FromRow, Debug, Clone, Eq, PartialEq)]
+pub struct JobRun {
+    job: String,
+    started_at: String,
+    finished_at: String,
+    error: Option<String>,
+    delivered: i64,
+}
+
+impl JobRun {
+    pub fn new(
+        job: impl ToString,
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+        error: Option<String>,
+        delivered: usize,
+    ) -> Self {
+        Self {
+            job: job.to_string(),
+            started_at: started_at.to_rfc3339(),
+            finished_at: finished_at.to_rfc3339(),
+            error,
+            delivered: delivered as i64,
+        }
+    }
+
+    /// Return started_at as a `DateTime`
+    pub fn started_at(&self) -> RepositoryResult<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(&self.started_at)
+            .map_err(|_| RepositoryError::BadDateTimeSyntax)
+    }
+
+    /// Return finished_at as a `DateTime`
+    pub fn finished_at(&self) -> RepositoryResult<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(&self.finished_at)
+            .map_err(|_| RepositoryError::BadDateTimeSyntax)
+    }
+
+    /// Return the error the run failed with, if any
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Return the amount of messages delivered by the run
+    pub fn delivered(&self) -> usize {
+        self.delivered as usize
+    }
+
+    /// Collect the latest `limit` runs of `job`, newest first
+    pub async fn get_latest(
+        db: &Pool<Sqlite>,
+        job: &str,
+        limit: u32,
+    ) -> RepositoryResult<Vec<JobRun>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM job_run
+            WHERE job = $1
+            ORDER BY started_at DESC, rowid DESC
+            LIMIT $2"#,
+        )
+        .bind(job)
+        .bind(limit)
+        .fetch_all(db)
+        .await
+        .map_err(RepositoryError::from)
+    }
+
+    /// Get the latest successful run of `job`
+    pub async fn get_last_success(
+        db: &Pool<Sqlite>,
+        job: &str,
+    ) -> RepositoryResult<Option<JobRun>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM job_run
+            WHERE job = $1 AND error IS NULL
+            ORDER BY started_at DESC, rowid DESC
+            LIMIT 1"#,
+        )
+        .bind(job)
+        .fetch_optional(db)
+        .await
+        .map_err(RepositoryError::from)
+    }
+
+    /// Count the failed runs of `job` since its latest successful run
+    pub async fn count_consecutive_failures(
+        db: &Pool<Sqlite>,
+        job: &str,
+    ) -> RepositoryResult<usize> {
+        let (count,): (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*)
+            FROM job_run
+            WHERE job = $1 AND error IS NOT NULL AND started_at > COALESCE(
+                (SELECT MAX(started_at) FROM job_run WHERE job = $1 AND error IS NULL),
+                ''
+            )"#,
+        )
+        .bind(job)
+        .fetch_one(db)
+        .await
+        .map_err(RepositoryError::from)?;
+        Ok(count as usize)
+    }
+
+    /// Insert `JobRun` to database
+    pub async fn insert(&self, db: &Pool<Sqlite>) -> RepositoryResult<()> {
+        debug!(
+            "recording run of {} started at {}",
+            self.job, self.started_at
+        );
+        let rows = sqlx::query(
+            r#"INSERT INTO job_run
+            (job, started_at, finished_at, error, delivered)
+            VALUES ($1, $2, $3, $4, $5)"#,
+        )
+        .bind(&self.job)
+        .bind(&self.started_at)
+        .bind(&self.finished_at)
+        .bind(&self.error)
+        .bind(self.delivered)
+        .execute(db)
+        .await
+        .map_err(RepositoryError::from)?
+        .rows_affected();
+        if rows != 1 {
+            return Err(RepositoryError::TooManyInserts);
+        }
+
+        Ok(())
+    }
+
+    /// Delete the runs started before `date`
+    pub async fn delete_before(db: &Pool<Sqlite>, date: DateTime<Utc>) -> RepositoryResult<()> {
+        sqlx::query("DELETE FROM job_run WHERE started_at < $1")
+            .bind(date.to_rfc3339())
+            .execute(db)
+            .await
+            .map_err(RepositoryError::from)
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::repository::test::init_database;
+
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn should_insert_and_get_job_runs() {
+        let (db, temp) = init_database().await;
+        let runs = [
+            run("video", 1, None, 3),
+            run("video", 2, Some("youtube error"), 0),
+            run("feed", 3, None, 1),
+        ];
+        for run in runs.iter() {
+            assert!(run.insert(db.pool()).await.is_ok());
+        }
+        assert_eq!(
+            JobRun::get_latest(db.pool(), "video", 10).await.unwrap(),
+            vec![runs[1].clone(), runs[0].clone()]
+        );
+        assert_eq!(
+            JobRun::get_latest(db.pool(), "video", 1).await.unwrap(),
+            vec![runs[1].clone()]
+        );
+        assert_eq!(runs[0].delivered(), 3);
+        assert_eq!(runs[1].error(), Some("youtube error"));
+        assert!(JobRun::get_latest(db.pool(), "instagram", 10)
+            .await
+            .unwrap()
+            .is_empty());
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_count_consecutive_failures() {
+        let (db, temp) = init_database().await;
+        assert_eq!(
+            JobRun::count_consecutive_failures(db.pool(), "instagram")
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(JobRun::get_last_success(db.pool(), "instagram")
+            .await
+            .unwrap()
+            .is_none());
+        let runs = [
+            run("instagram", 1, Some("scraper error"), 0),
+            run("instagram", 2, None, 1),
+            run("instagram", 3, Some("scraper error"), 0),
+            run("instagram", 4, Some("scraper error"), 0),
+            run("video", 5, Some("youtube error"), 0),
+        ];
+        for run in runs.iter() {
+            assert!(run.insert(db.pool()).await.is_ok());
+        }
+        assert_eq!(
+            JobRun::count_consecutive_failures(db.pool(), "instagram")
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            JobRun::get_last_success(db.pool(), "instagram")
+                .await
+                .unwrap(),
+            Some(runs[1].clone())
+        );
+        assert_eq!(
+            JobRun::count_consecutive_failures(db.pool(), "video")
+                .await
+                .unwrap(),
+            1
+        );
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_delete_old_job_runs() {
+        let (db, temp) = init_database().await;
+        for run in [run("video", 1, None, 0), run("video", 3, None, 0)].iter() {
+            assert!(run.insert(db.pool()).await.is_ok());
+        }
+        assert!(
+            JobRun::delete_before(db.pool(), Utc.ymd(2022, 9, 2).and_hms(0, 0, 0))
+                .await
+                .is_ok()
+        );
+        assert_eq!(
+            JobRun::get_latest(db.pool(), "video", 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        drop(temp)
+    }
+
+    fn run(job: &str, day: u32, error: Option<&str>, delivered: usize) -> JobRun {
+        let started_at = Utc.ymd(2022, 9, day).and_hms(8, 0, 0);
+        JobRun::new(
+            job,
+            started_at,
+            started_at + chrono::Duration::seconds(2),
+            error.map(|x| x.to_string()),
+            delivered,
+        )
+    }
+}
@@ -3,6 +3,11 @@
 //! This module contains the trait and the model to implement to interact with the repository
 
 pub mod chat;
+pub mod content;
+pub mod follow;
+pub mod job_run;
+pub mod optout;
+pub mod subscription;
 use sqlx::sqlite::SqlitePool;
 use thiserror::Error;
 
@@ -57,13 +62,82 @@ impl SqliteDb {
         )
         .execute(self.pool())
         .await
+        .map_err(RepositoryError::from)?;
+        // follow table
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS follow (
+            chat_id INTEGER NOT NULL,
+            creator TEXT NOT NULL,
+            created_at TEXT,
+            PRIMARY KEY (chat_id, creator)
+          );"#,
+        )
+        .execute(self.pool())
+        .await
+        .map_err(RepositoryError::from)?;
+        // optout table
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS optout (
+            chat_id INTEGER NOT NULL,
+            topic TEXT NOT NULL,
+            created_at TEXT,
+            PRIMARY KEY (chat_id, topic)
+          );"#,
+        )
+        .execute(self.pool())
+        .await
+        .map_err(RepositoryError::from)?;
+        // content table
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS content (
+            id TEXT PRIMARY KEY,
+            url TEXT,
+            topic TEXT NOT NULL,
+            creator TEXT,
+            title TEXT NOT NULL,
+            summary TEXT NOT NULL,
+            image TEXT,
+            published_at TEXT NOT NULL,
+            created_at TEXT
+          );"#,
+        )
+        .execute(self.pool())
+        .await
+        .map_err(RepositoryError::from)?;
+        // job run table
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS job_run (
+            job TEXT NOT NULL,
+            started_at TEXT NOT NULL,
+            finished_at TEXT NOT NULL,
+            error TEXT,
+            delivered INTEGER NOT NULL
+          );"#,
+        )
+        .execute(self.pool())
+        .await
+        .map_err(RepositoryError::from)?;
+        // subscription table
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS subscription (
+            chat_id INTEGER NOT NULL,
+            target TEXT NOT NULL,
+            address TEXT NOT NULL,
+            creator TEXT NOT NULL,
+            confirmation TEXT,
+            created_at TEXT,
+            PRIMARY KEY (chat_id, target, address, creator)
+          );"#,
+        )
+        .execute(self.pool())
+        .await
         .map_err(RepositoryError::from)
         .map(|_| ())
     }
 }
 
 #[cfg(test)]
-mod test {
+pub mod test {
 
     use super::*;
 
@@ -0,0 +1,184 @@
+//! # Opt-out
+//!
+//! this module contains the opt-out entity repository, which stores the topics a chat doesn't want to be notified about
+
+use super::{RepositoryError, RepositoryResult};
+
+use chrono::Utc;
+use sqlx::{Executor, Pool, Sqlite};
+use teloxide::types::ChatId;
+
+#[derive(sqlx::FromRow, Debug, Clone, Eq, PartialEq)]
+pub struct OptOut {
+    chat_id: i64,
+    topic: String,
+    created_at: String,
+}
+
+impl OptOut {
+    pub fn new(chat_id: ChatId, topic: impl ToString) -> Self {
+        Self {
+            chat_id: chat_id.0,
+            topic: topic.to_string(),
+            created_at: Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Return inner `ChatId`
+    pub fn chat_id(&self) -> ChatId {
+        ChatId(self.chat_id)
+    }
+
+    /// Return opted-out topic
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Collect all the opt-outs of `chat_id`
+    pub async fn get_by_chat(db: &Pool<Sqlite>, chat_id: ChatId) -> RepositoryResult<Vec<OptOut>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM optout
+            WHERE chat_id = $1
+            ORDER BY created_at"#,
+        )
+        .bind(chat_id.0)
+        .fetch_all(db)
+        .await
+        .map_err(RepositoryError::from)
+    }
+
+    /// Collect all the opt-outs for `topic`
+    pub async fn get_by_topic(db: &Pool<Sqlite>, topic: &str) -> RepositoryResult<Vec<OptOut>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM optout
+            WHERE topic = $1
+            ORDER BY created_at"#,
+        )
+        .bind(topic)
+        .fetch_all(db)
+        .await
+        .map_err(RepositoryError::from)
+    }
+
+    /// Insert `OptOut` to database. If the chat had already opted out from the topic, nothing happens
+    pub async fn insert<'e, E>(&self, db: E) -> RepositoryResult<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
+        debug!(
+            "inserting a new opt-out {} -> {} to repository",
+            self.chat_id, self.topic
+        );
+        sqlx::query(
+            "INSERT OR IGNORE INTO optout (chat_id, topic, created_at) VALUES ($1, $2, $3)",
+        )
+        .bind(self.chat_id)
+        .bind(&self.topic)
+        .bind(&self.created_at)
+        .execute(db)
+        .await
+        .map_err(RepositoryError::from)?;
+
+        Ok(())
+    }
+
+    /// Delete this opt-out from database
+    pub async fn delete(&self, db: &Pool<Sqlite>) -> RepositoryResult<()> {
+        debug!(
+            "deleting opt-out {} -> {} from repository",
+            self.chat_id, self.topic
+        );
+        sqlx::query("DELETE FROM optout WHERE chat_id = $1 AND topic = $2")
+            .bind(self.chat_id)
+            .bind(&self.topic)
+            .execute(db)
+            .await
+            .map_err(RepositoryError::from)?;
+
+        Ok(())
+    }
+
+    /// Delete all the opt-outs of `chat_id`
+    pub async fn delete_by_chat<'e, E>(db: E, chat_id: ChatId) -> RepositoryResult<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
+        debug!("deleting all opt-outs of {} from repository", chat_id);
+        sqlx::query("DELETE FROM optout WHERE chat_id = $1")
+            .bind(chat_id.0)
+            .execute(db)
+            .await
+            .map_err(RepositoryError::from)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::repository::test::init_database;
+
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn should_insert_optout_once() {
+        let (db, temp) = init_database().await;
+        let optout = OptOut::new(ChatId(32), "short");
+        assert!(optout.insert(db.pool()).await.is_ok());
+        assert!(optout.insert(db.pool()).await.is_ok());
+        assert_eq!(
+            OptOut::get_by_chat(db.pool(), ChatId(32))
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_delete_optout() {
+        let (db, temp) = init_database().await;
+        let optout = OptOut::new(ChatId(32), "short");
+        assert!(optout.insert(db.pool()).await.is_ok());
+        assert!(optout.delete(db.pool()).await.is_ok());
+        assert!(OptOut::get_by_topic(db.pool(), "short")
+            .await
+            .unwrap()
+            .is_empty());
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_retrieve_optouts() {
+        let (db, temp) = init_database().await;
+        let optouts = [
+            OptOut::new(ChatId(1), "short"),
+            OptOut::new(ChatId(1), "instagram"),
+            OptOut::new(ChatId(2), "short"),
+        ];
+        for optout in optouts.iter() {
+            assert!(optout.insert(db.pool()).await.is_ok());
+        }
+        assert_eq!(
+            OptOut::get_by_chat(db.pool(), ChatId(1)).await.unwrap(),
+            &optouts[0..2]
+        );
+        assert_eq!(
+            OptOut::get_by_topic(db.pool(), "short").await.unwrap(),
+            vec![optouts[0].clone(), optouts[2].clone()]
+        );
+        assert!(OptOut::delete_by_chat(db.pool(), ChatId(1)).await.is_ok());
+        assert!(OptOut::get_by_chat(db.pool(), ChatId(1))
+            .await
+            .unwrap()
+            .is_empty());
+        drop(temp)
+    }
+}
@@ -0,0 +1,429 @@
+//! # Subscription
+//!
+//! this module contains the subscription entity repository, which relates delivery targets outside of Telegram
+//! (matrix rooms, discord webhooks, email addresses) to the chat owning them and to the creators they follow
+
+use super::{RepositoryError, RepositoryResult};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use sqlx::{Executor, Pool, Sqlite};
+use teloxide::types::ChatId;
+
+#[derive(sqlx::FromRow, Debug, Clone, Eq, PartialEq)]
+pub struct Subscription {
+    chat_id: i64,
+    target: String,
+    address: String,
+    creator: String,
+    /// Code the owner must send back to confirm the subscription. `None` once confirmed
+    confirmation: Option<String>,
+    created_at: String,
+}
+
+impl Subscription {
+    pub fn new(
+        chat_id: ChatId,
+        target: impl ToString,
+        address: impl ToString,
+        creator: impl ToString,
+    ) -> Self {
+        Self {
+            chat_id: chat_id.0,
+            target: target.to_string(),
+            address: address.to_string(),
+            creator: creator.to_string(),
+            confirmation: None,
+            created_at: Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Make the subscription pending until confirmed with `code`
+    pub fn pending(mut self, code: impl ToString) -> Self {
+        self.confirmation = Some(code.to_string());
+        self
+    }
+
+    /// Return the `ChatId` of the chat owning the subscription
+    pub fn chat_id(&self) -> ChatId {
+        ChatId(self.chat_id)
+    }
+
+    /// Return the kind of the delivery target
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Return the address of the delivery target (room id, webhook url, email address)
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Return followed creator id
+    pub fn creator(&self) -> &str {
+        &self.creator
+    }
+
+    /// Returns whether the subscription has been confirmed
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmation.is_none()
+    }
+
+    /// Return the date the subscription was requested
+    pub fn created_at(&self) -> RepositoryResult<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(&self.created_at)
+            .map_err(|_| RepositoryError::BadDateTimeSyntax)
+    }
+
+    /// Collect all the confirmed subscriptions
+    pub async fn get_all(db: &Pool<Sqlite>) -> RepositoryResult<Vec<Subscription>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM subscription
+            WHERE confirmation IS NULL
+            ORDER BY created_at"#,
+        )
+        .fetch_all(db)
+        .await
+        .map_err(RepositoryError::from)
+    }
+
+    /// Collect all the subscriptions of `chat_id`, including the pending ones
+    pub async fn get_by_chat(
+        db: &Pool<Sqlite>,
+        chat_id: ChatId,
+    ) -> RepositoryResult<Vec<Subscription>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM subscription
+            WHERE chat_id = $1
+            ORDER BY created_at"#,
+        )
+        .bind(chat_id.0)
+        .fetch_all(db)
+        .await
+        .map_err(RepositoryError::from)
+    }
+
+    /// Collect the pending subscriptions of any chat for the target of `target` kind at `address`
+    pub async fn get_pending_by_address(
+        db: &Pool<Sqlite>,
+        target: &str,
+        address: &str,
+    ) -> RepositoryResult<Vec<Subscription>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM subscription
+            WHERE target = $1 AND address = $2 AND confirmation IS NOT NULL
+            ORDER BY created_at"#,
+        )
+        .bind(target)
+        .bind(address)
+        .fetch_all(db)
+        .await
+        .map_err(RepositoryError::from)
+    }
+
+    /// Collect all the confirmed subscriptions to `creator`
+    pub async fn get_by_creator(
+        db: &Pool<Sqlite>,
+        creator: &str,
+    ) -> RepositoryResult<Vec<Subscription>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM subscription
+            WHERE creator = $1 AND confirmation IS NULL
+            ORDER BY created_at"#,
+        )
+        .bind(creator)
+        .fetch_all(db)
+        .await
+        .map_err(RepositoryError::from)
+    }
+
+    /// Collect all the confirmed subscriptions of `target` kind
+    pub async fn get_by_target(
+        db: &Pool<Sqlite>,
+        target: &str,
+    ) -> RepositoryResult<Vec<Subscription>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM subscription
+            WHERE target = $1 AND confirmation IS NULL
+            ORDER BY created_at"#,
+        )
+        .bind(target)
+        .fetch_all(db)
+        .await
+        .map_err(RepositoryError::from)
+    }
+
+    /// Insert `Subscription` to database
+    pub async fn insert<'e, E>(&self, db: E) -> RepositoryResult<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
+        debug!(
+            "inserting a new subscription {} {} -> {} of {} to repository",
+            self.target, self.address, self.creator, self.chat_id
+        );
+        let rows = sqlx::query(
+            "INSERT INTO subscription (chat_id, target, address, creator, confirmation, created_at) VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(self.chat_id)
+        .bind(&self.target)
+        .bind(&self.address)
+        .bind(&self.creator)
+        .bind(&self.confirmation)
+        .bind(&self.created_at)
+        .execute(db)
+        .await
+        .map_err(RepositoryError::from)?
+        .rows_affected();
+        if rows != 1 {
+            return Err(RepositoryError::TooManyInserts);
+        }
+
+        Ok(())
+    }
+
+    /// Confirm the pending subscriptions of `chat_id` for the target of `target` kind at `address`.
+    /// Returns the amount of confirmed subscriptions, which is 0 if `code` doesn't match
+    pub async fn confirm(
+        db: &Pool<Sqlite>,
+        chat_id: ChatId,
+        target: &str,
+        address: &str,
+        code: &str,
+    ) -> RepositoryResult<u64> {
+        debug!(
+            "confirming subscriptions of {} {} for {}",
+            target, address, chat_id
+        );
+        sqlx::query(
+            "UPDATE subscription SET confirmation = NULL WHERE chat_id = $1 AND target = $2 AND address = $3 AND confirmation = $4",
+        )
+        .bind(chat_id.0)
+        .bind(target)
+        .bind(address)
+        .bind(code)
+        .execute(db)
+        .await
+        .map_err(RepositoryError::from)
+        .map(|x| x.rows_affected())
+    }
+
+    /// Delete all the subscriptions of `chat_id` for the target of `target` kind at `address`
+    pub async fn delete_by_address(
+        db: &Pool<Sqlite>,
+        chat_id: ChatId,
+        target: &str,
+        address: &str,
+    ) -> RepositoryResult<()> {
+        debug!(
+            "deleting all subscriptions of {} {} for {} from repository",
+            target, address, chat_id
+        );
+        sqlx::query("DELETE FROM subscription WHERE chat_id = $1 AND target = $2 AND address = $3")
+            .bind(chat_id.0)
+            .bind(target)
+            .bind(address)
+            .execute(db)
+            .await
+            .map_err(RepositoryError::from)?;
+
+        Ok(())
+    }
+
+    /// Delete all the subscriptions of `chat_id`
+    pub async fn delete_by_chat<'e, E>(db: E, chat_id: ChatId) -> RepositoryResult<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
+        debug!("deleting all subscriptions of {} from repository", chat_id);
+        sqlx::query("DELETE FROM subscription WHERE chat_id = $1")
+            .bind(chat_id.0)
+            .execute(db)
+            .await
+            .map_err(RepositoryError::from)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::repository::test::init_database;
+
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn should_insert_subscription() {
+        let (db, temp) = init_database().await;
+        let subscription =
+            Subscription::new(ChatId(1), "email", "irina@spaziogrigio.com", "spaziogrigio");
+        assert!(subscription.insert(db.pool()).await.is_ok());
+        assert!(subscription.insert(db.pool()).await.is_err());
+        // another chat can subscribe the same target
+        assert!(
+            Subscription::new(ChatId(2), "email", "irina@spaziogrigio.com", "spaziogrigio")
+                .insert(db.pool())
+                .await
+                .is_ok()
+        );
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_delete_subscriptions() {
+        let (db, temp) = init_database().await;
+        let subscriptions = [
+            Subscription::new(ChatId(1), "email", "irina@spaziogrigio.com", "spaziogrigio"),
+            Subscription::new(ChatId(1), "email", "irina@spaziogrigio.com", "minimalista"),
+            Subscription::new(ChatId(2), "email", "irina@spaziogrigio.com", "spaziogrigio"),
+            Subscription::new(ChatId(1), "matrix", "!room:matrix.org", "spaziogrigio"),
+        ];
+        for subscription in subscriptions.iter() {
+            assert!(subscription.insert(db.pool()).await.is_ok());
+        }
+        assert!(Subscription::delete_by_address(
+            db.pool(),
+            ChatId(1),
+            "email",
+            "irina@spaziogrigio.com"
+        )
+        .await
+        .is_ok());
+        assert_eq!(
+            Subscription::get_all(db.pool()).await.unwrap(),
+            &subscriptions[2..]
+        );
+        assert!(Subscription::delete_by_chat(db.pool(), ChatId(1))
+            .await
+            .is_ok());
+        assert_eq!(
+            Subscription::get_all(db.pool()).await.unwrap(),
+            &subscriptions[2..3]
+        );
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_retrieve_subscriptions() {
+        let (db, temp) = init_database().await;
+        let subscriptions = [
+            Subscription::new(ChatId(1), "email", "irina@spaziogrigio.com", "spaziogrigio"),
+            Subscription::new(ChatId(1), "matrix", "!room:matrix.org", "spaziogrigio"),
+            Subscription::new(ChatId(2), "email", "irina@spaziogrigio.com", "minimalista"),
+        ];
+        for subscription in subscriptions.iter() {
+            assert!(subscription.insert(db.pool()).await.is_ok());
+        }
+        assert_eq!(
+            Subscription::get_all(db.pool()).await.unwrap(),
+            &subscriptions
+        );
+        assert_eq!(
+            Subscription::get_by_chat(db.pool(), ChatId(1))
+                .await
+                .unwrap(),
+            &subscriptions[0..2]
+        );
+        assert_eq!(
+            Subscription::get_by_creator(db.pool(), "spaziogrigio")
+                .await
+                .unwrap(),
+            &subscriptions[0..2]
+        );
+        assert_eq!(
+            Subscription::get_by_target(db.pool(), "email")
+                .await
+                .unwrap(),
+            vec![subscriptions[0].clone(), subscriptions[2].clone()]
+        );
+        drop(temp)
+    }
+
+    #[tokio::test]
+    async fn should_confirm_pending_subscriptions() {
+        let (db, temp) = init_database().await;
+        let subscription = Subscription::new(
+            ChatId(1),
+            "discord",
+            "https://discord.com/api/webhooks/1234/token",
+            "spaziogrigio",
+        );
+        assert!(subscription
+            .clone()
+            .pending("123456")
+            .insert(db.pool())
+            .await
+            .is_ok());
+        // pending subscriptions are not delivered to
+        assert!(Subscription::get_all(db.pool()).await.unwrap().is_empty());
+        assert!(!Subscription::get_by_chat(db.pool(), ChatId(1))
+            .await
+            .unwrap()[0]
+            .is_confirmed());
+        assert_eq!(
+            Subscription::get_pending_by_address(db.pool(), "discord", subscription.address())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            Subscription::confirm(
+                db.pool(),
+                ChatId(1),
+                "discord",
+                subscription.address(),
+                "654321"
+            )
+            .await
+            .unwrap(),
+            0
+        );
+        assert_eq!(
+            Subscription::confirm(
+                db.pool(),
+                ChatId(2),
+                "discord",
+                subscription.address(),
+                "123456"
+            )
+            .await
+            .unwrap(),
+            0
+        );
+        assert_eq!(
+            Subscription::confirm(
+                db.pool(),
+                ChatId(1),
+                "discord",
+                subscription.address(),
+                "123456"
+            )
+            .await
+            .unwrap(),
+            1
+        );
+        assert!(
+            Subscription::get_pending_by_address(db.pool(), "discord", subscription.address())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            Subscription::get_all(db.pool()).await.unwrap(),
+            vec![subscription]
+        );
+        drop(temp)
+    }
+}
@@ -42,6 +42,23 @@ pub fn strip_html(s: &str) -> String {
     escaped
 }
 
+/// excerpt
+///
+/// Get the first `max_chars` characters of `s`, cutting at the last whitespace and appending an ellipsis.
+/// Only the first paragraph of `s` is considered
+pub fn excerpt(s: &str, max_chars: usize) -> String {
+    let paragraph = s.trim().lines().next().unwrap_or_default().trim();
+    if paragraph.chars().count() <= max_chars {
+        return paragraph.to_string();
+    }
+    let cut: String = paragraph.chars().take(max_chars).collect();
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(index) => &cut[..index],
+        None => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end())
+}
+
 #[cfg(test)]
 mod test {
 
@@ -62,4 +79,16 @@ mod test {
             "Hello, <World>!"
         );
     }
+
+    #[test]
+    fn should_make_excerpt() {
+        assert_eq!(excerpt("Ciao sono Irina", 32), "Ciao sono Irina");
+        assert_eq!(excerpt("Ciao sono Irina", 12), "Ciao sono…");
+        assert_eq!(
+            excerpt("  Ciao sono Irina\nBenvenuto in spazio grigio", 32),
+            "Ciao sono Irina"
+        );
+        assert_eq!(excerpt("minimalismo", 4), "mini…");
+        assert_eq!(excerpt("", 4), "");
+    }
 }
@@ -2,7 +2,10 @@
 //!
 //! Youtube client errors
 
+use crate::feed::FeedError;
+
 use feed_rs::parser::ParseFeedError;
+use reqwest::StatusCode;
 use thiserror::Error;
 
 pub type YoutubeResult<T> = Result<T, YoutubeError>;
@@ -12,10 +15,22 @@ pub type YoutubeResult<T> = Result<T, YoutubeError>;
 pub enum YoutubeError {
     #[error("HTTP error: {0}")]
     HttpError(reqwest::Error),
+    #[error("HTTP request failed with status {0}")]
+    HttpStatus(StatusCode),
     #[error("Feed parser error: {0}")]
     FeedParserError(ParseFeedError),
 }
 
+impl From<FeedError> for YoutubeError {
+    fn from(e: FeedError) -> Self {
+        match e {
+            FeedError::HttpError(e) => Self::HttpError(e),
+            FeedError::HttpStatus(status) => Self::HttpStatus(status),
+            FeedError::FeedParserError(e) => Self::FeedParserError(e),
+        }
+    }
+}
+
 impl From<reqwest::Error> for YoutubeError {
     fn from(e: reqwest::Error) -> Self {
         Self::HttpError(e)
@@ -0,0 +1,168 @@
+//! # Kind
+//!
+//! Youtube video kind classification
+
+use super::Entry;
+
+/// Describes the kind of a youtube feed entry
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VideoKind {
+    /// A regular video
+    Video,
+    /// A youtube short
+    Short,
+    /// A scheduled premiere, which hasn't been aired yet
+    Premiere,
+    /// A live stream
+    Live,
+}
+
+impl VideoKind {
+    /// Classify a feed entry by its url and its metadata.
+    ///
+    /// Shorts and live streams are told by their url. The feed lists scheduled premieres and upcoming live streams
+    /// with 0 views, but so are regular uploads before their first view: an entry without views is a premiere
+    /// or a live stream only if its title says so, otherwise it's a regular video.
+    pub fn classify(entry: &Entry) -> Self {
+        if entry.url.contains("/shorts/") {
+            Self::Short
+        } else if entry.url.contains("/live/")
+            || (entry.views == Some(0) && Self::title_has(entry, &["live", "diretta"]))
+        {
+            Self::Live
+        } else if entry.views == Some(0) && Self::title_has(entry, &["premiere", "anteprima"]) {
+            Self::Premiere
+        } else {
+            Self::Video
+        }
+    }
+
+    /// Check whether the title of entry contains one of `words`, ignoring case
+    fn title_has(entry: &Entry, words: &[&str]) -> bool {
+        entry
+            .title
+            .as_deref()
+            .map(|x| {
+                x.to_lowercase()
+                    .split(|c: char| !c.is_alphanumeric())
+                    .any(|word| words.contains(&word))
+            })
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_classify_video() {
+        assert_eq!(
+            VideoKind::classify(&entry(
+                "https://www.youtube.com/watch?v=rRQP8PNEouo",
+                "La mia morning routine",
+                Some(1024)
+            )),
+            VideoKind::Video
+        );
+        assert_eq!(
+            VideoKind::classify(&entry(
+                "https://www.youtube.com/watch?v=rRQP8PNEouo",
+                "La mia morning routine",
+                None
+            )),
+            VideoKind::Video
+        );
+    }
+
+    #[test]
+    fn should_classify_short() {
+        assert_eq!(
+            VideoKind::classify(&entry(
+                "https://www.youtube.com/shorts/rRQP8PNEouo",
+                "Declutter in 30 secondi",
+                Some(0)
+            )),
+            VideoKind::Short
+        );
+    }
+
+    #[test]
+    fn should_classify_fresh_upload_as_video() {
+        assert_eq!(
+            VideoKind::classify(&entry(
+                "https://www.youtube.com/watch?v=rRQP8PNEouo",
+                "Il mio nuovo armadio minimalista",
+                Some(0)
+            )),
+            VideoKind::Video
+        );
+    }
+
+    #[test]
+    fn should_classify_premiere() {
+        assert_eq!(
+            VideoKind::classify(&entry(
+                "https://www.youtube.com/watch?v=rRQP8PNEouo",
+                "Il mio nuovo armadio minimalista (anteprima)",
+                Some(0)
+            )),
+            VideoKind::Premiere
+        );
+        // once aired, a premiere is a regular video
+        assert_eq!(
+            VideoKind::classify(&entry(
+                "https://www.youtube.com/watch?v=rRQP8PNEouo",
+                "Il mio nuovo armadio minimalista (anteprima)",
+                Some(128)
+            )),
+            VideoKind::Video
+        );
+    }
+
+    #[test]
+    fn should_classify_live() {
+        assert_eq!(
+            VideoKind::classify(&entry(
+                "https://www.youtube.com/live/rRQP8PNEouo",
+                "Domande e risposte",
+                Some(12)
+            )),
+            VideoKind::Live
+        );
+        assert_eq!(
+            VideoKind::classify(&entry(
+                "https://www.youtube.com/watch?v=rRQP8PNEouo",
+                "Domande e risposte in diretta",
+                Some(0)
+            )),
+            VideoKind::Live
+        );
+        assert_eq!(
+            VideoKind::classify(&entry(
+                "https://www.youtube.com/watch?v=rRQP8PNEouo",
+                "LIVE: domande e risposte",
+                Some(0)
+            )),
+            VideoKind::Live
+        );
+    }
+
+    fn entry(url: &str, title: &str, views: Option<u64>) -> Entry {
+        Entry {
+            id: String::from("yt:video:rRQP8PNEouo"),
+            title: Some(title.to_string()),
+            authors: Vec::new(),
+            summary: String::new(),
+            url: url.to_string(),
+            date: None,
+            thumbnail: None,
+            description: None,
+            views,
+            enclosure: None,
+        }
+    }
+}
@@ -2,56 +2,75 @@
 //!
 //! A youtube feed client
 
-use feed_rs::parser as feed_parser;
-use std::io::Cursor;
+use crate::feed::FeedClient;
+
+use std::sync::Arc;
 
 mod errors;
+mod kind;
 
 pub use crate::feed::{Entry, Feed};
 pub use errors::{YoutubeError, YoutubeResult};
+pub use kind::VideoKind;
+
+/// Get the youtube video id of a feed entry
+pub fn video_id(entry: &Entry) -> Option<&str> {
+    entry.id.strip_prefix("yt:video:")
+}
 
-/// Youtube feed client
+/// Get the thumbnail of a youtube video. If the entry has no thumbnail,
+/// the default thumbnail for the video id is returned
+pub fn thumbnail(entry: &Entry) -> Option<String> {
+    entry.thumbnail.clone().or_else(|| {
+        video_id(entry).map(|id| format!("https://i.ytimg.com/vi/{}/hqdefault.jpg", id))
+    })
+}
+
+/// Youtube feed client.
+///
+/// The underlying `FeedClient` keeps the last fetched feed, so the following requests are conditional.
 pub struct YoutubeClient {
     channel_id: String,
+    client: Arc<FeedClient>,
 }
 
+/// Default youtube url
+const YOUTUBE_URL: &str = "https://www.youtube.com";
+
 impl YoutubeClient {
     /// Instantiate a new `YoutubeClient`
     pub fn new(channel_id: impl ToString) -> Self {
+        let channel_id = channel_id.to_string();
         Self {
-            channel_id: channel_id.to_string(),
+            client: Arc::new(FeedClient::new(Self::feed_url(YOUTUBE_URL, &channel_id))),
+            channel_id,
         }
     }
 
-    /// Fetch youtube channel feed
-    pub async fn fetch(&self) -> YoutubeResult<Feed> {
-        let body = self.fetch_feed().await?;
-        trace!("Got body {}", body);
-        self.parse_feed(body)
+    /// Set the base url to fetch the feed from, instead of youtube
+    pub fn base_url(mut self, url: impl AsRef<str>) -> Self {
+        self.client = Arc::new(FeedClient::new(Self::feed_url(
+            url.as_ref().trim_end_matches('/'),
+            &self.channel_id,
+        )));
+        self
+    }
+
+    /// Use the shared feed client for the channel, so that the cached feed is kept between clients
+    pub fn shared(mut self) -> Self {
+        self.client = FeedClient::shared(self.client.url());
+        self
     }
 
     /// Fetch youtube channel feed
-    async fn fetch_feed(&self) -> YoutubeResult<String> {
-        let uri = format!(
-            "https://www.youtube.com/feeds/videos.xml?channel_id={}",
-            self.channel_id
-        );
-        debug!("fetching youtube channel {}", uri);
-        reqwest::get(uri)
-            .await
-            .map_err(YoutubeError::from)?
-            .text()
-            .await
-            .map_err(YoutubeError::from)
+    pub async fn fetch(&self) -> YoutubeResult<Feed> {
+        debug!("fetching youtube channel {}", self.channel_id);
+        self.client.fetch().await.map_err(YoutubeError::from)
     }
 
-    /// Parse feed
-    fn parse_feed(&self, feed: String) -> YoutubeResult<Feed> {
-        debug!("parsing feed");
-        let reader = Cursor::new(feed);
-        feed_parser::parse(reader)
-            .map(Feed::from)
-            .map_err(YoutubeError::from)
+    /// Get feed url for channel
+    fn feed_url(base_url: &str, channel_id: &str) -> String {
+        format!("{}/feeds/videos.xml?channel_id={}", base_url, channel_id)
     }
 }
 
@@ -59,17 +78,256 @@ impl YoutubeClient {
 mod test {
 
     use super::*;
+    use crate::feed::FeedResponse;
+
+    use pretty_assertions::assert_eq;
+    use wiremock::matchers::{header, header_exists, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn should_get_video_id_and_thumbnail() {
+        let mut entry = Entry {
+            id: String::from("yt:video:rRQP8PNEouo"),
+            title: None,
+            authors: Vec::new(),
+            summary: String::new(),
+            url: String::from("https://www.youtube.com/watch?v=rRQP8PNEouo"),
+            date: None,
+            thumbnail: None,
+            description: None,
+            views: None,
+            enclosure: None,
+        };
+        assert_eq!(video_id(&entry), Some("rRQP8PNEouo"));
+        assert_eq!(
+            thumbnail(&entry).as_deref(),
+            Some("https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg")
+        );
+        entry.thumbnail = Some(String::from("https://i1.ytimg.com/vi/rRQP8PNEouo/1.jpg"));
+        assert_eq!(
+            thumbnail(&entry).as_deref(),
+            Some("https://i1.ytimg.com/vi/rRQP8PNEouo/1.jpg")
+        );
+        entry.id = String::from("pippo");
+        assert_eq!(video_id(&entry), None);
+    }
 
     #[tokio::test]
     async fn should_fetch_feed() {
-        let client = YoutubeClient::new("UCTpU7OQg9QVsqayEYXTL1LQ");
-        assert!(client.fetch_feed().await.is_ok());
+        let server = mock_server(ResponseTemplate::new(200).set_body_string(FEED)).await;
+        let client = YoutubeClient::new(CHANNEL_ID).base_url(server.uri());
+        assert!(client.fetch().await.is_ok());
     }
 
     #[tokio::test]
     async fn should_parse_feed() {
-        let client = YoutubeClient::new("UCTpU7OQg9QVsqayEYXTL1LQ");
+        let server = mock_server(ResponseTemplate::new(200).set_body_string(FEED)).await;
+        let client = YoutubeClient::new(CHANNEL_ID).base_url(server.uri());
+        let feed = client.fetch().await.unwrap();
+        let entries: Vec<&Entry> = feed.entries().collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[0].title.as_deref(),
+            Some("Il mio armadio minimalista")
+        );
+        assert_eq!(
+            entries[0].url.as_str(),
+            "https://www.youtube.com/watch?v=ZEHVgvLAv6Q"
+        );
+        assert_eq!(video_id(entries[0]), Some("ZEHVgvLAv6Q"));
+        assert_eq!(
+            entries[0].description.as_deref(),
+            Some("Ecco il mio armadio minimalista.\nSeguimi anche su instagram")
+        );
+        // a fresh upload without views yet
+        assert_eq!(VideoKind::classify(entries[0]), VideoKind::Video);
+        assert_eq!(VideoKind::classify(entries[1]), VideoKind::Short);
+        assert_eq!(VideoKind::classify(entries[2]), VideoKind::Video);
+        assert_eq!(entries[2].views, Some(32768));
+        assert!(entries.iter().all(|x| x.date.is_some()));
+    }
+
+    #[tokio::test]
+    async fn should_parse_empty_feed() {
+        let server = mock_server(ResponseTemplate::new(200).set_body_string(EMPTY_FEED)).await;
+        let client = YoutubeClient::new(CHANNEL_ID).base_url(server.uri());
+        let feed = client.fetch().await.unwrap();
+        assert!(feed.entries().next().is_none());
+    }
+
+    #[tokio::test]
+    async fn should_parse_entries_without_date() {
+        let server = mock_server(ResponseTemplate::new(200).set_body_string(NO_DATES_FEED)).await;
+        let client = YoutubeClient::new(CHANNEL_ID).base_url(server.uri());
+        let feed = client.fetch().await.unwrap();
+        let entry = feed.entries().next().unwrap();
+        assert_eq!(entry.date, None);
+        assert_eq!(
+            entry.url.as_str(),
+            "https://www.youtube.com/watch?v=rRQP8PNEouo"
+        );
+        assert_eq!(entry.views, None);
+        assert_eq!(
+            thumbnail(entry).as_deref(),
+            Some("https://i.ytimg.com/vi/rRQP8PNEouo/hqdefault.jpg")
+        );
+    }
+
+    #[tokio::test]
+    async fn should_fail_parsing_malformed_feed() {
+        let server = mock_server(ResponseTemplate::new(200).set_body_string(MALFORMED_FEED)).await;
+        let client = YoutubeClient::new(CHANNEL_ID).base_url(server.uri());
+        assert!(matches!(
+            client.fetch().await.unwrap_err(),
+            YoutubeError::FeedParserError(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_report_http_errors() {
+        for status in [404, 500] {
+            let server = mock_server(ResponseTemplate::new(status).set_body_string("error")).await;
+            let client = YoutubeClient::new(CHANNEL_ID).base_url(server.uri());
+            match client.fetch().await.unwrap_err() {
+                YoutubeError::HttpStatus(code) => assert_eq!(code.as_u16(), status),
+                err => panic!("unexpected error: {}", err),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn should_use_cached_feed_if_not_modified() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/feeds/videos.xml"))
+            .and(query_param("channel_id", CHANNEL_ID))
+            .and(header("If-None-Match", "\"v1\""))
+            .and(header_exists("If-Modified-Since"))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/feeds/videos.xml"))
+            .and(query_param("channel_id", CHANNEL_ID))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .insert_header("Last-Modified", LAST_MODIFIED_DATE)
+                    .set_body_string(FEED),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = YoutubeClient::new(CHANNEL_ID).base_url(server.uri());
         let feed = client.fetch().await.unwrap();
-        assert!(feed.entries().next().is_some());
+        assert!(matches!(
+            client.client.fetch_feed().await.unwrap(),
+            FeedResponse::NotModified
+        ));
+        assert_eq!(client.fetch().await.unwrap(), feed);
+    }
+
+    #[tokio::test]
+    async fn should_share_cached_feed_between_clients() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/feeds/videos.xml"))
+            .and(header("If-None-Match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/feeds/videos.xml"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_string(FEED),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let feed = YoutubeClient::new(CHANNEL_ID)
+            .base_url(server.uri())
+            .shared()
+            .fetch()
+            .await
+            .unwrap();
+        let client = YoutubeClient::new(CHANNEL_ID)
+            .base_url(server.uri())
+            .shared();
+        assert_eq!(client.fetch().await.unwrap(), feed);
+    }
+
+    #[tokio::test]
+    async fn should_refresh_cached_feed_when_modified() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/feeds/videos.xml"))
+            .and(header("If-None-Match", "\"v2\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/feeds/videos.xml"))
+            .and(header("If-None-Match", "\"v1\""))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v2\"")
+                    .set_body_string(EMPTY_FEED),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/feeds/videos.xml"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_string(FEED),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = YoutubeClient::new(CHANNEL_ID).base_url(server.uri());
+        assert_eq!(client.fetch().await.unwrap().entries().count(), 3);
+        // the changed feed replaces the cached one, along with its etag
+        assert_eq!(client.fetch().await.unwrap().entries().count(), 0);
+        assert_eq!(client.fetch().await.unwrap().entries().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn should_not_send_conditional_headers_without_validators() {
+        let server = mock_server(ResponseTemplate::new(200).set_body_string(FEED)).await;
+        let client = YoutubeClient::new(CHANNEL_ID).base_url(server.uri());
+        assert!(client.fetch().await.is_ok());
+        assert!(client.fetch().await.is_ok());
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|x| {
+            !x.headers.contains_key(&"If-None-Match".into())
+                && !x.headers.contains_key(&"If-Modified-Since".into())
+        }));
+    }
+
+    const CHANNEL_ID: &str = "UCK3cMi97Kf_WENLvRFdztoQ";
+    const LAST_MODIFIED_DATE: &str = "Sat, 10 Sep 2022 16:00:00 GMT";
+    const FEED: &str = include_str!("../../tests/fixtures/youtube/feed.xml");
+    const EMPTY_FEED: &str = include_str!("../../tests/fixtures/youtube/empty.xml");
+    const MALFORMED_FEED: &str = include_str!("../../tests/fixtures/youtube/malformed.xml");
+    const NO_DATES_FEED: &str = include_str!("../../tests/fixtures/youtube/no_dates.xml");
+
+    /// Start a mock server which serves the feed for `CHANNEL_ID` with `response`
+    async fn mock_server(response: ResponseTemplate) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/feeds/videos.xml"))
+            .and(query_param("channel_id", CHANNEL_ID))
+            .respond_with(response)
+            .mount(&server)
+            .await;
+        server
     }
 }